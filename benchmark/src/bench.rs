@@ -2,15 +2,13 @@
 
 #![allow(renamed_and_removed_lints)]
 
-use std::io::Read;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use futures::{Future, Sink, Stream};
 use grpc::{
-    self, ClientStreamingSink, DuplexSink, MessageReader, Method, MethodType, RequestStream,
-    RpcContext, RpcStatus, RpcStatusCode, ServerStreamingSink, ServiceBuilder, UnarySink,
-    WriteFlags,
+    self, ClientStreamingSink, DuplexSink, Method, MethodType, RequestStream, RpcContext,
+    RpcStatus, RpcStatusCode, ServerStreamingSink, ServiceBuilder, UnarySink, WriteFlags,
 };
 use grpc_proto::testing::messages::{SimpleRequest, SimpleResponse};
 use grpc_proto::testing::services_grpc::BenchmarkService;
@@ -98,29 +96,16 @@ impl Generic {
     }
 }
 
-#[inline]
-#[allow(clippy::ptr_arg)]
-pub fn bin_ser(t: &Vec<u8>, buf: &mut Vec<u8>) {
-    buf.extend_from_slice(t)
-}
-
-#[inline]
-pub fn bin_de(mut reader: MessageReader) -> grpc::Result<Vec<u8>> {
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    Ok(buf)
-}
-
 pub const METHOD_BENCHMARK_SERVICE_GENERIC_CALL: Method<Vec<u8>, Vec<u8>> = Method {
     ty: MethodType::Duplex,
     name: "/grpc.testing.BenchmarkService/StreamingCall",
     req_mar: crate::grpc::Marshaller {
-        ser: bin_ser,
-        de: bin_de,
+        ser: grpc::raw_ser,
+        de: grpc::raw_de,
     },
     resp_mar: crate::grpc::Marshaller {
-        ser: bin_ser,
-        de: bin_de,
+        ser: grpc::raw_ser,
+        de: grpc::raw_de,
     },
 };
 
@@ -27,6 +27,7 @@ use protobuf;
 use protobuf::compiler_plugin;
 use protobuf::descriptor::*;
 use protobuf::descriptorx::*;
+use protobuf::Message;
 use std::io::Write;
 
 struct CodeWriter<'a> {
@@ -177,7 +178,7 @@ impl<'a> CodeWriter<'a> {
     }
 }
 
-use super::util::{self, fq_grpc, to_snake_case, MethodType};
+use super::util::{self, fq_grpc, to_snake_case, Customize, MethodType};
 
 struct MethodGen<'a> {
     proto: &'a MethodDescriptorProto,
@@ -516,6 +517,27 @@ impl<'a> MethodGen<'a> {
         w.fn_def(&sig);
     }
 
+    fn write_async_service(&self, w: &mut CodeWriter) {
+        let req_stream_type = format!("{}<{}>", fq_grpc("RequestStream"), self.input());
+        let resp_stream_type = format!("{}<{}>", fq_grpc("ResponseStream"), self.output());
+        let (req, req_type, resp_type) = match self.method_type().0 {
+            MethodType::Unary => ("req", self.input(), self.output()),
+            MethodType::ClientStreaming => ("stream", req_stream_type, self.output()),
+            MethodType::ServerStreaming => ("req", self.input(), resp_stream_type),
+            MethodType::Duplex => ("stream", req_stream_type, resp_stream_type),
+        };
+        let sig = format!(
+            "async fn {}(&self, ctx: {}, {}: {}) -> ::std::result::Result<{}, {}>;",
+            self.name(),
+            fq_grpc("AsyncRpcContext"),
+            req,
+            req_type,
+            resp_type,
+            fq_grpc("RpcStatus")
+        );
+        w.write_line(&sig);
+    }
+
     fn write_bind(&self, w: &mut CodeWriter) {
         let add = match self.method_type().0 {
             MethodType::Unary => "add_unary_handler",
@@ -535,11 +557,36 @@ impl<'a> MethodGen<'a> {
             },
         );
     }
+
+    fn write_async_bind(&self, w: &mut CodeWriter) {
+        let add = match self.method_type().0 {
+            MethodType::Unary => "add_async_unary_handler",
+            MethodType::ClientStreaming => "add_async_client_streaming_handler",
+            MethodType::ServerStreaming => "add_async_server_streaming_handler",
+            MethodType::Duplex => "add_async_duplex_streaming_handler",
+        };
+        w.block(
+            &format!(
+                "builder = builder.{}(&{}, move |ctx, req| {{",
+                add,
+                self.const_method_name()
+            ),
+            "});",
+            |w| {
+                w.write_line("let instance = instance.clone();");
+                w.write_line(&format!(
+                    "async move {{ instance.{}(ctx, req).await }}",
+                    self.name()
+                ));
+            },
+        );
+    }
 }
 
 struct ServiceGen<'a> {
     proto: &'a ServiceDescriptorProto,
     methods: Vec<MethodGen<'a>>,
+    customize: &'a Customize,
 }
 
 impl<'a> ServiceGen<'a> {
@@ -547,6 +594,7 @@ impl<'a> ServiceGen<'a> {
         proto: &'a ServiceDescriptorProto,
         file: &FileDescriptorProto,
         root_scope: &'a RootScope,
+        customize: &'a Customize,
     ) -> ServiceGen<'a> {
         let service_path = if file.get_package().is_empty() {
             format!("/{}", proto.get_name())
@@ -566,7 +614,11 @@ impl<'a> ServiceGen<'a> {
             })
             .collect();
 
-        ServiceGen { proto, methods }
+        ServiceGen {
+            proto,
+            methods,
+            customize,
+        }
     }
 
     fn service_name(&self) -> String {
@@ -636,6 +688,37 @@ impl<'a> ServiceGen<'a> {
         });
     }
 
+    fn write_async_server(&self, w: &mut CodeWriter) {
+        w.write_line("#[::grpcio::async_trait]");
+        w.pub_trait(&self.service_name(), |w| {
+            for method in &self.methods {
+                method.write_async_service(w);
+            }
+        });
+
+        w.write_line("");
+
+        let s = format!(
+            "create_{}<S: {} + Send + Sync + 'static>(s: S) -> {}",
+            to_snake_case(&self.service_name()),
+            self.service_name(),
+            fq_grpc("Service")
+        );
+        w.pub_fn(&s, |w| {
+            w.write_line("let s = ::std::sync::Arc::new(s);");
+            w.write_line("let mut builder = ::grpcio::ServiceBuilder::new();");
+            for method in &self.methods[0..self.methods.len() - 1] {
+                w.write_line("let instance = s.clone();");
+                method.write_async_bind(w);
+            }
+
+            w.write_line("let instance = s;");
+            self.methods[self.methods.len() - 1].write_async_bind(w);
+
+            w.write_line("builder.build()");
+        });
+    }
+
     fn write_method_definitions(&self, w: &mut CodeWriter) {
         for (i, method) in self.methods.iter().enumerate() {
             if i != 0 {
@@ -646,18 +729,40 @@ impl<'a> ServiceGen<'a> {
         }
     }
 
-    fn write(&self, w: &mut CodeWriter) {
+    fn write_file_descriptors(&self, w: &mut CodeWriter, descriptors: &[Vec<u8>]) {
+        w.block(
+            &format!(
+                "pub const {}: &[&[u8]] = &[",
+                util::const_file_descriptors_name(&self.service_name())
+            ),
+            "];",
+            |w| {
+                for desc in descriptors {
+                    w.write_line(&format!("{},", util::to_byte_str_literal(desc)));
+                }
+            },
+        );
+    }
+
+    fn write(&self, w: &mut CodeWriter, descriptors: &[Vec<u8>]) {
         self.write_method_definitions(w);
         w.write_line("");
         self.write_client(w);
         w.write_line("");
-        self.write_server(w);
+        if self.customize.async_service {
+            self.write_async_server(w);
+        } else {
+            self.write_server(w);
+        }
+        w.write_line("");
+        self.write_file_descriptors(w, descriptors);
     }
 }
 
 fn gen_file(
     file: &FileDescriptorProto,
     root_scope: &RootScope,
+    customize: &Customize,
 ) -> Option<compiler_plugin::GenResult> {
     if file.get_service().is_empty() {
         return None;
@@ -665,6 +770,25 @@ fn gen_file(
 
     let base = protobuf::descriptorx::proto_path_to_rust_mod(file.get_name());
 
+    // Serialized descriptors of the file and its dependencies, which can be
+    // used to serve reflection requests.
+    let files_map: HashMap<&str, &FileDescriptorProto> = root_scope
+        .file_descriptors
+        .iter()
+        .map(|f| (f.get_name(), f))
+        .collect();
+    let descriptors: Vec<_> = util::transitive_deps(file.get_name(), |f| {
+        files_map.get(f).map(|d| d.get_dependency())
+    })
+    .into_iter()
+    .filter_map(|f| files_map.get(f))
+    .map(|d| {
+        let mut d = (*d).clone();
+        d.clear_source_code_info();
+        d.write_to_bytes().unwrap()
+    })
+    .collect();
+
     let mut v = Vec::new();
     {
         let mut w = CodeWriter::new(&mut v);
@@ -672,7 +796,7 @@ fn gen_file(
 
         for service in file.get_service() {
             w.write_line("");
-            ServiceGen::new(service, file, root_scope).write(&mut w);
+            ServiceGen::new(service, file, root_scope, customize).write(&mut w, &descriptors);
         }
     }
 
@@ -685,6 +809,15 @@ fn gen_file(
 pub fn gen(
     file_descriptors: &[FileDescriptorProto],
     files_to_generate: &[String],
+) -> Vec<compiler_plugin::GenResult> {
+    gen_with_customize(file_descriptors, files_to_generate, &Customize::default())
+}
+
+/// Same as [`gen`], but the generated code can be customized.
+pub fn gen_with_customize(
+    file_descriptors: &[FileDescriptorProto],
+    files_to_generate: &[String],
+    customize: &Customize,
 ) -> Vec<compiler_plugin::GenResult> {
     let files_map: HashMap<&str, &FileDescriptorProto> =
         file_descriptors.iter().map(|f| (f.get_name(), f)).collect();
@@ -700,12 +833,19 @@ pub fn gen(
             continue;
         }
 
-        results.extend(gen_file(file, &root_scope).into_iter());
+        results.extend(gen_file(file, &root_scope, customize).into_iter());
     }
 
     results
 }
 
+/// Entry of the protoc plugin.
+///
+/// Options can be passed as the plugin parameter, for example
+/// `--grpc_out=async_service:<out_dir>` generates async service traits.
 pub fn protoc_gen_grpc_rust_main() {
-    compiler_plugin::plugin_main(gen);
+    compiler_plugin::plugin_main_2(|r| {
+        let customize = Customize::parse(r.parameter).unwrap_or_else(|e| panic!("{}", e));
+        gen_with_customize(r.file_descriptors, r.files_to_generate, &customize)
+    });
 }
@@ -6,3 +6,5 @@ pub mod codegen;
 pub mod prost_codegen;
 
 mod util;
+
+pub use util::Customize;
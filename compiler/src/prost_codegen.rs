@@ -1,21 +1,34 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use super::util::{fq_grpc, to_snake_case, MethodType};
+use super::util::{self, fq_grpc, to_snake_case, Customize, MethodType};
 use derive_new::new;
 use prost::Message;
 use prost_build::{protoc, protoc_include, Config, Method, Service, ServiceGenerator};
-use prost_types::FileDescriptorSet;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use std::collections::HashMap;
 use std::io::{Error, ErrorKind, Read};
 use std::path::Path;
 use std::{fs, io, process::Command};
 
 /// Returns the names of all packages compiled.
 pub fn compile_protos<P>(protos: &[P], includes: &[P], out_dir: &str) -> io::Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    compile_protos_with_customize(protos, includes, out_dir, &Customize::default())
+}
+
+/// Same as [`compile_protos`], but the generated code can be customized.
+pub fn compile_protos_with_customize<P>(
+    protos: &[P],
+    includes: &[P],
+    out_dir: &str,
+    customize: &Customize,
+) -> io::Result<Vec<String>>
 where
     P: AsRef<Path>,
 {
     let mut prost_config = Config::new();
-    prost_config.service_generator(Box::new(Generator));
     prost_config.out_dir(out_dir);
 
     // Create a file descriptor set for the protocol files.
@@ -61,6 +74,8 @@ where
     packages.sort();
     packages.dedup();
 
+    prost_config.service_generator(Box::new(Generator::new(&descriptor_set, customize)));
+
     // FIXME(https://github.com/danburkert/prost/pull/155)
     // Unfortunately we have to forget the above work and use `compile_protos` to
     // actually generate the Rust code.
@@ -69,13 +84,77 @@ where
     Ok(packages)
 }
 
-struct Generator;
+struct Generator {
+    /// Serialized descriptors of the files required by each service, keyed
+    /// by the fully qualified service name.
+    file_descriptors: HashMap<String, Vec<Vec<u8>>>,
+    customize: Customize,
+}
+
+impl Generator {
+    fn new(descriptor_set: &FileDescriptorSet, customize: &Customize) -> Generator {
+        let files: HashMap<&str, &FileDescriptorProto> =
+            descriptor_set.file.iter().map(|f| (f.name(), f)).collect();
+        let mut file_descriptors = HashMap::new();
+        for file in &descriptor_set.file {
+            if file.service.is_empty() {
+                continue;
+            }
+            let descriptors: Vec<_> = util::transitive_deps(file.name(), |f| {
+                files.get(f).map(|d| d.dependency.as_slice())
+            })
+            .into_iter()
+            .filter_map(|f| files.get(f))
+            .map(|d| {
+                let mut d = (*d).clone();
+                d.source_code_info = None;
+                let mut buf = Vec::with_capacity(d.encoded_len());
+                d.encode(&mut buf).unwrap();
+                buf
+            })
+            .collect();
+            for service in &file.service {
+                let name = if file.package().is_empty() {
+                    service.name().to_owned()
+                } else {
+                    format!("{}.{}", file.package(), service.name())
+                };
+                file_descriptors.insert(name, descriptors.clone());
+            }
+        }
+        Generator {
+            file_descriptors,
+            customize: customize.clone(),
+        }
+    }
+
+    fn generate_file_descriptors(&self, service: &Service, buf: &mut String) {
+        let name = if service.package.is_empty() {
+            service.proto_name.clone()
+        } else {
+            format!("{}.{}", service.package, service.proto_name)
+        };
+        buf.push_str("pub const ");
+        buf.push_str(&util::const_file_descriptors_name(&service.name));
+        buf.push_str(": &[&[u8]] = &[\n");
+        for desc in self.file_descriptors.get(&name).into_iter().flatten() {
+            buf.push_str(&util::to_byte_str_literal(desc));
+            buf.push_str(",\n");
+        }
+        buf.push_str("];\n");
+    }
+}
 
 impl ServiceGenerator for Generator {
     fn generate(&mut self, service: Service, buf: &mut String) {
         generate_methods(&service, buf);
         generate_client(&service, buf);
-        generate_server(&service, buf);
+        if self.customize.async_service {
+            generate_async_server(&service, buf);
+        } else {
+            generate_server(&service, buf);
+        }
+        self.generate_file_descriptors(&service, buf);
     }
 }
 
@@ -534,3 +613,88 @@ fn generate_method_bind(service_name: &str, method: &Method, buf: &mut String) {
     buf.push_str(&method.name);
     buf.push_str("(ctx, req, resp));\n");
 }
+
+fn generate_async_server(service: &Service, buf: &mut String) {
+    buf.push_str("#[::grpcio::async_trait]\n");
+    buf.push_str("pub trait ");
+    buf.push_str(&service.name);
+    buf.push_str(" {\n");
+    generate_async_server_methods(service, buf);
+    buf.push_str("}\n");
+
+    buf.push_str("pub fn create_");
+    buf.push_str(&to_snake_case(&service.name));
+    buf.push_str("<S: ");
+    buf.push_str(&service.name);
+    buf.push_str(" + Send + Sync + 'static>(s: S) -> ");
+    buf.push_str(&fq_grpc("Service"));
+    buf.push_str(" {\n");
+    buf.push_str("let s = ::std::sync::Arc::new(s);\n");
+    buf.push_str("let mut builder = ::grpcio::ServiceBuilder::new();\n");
+
+    for method in &service.methods[0..service.methods.len() - 1] {
+        buf.push_str("let instance = s.clone();\n");
+        generate_async_method_bind(&service.name, method, buf);
+    }
+
+    buf.push_str("let instance = s;\n");
+    generate_async_method_bind(
+        &service.name,
+        &service.methods[service.methods.len() - 1],
+        buf,
+    );
+
+    buf.push_str("builder.build()\n");
+    buf.push_str("}\n");
+}
+
+fn generate_async_server_methods(service: &Service, buf: &mut String) {
+    for method in &service.methods {
+        let method_type = MethodType::from_method(method);
+        let request_arg = match method_type {
+            MethodType::Unary | MethodType::ServerStreaming => {
+                format!("req: {}", method.input_type)
+            }
+            MethodType::ClientStreaming | MethodType::Duplex => format!(
+                "stream: {}<{}>",
+                fq_grpc("RequestStream"),
+                method.input_type
+            ),
+        };
+        let response_type = match method_type {
+            MethodType::Unary | MethodType::ClientStreaming => method.output_type.clone(),
+            MethodType::ServerStreaming | MethodType::Duplex => {
+                format!("{}<{}>", fq_grpc("ResponseStream"), method.output_type)
+            }
+        };
+
+        buf.push_str("async fn ");
+        buf.push_str(&method.name);
+        buf.push_str("(&self, ctx: ");
+        buf.push_str(&fq_grpc("AsyncRpcContext"));
+        buf.push_str(", ");
+        buf.push_str(&request_arg);
+        buf.push_str(") -> ::std::result::Result<");
+        buf.push_str(&response_type);
+        buf.push_str(", ");
+        buf.push_str(&fq_grpc("RpcStatus"));
+        buf.push_str(">;\n");
+    }
+}
+
+fn generate_async_method_bind(service_name: &str, method: &Method, buf: &mut String) {
+    let add_name = match MethodType::from_method(method) {
+        MethodType::Unary => "add_async_unary_handler",
+        MethodType::ClientStreaming => "add_async_client_streaming_handler",
+        MethodType::ServerStreaming => "add_async_server_streaming_handler",
+        MethodType::Duplex => "add_async_duplex_streaming_handler",
+    };
+
+    buf.push_str("builder = builder.");
+    buf.push_str(add_name);
+    buf.push_str("(&");
+    buf.push_str(&const_method_name(service_name, method));
+    buf.push_str(", move |ctx, req| { let instance = instance.clone(); async move { instance.");
+    buf.push_str(&method.name);
+    buf.push_str("(ctx, req).await } });\n");
+}
@@ -1,5 +1,6 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+use std::ascii;
 use std::fmt;
 use std::str;
 
@@ -81,6 +82,81 @@ pub fn to_camel_case(name: &str) -> String {
     camel_case_name
 }
 
+/// Returns `file` followed by all of its transitive dependencies.
+///
+/// `deps` should return the direct dependencies of a file, or `None` if the
+/// file is unknown.
+pub fn transitive_deps<'a, F>(file: &'a str, deps: F) -> Vec<&'a str>
+where
+    F: Fn(&str) -> Option<&'a [String]>,
+{
+    let mut files = vec![file];
+    let mut i = 0;
+    while i < files.len() {
+        for dep in deps(files[i]).unwrap_or_default() {
+            if !files.contains(&dep.as_str()) {
+                files.push(dep.as_str());
+            }
+        }
+        i += 1;
+    }
+    files
+}
+
+/// Formats bytes as a byte string literal.
+pub fn to_byte_str_literal(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() + 3);
+    s.push_str("b\"");
+    for b in bytes {
+        s.extend(ascii::escape_default(*b).map(char::from));
+    }
+    s.push('"');
+    s
+}
+
+/// Name of the constant that holds the file descriptors required by a service.
+pub fn const_file_descriptors_name(service_name: &str) -> String {
+    format!(
+        "FILE_DESCRIPTORS_{}",
+        to_snake_case(service_name).to_uppercase()
+    )
+}
+
+/// Options to customize the generated code.
+#[derive(Clone, Debug, Default)]
+pub struct Customize {
+    /// Generate service traits with async methods instead of sink based
+    /// ones. The generated code requires the `std-future` feature of grpcio.
+    pub async_service: bool,
+}
+
+impl Customize {
+    /// Parses options in the form of `key[=value],...`, which is how they
+    /// are passed to protoc plugins.
+    pub fn parse(parameter: &str) -> Result<Customize, String> {
+        let mut customize = Customize::default();
+        for opt in parameter
+            .split(',')
+            .map(str::trim)
+            .filter(|o| !o.is_empty())
+        {
+            let (key, value) = match opt.find('=') {
+                Some(pos) => (&opt[..pos], &opt[pos + 1..]),
+                None => (opt, "true"),
+            };
+            match key {
+                "async_service" => {
+                    customize.async_service = value
+                        .parse()
+                        .map_err(|_| format!("invalid value of {}: {}", key, value))?;
+                }
+                _ => return Err(format!("unknown option: {}", key)),
+            }
+        }
+        Ok(customize)
+    }
+}
+
 pub fn fq_grpc(item: &str) -> String {
     format!("::grpcio::{}", item)
 }
@@ -130,6 +206,53 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_transitive_deps() {
+        let deps = vec![
+            ("a.proto", vec!["b.proto".to_owned(), "c.proto".to_owned()]),
+            ("b.proto", vec!["c.proto".to_owned(), "d.proto".to_owned()]),
+            ("c.proto", vec![]),
+        ];
+        let find = |f: &str| {
+            deps.iter()
+                .find(|(name, _)| *name == f)
+                .map(|(_, d)| d.as_slice())
+        };
+        let res = super::transitive_deps("a.proto", find);
+        assert_eq!(res, vec!["a.proto", "b.proto", "c.proto", "d.proto"]);
+        let res = super::transitive_deps("c.proto", find);
+        assert_eq!(res, vec!["c.proto"]);
+    }
+
+    #[test]
+    fn test_byte_str_literal() {
+        let cases: Vec<(&[u8], &str)> = vec![
+            (b"", r#"b"""#),
+            (b"abc", r#"b"abc""#),
+            (b"\n\"\\\x00\xff", r#"b"\n\"\\\x00\xff""#),
+        ];
+        for (origin, exp) in cases {
+            assert_eq!(super::to_byte_str_literal(origin), exp);
+        }
+    }
+
+    #[test]
+    fn test_parse_customize() {
+        let cases = vec![
+            ("", false),
+            ("async_service", true),
+            ("async_service=true", true),
+            (" async_service=false, ", false),
+        ];
+        for (param, exp) in cases {
+            let res = super::Customize::parse(param).unwrap();
+            assert_eq!(res.async_service, exp, "{}", param);
+        }
+
+        assert!(super::Customize::parse("async_service=yes").is_err());
+        assert!(super::Customize::parse("unknown").is_err());
+    }
+
     #[test]
     fn test_camel_name() {
         let cases = vec![
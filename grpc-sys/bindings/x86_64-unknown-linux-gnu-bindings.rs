@@ -6792,7 +6792,7 @@ extern "C" {
         send_buffer: *const ::std::os::raw::c_char,
         send_buffer_len: usize,
         write_flags: u32,
-        send_empty_initial_metadata: i32,
+        initial_metadata: *mut grpc_metadata_array,
         tag: *mut ::std::os::raw::c_void,
     ) -> grpc_call_error;
 }
@@ -6810,7 +6810,7 @@ extern "C" {
         status_details: *const ::std::os::raw::c_char,
         status_details_len: usize,
         trailing_metadata: *mut grpc_metadata_array,
-        send_empty_initial_metadata: i32,
+        initial_metadata: *mut grpc_metadata_array,
         optional_send_buffer: *const ::std::os::raw::c_char,
         optional_send_buffer_len: usize,
         write_flags: u32,
@@ -6867,15 +6867,6 @@ extern "C" {
         key_cert_pair_private_key: *const ::std::os::raw::c_char,
     ) -> *mut grpc_channel_credentials;
 }
-extern "C" {
-    pub fn grpcwrap_ssl_server_credentials_create(
-        pem_root_certs: *const ::std::os::raw::c_char,
-        key_cert_pair_cert_chain_array: *mut *const ::std::os::raw::c_char,
-        key_cert_pair_private_key_array: *mut *const ::std::os::raw::c_char,
-        num_key_cert_pairs: usize,
-        force_client_auth: ::std::os::raw::c_int,
-    ) -> *mut grpc_server_credentials;
-}
 extern "C" {
     pub fn grpcwrap_sanity_check_slice(size: usize, align: usize);
 }
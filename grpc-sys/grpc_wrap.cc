@@ -644,19 +644,26 @@ GPR_EXPORT grpc_call_error GPR_CALLTYPE grpcwrap_call_recv_initial_metadata(
 GPR_EXPORT grpc_call_error GPR_CALLTYPE grpcwrap_call_send_message(
     grpc_call* call, grpcwrap_batch_context* ctx, const char* send_buffer,
     size_t send_buffer_len, uint32_t write_flags,
-    int32_t send_empty_initial_metadata, void* tag) {
+    grpc_metadata_array* initial_metadata, void* tag) {
   /* TODO: don't use magic number */
   grpc_op ops[2];
   memset(ops, 0, sizeof(ops));
-  size_t nops = send_empty_initial_metadata ? 2 : 1;
+  size_t nops = initial_metadata ? 2 : 1;
   ops[0].op = GRPC_OP_SEND_MESSAGE;
   ctx->send_message = string_to_byte_buffer(send_buffer, send_buffer_len);
   ops[0].data.send_message.send_message = ctx->send_message;
   ops[0].flags = write_flags;
   ops[0].reserved = nullptr;
-  ops[1].op = GRPC_OP_SEND_INITIAL_METADATA;
-  ops[1].flags = 0;
-  ops[1].reserved = nullptr;
+  if (initial_metadata) {
+    ops[1].op = GRPC_OP_SEND_INITIAL_METADATA;
+    grpcwrap_metadata_array_move(&(ctx->send_initial_metadata),
+                                 initial_metadata);
+    ops[1].data.send_initial_metadata.count = ctx->send_initial_metadata.count;
+    ops[1].data.send_initial_metadata.metadata =
+        ctx->send_initial_metadata.metadata;
+    ops[1].flags = 0;
+    ops[1].reserved = nullptr;
+  }
 
   return grpc_call_start_batch(call, ops, nops, tag, nullptr);
 }
@@ -676,8 +683,9 @@ grpcwrap_call_send_close_from_client(grpc_call* call, void* tag) {
 GPR_EXPORT grpc_call_error GPR_CALLTYPE grpcwrap_call_send_status_from_server(
     grpc_call* call, grpcwrap_batch_context* ctx, grpc_status_code status_code,
     const char* status_details, size_t status_details_len,
-    grpc_metadata_array* trailing_metadata, int32_t send_empty_initial_metadata,
-    const char* optional_send_buffer, size_t optional_send_buffer_len,
+    grpc_metadata_array* trailing_metadata,
+    grpc_metadata_array* initial_metadata, const char* optional_send_buffer,
+    size_t optional_send_buffer_len,
     uint32_t write_flags, void* tag) {
   /* TODO: don't use magic number */
   grpc_op ops[3];
@@ -705,8 +713,14 @@ GPR_EXPORT grpc_call_error GPR_CALLTYPE grpcwrap_call_send_status_from_server(
     ops[nops].reserved = nullptr;
     nops++;
   }
-  if (send_empty_initial_metadata) {
+  if (initial_metadata) {
     ops[nops].op = GRPC_OP_SEND_INITIAL_METADATA;
+    grpcwrap_metadata_array_move(&(ctx->send_initial_metadata),
+                                 initial_metadata);
+    ops[nops].data.send_initial_metadata.count =
+        ctx->send_initial_metadata.count;
+    ops[nops].data.send_initial_metadata.metadata =
+        ctx->send_initial_metadata.metadata;
     ops[nops].flags = 0;
     ops[nops].reserved = nullptr;
     nops++;
@@ -824,36 +838,6 @@ grpcwrap_ssl_credentials_create(const char* pem_root_certs,
   }
 }
 
-GPR_EXPORT grpc_server_credentials* GPR_CALLTYPE
-grpcwrap_ssl_server_credentials_create(
-    const char* pem_root_certs, const char** key_cert_pair_cert_chain_array,
-    const char** key_cert_pair_private_key_array, size_t num_key_cert_pairs,
-    int force_client_auth) {
-  size_t i;
-  grpc_server_credentials* creds;
-  grpc_ssl_pem_key_cert_pair* key_cert_pairs =
-      (grpc_ssl_pem_key_cert_pair*)gpr_malloc(
-          sizeof(grpc_ssl_pem_key_cert_pair) * num_key_cert_pairs);
-  memset(key_cert_pairs, 0,
-         sizeof(grpc_ssl_pem_key_cert_pair) * num_key_cert_pairs);
-
-  for (i = 0; i < num_key_cert_pairs; i++) {
-    if (key_cert_pair_cert_chain_array[i] ||
-        key_cert_pair_private_key_array[i]) {
-      key_cert_pairs[i].cert_chain = key_cert_pair_cert_chain_array[i];
-      key_cert_pairs[i].private_key = key_cert_pair_private_key_array[i];
-    }
-  }
-  creds = grpc_ssl_server_credentials_create_ex(
-      pem_root_certs, key_cert_pairs, num_key_cert_pairs,
-      force_client_auth
-          ? GRPC_SSL_REQUEST_AND_REQUIRE_CLIENT_CERTIFICATE_AND_VERIFY
-          : GRPC_SSL_DONT_REQUEST_CLIENT_CERTIFICATE,
-      NULL);
-  gpr_free(key_cert_pairs);
-  return creds;
-}
-
 #endif
 
 /* Sanity check for complicated types */
@@ -0,0 +1,17 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! An implementation of the [gRPC health checking protocol].
+//!
+//! [`HealthService`] keeps the serving status of every service and answers
+//! both `Check` and `Watch` calls. Use [`create_health_service`] to register
+//! it to a server, so that all services are reported as `NOT_SERVING` once
+//! the server begins to shutdown.
+//!
+//! [gRPC health checking protocol]: https://github.com/grpc/grpc/blob/master/doc/health-checking.md
+
+#[macro_use]
+extern crate log;
+
+mod service;
+
+pub use self::service::{create_health_service, HealthService, ServingStatus};
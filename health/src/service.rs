@@ -0,0 +1,182 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::sync::mpsc::{self, UnboundedSender};
+use futures::{Future, Sink, Stream};
+use grpcio::{
+    Error, RpcContext, RpcStatus, RpcStatusCode, ServerStreamingSink, Service, UnarySink,
+    WriteFlags,
+};
+
+#[cfg(feature = "prost-codec")]
+pub use grpcio_proto::health::v1::health_check_response::ServingStatus;
+#[cfg(feature = "prost-codec")]
+use grpcio_proto::health::v1::{create_health, Health, HealthCheckRequest, HealthCheckResponse};
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+pub use grpcio_proto::health::v1::health::HealthCheckResponse_ServingStatus as ServingStatus;
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use grpcio_proto::health::v1::health::{HealthCheckRequest, HealthCheckResponse};
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use grpcio_proto::health::v1::health_grpc::{create_health, Health};
+
+#[cfg(feature = "prost-codec")]
+const NOT_SERVING: ServingStatus = ServingStatus::NotServing;
+#[cfg(feature = "prost-codec")]
+const SERVICE_UNKNOWN: ServingStatus = ServingStatus::ServiceUnknown;
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+const NOT_SERVING: ServingStatus = ServingStatus::NOT_SERVING;
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+const SERVICE_UNKNOWN: ServingStatus = ServingStatus::SERVICE_UNKNOWN;
+
+/// The serving status of a service and all the watchers interested in it.
+#[derive(Default)]
+struct StatusCast {
+    /// `None` means the status of the service has never been set.
+    status: Option<ServingStatus>,
+    subscribers: Vec<UnboundedSender<ServingStatus>>,
+}
+
+impl StatusCast {
+    fn broadcast(&mut self, status: ServingStatus) {
+        if self.status == Some(status) {
+            return;
+        }
+        self.status = Some(status);
+        // Subscribers whose calls have finished are dropped here.
+        self.subscribers
+            .retain(|s| s.unbounded_send(status).is_ok());
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    casts: HashMap<String, StatusCast>,
+    shutdown: bool,
+}
+
+/// A health checking service that can be shared between the server and the
+/// application.
+///
+/// The empty service name stands for the overall status of the server.
+#[derive(Clone, Default)]
+pub struct HealthService {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl HealthService {
+    /// Create a service that doesn't know the status of any service.
+    pub fn new() -> HealthService {
+        HealthService::default()
+    }
+
+    /// Set the serving status of the given service.
+    ///
+    /// All watchers of the service are notified if the status is changed.
+    /// It's a no-op after the service is shutdown.
+    pub fn set_serving_status(&self, service: &str, status: ServingStatus) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.shutdown {
+            info!(
+                "health service is shutdown, ignore setting {} to {:?}",
+                service, status
+            );
+            return;
+        }
+        inner
+            .casts
+            .entry(service.to_owned())
+            .or_default()
+            .broadcast(status);
+    }
+
+    /// Get the serving status of the given service.
+    ///
+    /// Returns `None` if the status of the service has never been set.
+    pub fn serving_status(&self, service: &str) -> Option<ServingStatus> {
+        let inner = self.inner.lock().unwrap();
+        inner.casts.get(service).and_then(|c| c.status)
+    }
+
+    /// Set all known services to `NOT_SERVING`, and ignore any later updates.
+    ///
+    /// All watch streams are finished after the final status is sent, so they
+    /// don't keep the server from shutting down.
+    ///
+    /// It's called automatically when a server registered with
+    /// [`create_health_service`] begins to shutdown.
+    pub fn shutdown(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.shutdown = true;
+        for cast in inner.casts.values_mut() {
+            if cast.status.is_some() {
+                cast.broadcast(NOT_SERVING);
+            }
+            // Dropping the senders ends the update streams once the pending
+            // statuses are consumed.
+            cast.subscribers.clear();
+        }
+    }
+}
+
+fn build_response(status: ServingStatus) -> HealthCheckResponse {
+    let mut resp = HealthCheckResponse::default();
+    resp.set_status(status);
+    resp
+}
+
+impl Health for HealthService {
+    fn check(
+        &mut self,
+        ctx: RpcContext<'_>,
+        req: HealthCheckRequest,
+        sink: UnarySink<HealthCheckResponse>,
+    ) {
+        let f = match self.serving_status(req.get_service()) {
+            Some(status) => sink.success(build_response(status)),
+            None => sink.fail(RpcStatus::new(RpcStatusCode::NOT_FOUND, None)),
+        };
+        ctx.spawn(f.map_err(|e| debug!("failed to reply health check: {:?}", e)));
+    }
+
+    fn watch(
+        &mut self,
+        ctx: RpcContext<'_>,
+        req: HealthCheckRequest,
+        sink: ServerStreamingSink<HealthCheckResponse>,
+    ) {
+        let (tx, rx) = mpsc::unbounded();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let shutdown = inner.shutdown;
+            let cast = inner.casts.entry(req.get_service().to_owned()).or_default();
+            // The receiver is still alive, so the first send never fails.
+            let _ = tx.unbounded_send(cast.status.unwrap_or(SERVICE_UNKNOWN));
+            // No more updates after shutdown, so the call finishes right after
+            // the current status is sent.
+            if !shutdown {
+                cast.subscribers.push(tx);
+            }
+        }
+        let updates = rx
+            .map(|status| (build_response(status), WriteFlags::default()))
+            .map_err(|()| -> Error { unreachable!() });
+        ctx.spawn(
+            sink.send_all(updates)
+                .map(|_| ())
+                .map_err(|e| debug!("failed to watch health status: {:?}", e)),
+        );
+    }
+}
+
+/// Create a [`Service`] that serves health checking requests with `health`.
+///
+/// All services are reported as `NOT_SERVING` once the server serving the
+/// returned service begins to shutdown.
+pub fn create_health_service(health: HealthService) -> Service {
+    let h = health.clone();
+    create_health(health).on_shutdown(move || h.shutdown())
+}
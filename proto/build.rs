@@ -7,7 +7,9 @@ fn main() {
     let modules = &[
         ("grpc/testing", "testing"),
         ("grpc/health/v1/", "health"),
+        ("grpc/reflection/v1alpha/", "reflection"),
         ("grpc/example", "example"),
+        ("google/rpc", "rpc"),
     ];
     for (dir, package) in modules {
         let out_dir = format!("{}/{}", out_dir, package);
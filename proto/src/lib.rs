@@ -20,6 +20,24 @@ pub mod health {
     }
 }
 
+pub mod reflection {
+    pub mod v1alpha {
+        include!(concat!(env!("OUT_DIR"), "/reflection/mod.rs"));
+
+        #[cfg(feature = "prost-codec")]
+        pub use self::grpc::reflection::v1alpha::*;
+    }
+}
+
+pub mod google {
+    pub mod rpc {
+        include!(concat!(env!("OUT_DIR"), "/rpc/mod.rs"));
+
+        #[cfg(feature = "prost-codec")]
+        pub use self::google::rpc::*;
+    }
+}
+
 #[cfg(feature = "prost-codec")]
 #[allow(clippy::large_enum_variant)]
 pub mod help {
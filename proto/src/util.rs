@@ -1,13 +1,26 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
 use grpcio::{
-    ChannelCredentials, ChannelCredentialsBuilder, ServerCredentials, ServerCredentialsBuilder,
+    ChannelCredentials, ChannelCredentialsBuilder, RpcStatus, ServerCredentials,
+    ServerCredentialsBuilder,
 };
 
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use crate::google::rpc::status::Status;
+#[cfg(feature = "prost-codec")]
+use crate::google::rpc::Status;
 #[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
 use crate::testing::messages::{Payload, ResponseParameters};
 #[cfg(feature = "prost-codec")]
 use crate::testing::{Payload, ResponseParameters};
+#[cfg(feature = "prost-codec")]
+use prost::Message;
+#[cfg(feature = "prost-codec")]
+use prost_types::Any;
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use protobuf::{well_known_types::Any, Message};
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
 
 /// Create a payload with the specified size.
 pub fn new_payload(size: usize) -> Payload {
@@ -36,3 +49,99 @@ pub fn create_test_channel_credentials() -> ChannelCredentials {
         .root_cert(ca.into())
         .build()
 }
+
+/// Pack a message into an `Any`.
+///
+/// `type_name` is the full name of the message type, e.g. `google.rpc.RetryInfo`.
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+pub fn pack_any<M: Message>(type_name: &str, msg: &M) -> Any {
+    let mut any = Any::default();
+    any.set_type_url(format!("{}{}", TYPE_URL_PREFIX, type_name));
+    any.set_value(msg.write_to_bytes().unwrap());
+    any
+}
+
+/// Pack a message into an `Any`.
+///
+/// `type_name` is the full name of the message type, e.g. `google.rpc.RetryInfo`.
+#[cfg(feature = "prost-codec")]
+pub fn pack_any<M: Message>(type_name: &str, msg: &M) -> Any {
+    let mut value = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut value).unwrap();
+    Any {
+        type_url: format!("{}{}", TYPE_URL_PREFIX, type_name),
+        value,
+    }
+}
+
+/// Unpack a message from an `Any`.
+///
+/// `None` is returned if the `Any` doesn't hold a `type_name` message.
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+pub fn unpack_any<M: Message>(type_name: &str, any: &Any) -> Option<M> {
+    if !is_type_url_of(any.get_type_url(), type_name) {
+        return None;
+    }
+    let mut msg = M::new();
+    msg.merge_from_bytes(any.get_value()).ok()?;
+    Some(msg)
+}
+
+/// Unpack a message from an `Any`.
+///
+/// `None` is returned if the `Any` doesn't hold a `type_name` message.
+#[cfg(feature = "prost-codec")]
+pub fn unpack_any<M: Message + Default>(type_name: &str, any: &Any) -> Option<M> {
+    if !is_type_url_of(&any.type_url, type_name) {
+        return None;
+    }
+    M::decode(any.value.as_slice()).ok()
+}
+
+fn is_type_url_of(type_url: &str, type_name: &str) -> bool {
+    type_url.starts_with(TYPE_URL_PREFIX) && &type_url[TYPE_URL_PREFIX.len()..] == type_name
+}
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+fn encode_status(status: &Status) -> Vec<u8> {
+    status.write_to_bytes().unwrap()
+}
+
+#[cfg(feature = "prost-codec")]
+fn encode_status(status: &Status) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(status.encoded_len());
+    status.encode(&mut buf).unwrap();
+    buf
+}
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+fn decode_status(buf: &[u8]) -> Option<Status> {
+    let mut status = Status::new();
+    status.merge_from_bytes(buf).ok()?;
+    Some(status)
+}
+
+#[cfg(feature = "prost-codec")]
+fn decode_status(buf: &[u8]) -> Option<Status> {
+    Status::decode(buf).ok()
+}
+
+/// Create an `RpcStatus` that carries `status` as its rich error details.
+pub fn new_rpc_status(status: &Status) -> RpcStatus {
+    let message = if status.get_message().is_empty() {
+        None
+    } else {
+        Some(status.get_message().to_owned())
+    };
+    RpcStatus::with_status_details(status.get_code(), message, encode_status(status))
+}
+
+/// Decode the rich error details carried by `status`.
+///
+/// `None` is returned if there are no details or they are malformed.
+pub fn status_details(status: &RpcStatus) -> Option<Status> {
+    if status.status_details().is_empty() {
+        return None;
+    }
+    decode_status(status.status_details())
+}
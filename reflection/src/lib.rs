@@ -0,0 +1,17 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! An implementation of the [gRPC server reflection protocol].
+//!
+//! [`ReflectionService`] answers queries about the services registered to a
+//! server, which allows tools like `grpcurl` to inspect the server without
+//! local proto files. It's fed by serialized file descriptors, e.g. the
+//! `FILE_DESCRIPTORS_*` constants generated by `grpcio-compiler`.
+//!
+//! [gRPC server reflection protocol]: https://github.com/grpc/grpc/blob/master/doc/server-reflection.md
+
+#[macro_use]
+extern crate log;
+
+mod service;
+
+pub use self::service::{create_reflection_service, ReflectionService};
@@ -0,0 +1,289 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use futures::{Future, Sink, Stream};
+use grpcio::{
+    DuplexSink, Error, RequestStream, Result, RpcContext, RpcStatusCode, Service, WriteFlags,
+};
+use protobuf::descriptor::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto};
+use protobuf::Message;
+
+#[cfg(feature = "prost-codec")]
+use grpcio_proto::reflection::v1alpha::{
+    create_server_reflection, server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse, ErrorResponse, FileDescriptorResponse,
+    ListServiceResponse, ServerReflection, ServerReflectionRequest, ServerReflectionResponse,
+    ServiceResponse,
+};
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use grpcio_proto::reflection::v1alpha::reflection::{
+    ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest,
+    ServerReflectionResponse, ServiceResponse,
+};
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+use grpcio_proto::reflection::v1alpha::reflection_grpc::{
+    create_server_reflection, ServerReflection,
+};
+
+/// A reflection query, independent of the codec in use.
+enum Query {
+    FileByFilename(String),
+    FileContainingSymbol(String),
+    FileContainingExtension,
+    AllExtensionNumbersOfType,
+    ListServices,
+    Empty,
+}
+
+/// The answer to a [`Query`].
+enum Reply {
+    Files(Vec<Vec<u8>>),
+    Services(Vec<String>),
+    Error(RpcStatusCode, String),
+}
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+fn parse_query(req: &ServerReflectionRequest) -> Query {
+    if req.has_file_by_filename() {
+        Query::FileByFilename(req.get_file_by_filename().to_owned())
+    } else if req.has_file_containing_symbol() {
+        Query::FileContainingSymbol(req.get_file_containing_symbol().to_owned())
+    } else if req.has_file_containing_extension() {
+        Query::FileContainingExtension
+    } else if req.has_all_extension_numbers_of_type() {
+        Query::AllExtensionNumbersOfType
+    } else if req.has_list_services() {
+        Query::ListServices
+    } else {
+        Query::Empty
+    }
+}
+
+#[cfg(all(feature = "protobuf-codec", not(feature = "prost-codec")))]
+fn build_response(req: ServerReflectionRequest, reply: Reply) -> ServerReflectionResponse {
+    let mut resp = ServerReflectionResponse::default();
+    resp.set_valid_host(req.get_host().to_owned());
+    match reply {
+        Reply::Files(files) => {
+            let mut r = FileDescriptorResponse::default();
+            r.set_file_descriptor_proto(files.into());
+            resp.set_file_descriptor_response(r);
+        }
+        Reply::Services(services) => {
+            let mut r = ListServiceResponse::default();
+            for name in services {
+                let mut s = ServiceResponse::default();
+                s.set_name(name);
+                r.mut_service().push(s);
+            }
+            resp.set_list_services_response(r);
+        }
+        Reply::Error(code, msg) => {
+            let mut r = ErrorResponse::default();
+            r.set_error_code(code.into());
+            r.set_error_message(msg);
+            resp.set_error_response(r);
+        }
+    }
+    resp.set_original_request(req);
+    resp
+}
+
+#[cfg(feature = "prost-codec")]
+fn parse_query(req: &ServerReflectionRequest) -> Query {
+    match &req.message_request {
+        Some(MessageRequest::FileByFilename(f)) => Query::FileByFilename(f.clone()),
+        Some(MessageRequest::FileContainingSymbol(s)) => Query::FileContainingSymbol(s.clone()),
+        Some(MessageRequest::FileContainingExtension(_)) => Query::FileContainingExtension,
+        Some(MessageRequest::AllExtensionNumbersOfType(_)) => Query::AllExtensionNumbersOfType,
+        Some(MessageRequest::ListServices(_)) => Query::ListServices,
+        None => Query::Empty,
+    }
+}
+
+#[cfg(feature = "prost-codec")]
+fn build_response(req: ServerReflectionRequest, reply: Reply) -> ServerReflectionResponse {
+    let message_response = match reply {
+        Reply::Files(files) => MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+            file_descriptor_proto: files,
+        }),
+        Reply::Services(services) => MessageResponse::ListServicesResponse(ListServiceResponse {
+            service: services
+                .into_iter()
+                .map(|name| ServiceResponse { name })
+                .collect(),
+        }),
+        Reply::Error(code, msg) => MessageResponse::ErrorResponse(ErrorResponse {
+            error_code: code.into(),
+            error_message: msg,
+        }),
+    };
+    ServerReflectionResponse {
+        valid_host: req.host.clone(),
+        original_request: Some(req),
+        message_response: Some(message_response),
+    }
+}
+
+/// Index of all registered file descriptors.
+#[derive(Clone, Default)]
+struct Registry {
+    /// Serialized file descriptors keyed by file name.
+    files: HashMap<String, Vec<u8>>,
+    /// Direct dependencies of every file.
+    deps: HashMap<String, Vec<String>>,
+    /// The file that defines a fully qualified symbol.
+    symbols: HashMap<String, String>,
+    services: BTreeSet<String>,
+}
+
+fn qualified_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+impl Registry {
+    fn add_file(&mut self, bytes: &[u8]) -> Result<()> {
+        let mut file = FileDescriptorProto::new();
+        file.merge_from_bytes(bytes)
+            .map_err(|e| Error::Codec(Box::new(e)))?;
+        let name = file.get_name().to_owned();
+        let package = file.get_package();
+        for msg in file.get_message_type() {
+            self.add_message(&name, package, msg);
+        }
+        for e in file.get_enum_type() {
+            self.add_enum(&name, package, e);
+        }
+        for service in file.get_service() {
+            let service_name = qualified_name(package, service.get_name());
+            for method in service.get_method() {
+                let method_name = qualified_name(&service_name, method.get_name());
+                self.symbols.insert(method_name, name.clone());
+            }
+            self.symbols.insert(service_name.clone(), name.clone());
+            self.services.insert(service_name);
+        }
+        self.deps
+            .insert(name.clone(), file.get_dependency().to_vec());
+        self.files.insert(name, bytes.to_vec());
+        Ok(())
+    }
+
+    fn add_message(&mut self, file: &str, scope: &str, msg: &DescriptorProto) {
+        let msg_name = qualified_name(scope, msg.get_name());
+        for nested in msg.get_nested_type() {
+            self.add_message(file, &msg_name, nested);
+        }
+        for e in msg.get_enum_type() {
+            self.add_enum(file, &msg_name, e);
+        }
+        self.symbols.insert(msg_name, file.to_owned());
+    }
+
+    fn add_enum(&mut self, file: &str, scope: &str, e: &EnumDescriptorProto) {
+        let enum_name = qualified_name(scope, e.get_name());
+        self.symbols.insert(enum_name, file.to_owned());
+    }
+
+    /// Returns the given file and all its known transitive dependencies.
+    fn file_with_deps(&self, file: &str) -> Reply {
+        if !self.files.contains_key(file) {
+            return Reply::Error(
+                RpcStatusCode::NOT_FOUND,
+                format!("file not found: {}", file),
+            );
+        }
+        let mut names = vec![file];
+        let mut i = 0;
+        while i < names.len() {
+            for dep in self.deps.get(names[i]).into_iter().flatten() {
+                if self.files.contains_key(dep) && !names.contains(&dep.as_str()) {
+                    names.push(dep);
+                }
+            }
+            i += 1;
+        }
+        Reply::Files(names.into_iter().map(|n| self.files[n].clone()).collect())
+    }
+
+    fn handle(&self, req: ServerReflectionRequest) -> ServerReflectionResponse {
+        let reply = match parse_query(&req) {
+            Query::FileByFilename(file) => self.file_with_deps(&file),
+            Query::FileContainingSymbol(symbol) => match self.symbols.get(&symbol) {
+                Some(file) => self.file_with_deps(file),
+                None => Reply::Error(
+                    RpcStatusCode::NOT_FOUND,
+                    format!("symbol not found: {}", symbol),
+                ),
+            },
+            Query::FileContainingExtension | Query::AllExtensionNumbersOfType => Reply::Error(
+                RpcStatusCode::UNIMPLEMENTED,
+                "extensions are not supported".to_owned(),
+            ),
+            Query::ListServices => Reply::Services(self.services.iter().cloned().collect()),
+            Query::Empty => Reply::Error(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "message_request is not set".to_owned(),
+            ),
+        };
+        build_response(req, reply)
+    }
+}
+
+/// A server reflection service.
+///
+/// Only the services defined in the registered file descriptors are visible
+/// to clients.
+#[derive(Clone, Default)]
+pub struct ReflectionService {
+    registry: Arc<Registry>,
+}
+
+impl ReflectionService {
+    /// Create a service that doesn't know any file descriptors.
+    pub fn new() -> ReflectionService {
+        ReflectionService::default()
+    }
+
+    /// Register serialized `FileDescriptorProto`s.
+    ///
+    /// The `FILE_DESCRIPTORS_*` constants generated by `grpcio-compiler`
+    /// contain the descriptor of a service along with all its dependencies,
+    /// so they can be passed here directly.
+    pub fn add_file_descriptors(&mut self, descriptors: &[&[u8]]) -> Result<()> {
+        let registry = Arc::make_mut(&mut self.registry);
+        for desc in descriptors {
+            registry.add_file(desc)?;
+        }
+        Ok(())
+    }
+}
+
+impl ServerReflection for ReflectionService {
+    fn server_reflection_info(
+        &mut self,
+        ctx: RpcContext<'_>,
+        stream: RequestStream<ServerReflectionRequest>,
+        sink: DuplexSink<ServerReflectionResponse>,
+    ) {
+        let registry = self.registry.clone();
+        let responses = stream.map(move |req| (registry.handle(req), WriteFlags::default()));
+        ctx.spawn(
+            sink.send_all(responses)
+                .map(|_| ())
+                .map_err(|e| debug!("failed to serve reflection: {:?}", e)),
+        );
+    }
+}
+
+/// Create a [`Service`] that serves reflection requests with `reflection`.
+pub fn create_reflection_service(reflection: ReflectionService) -> Service {
+    create_server_reflection(reflection)
+}
@@ -0,0 +1,215 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::{fmt, slice, str};
+
+use crate::grpc_sys::{self, grpc_auth_context, grpc_auth_property_iterator, grpc_call};
+
+const TRANSPORT_SECURITY_TYPE: &str = "transport_security_type";
+const LOCAL_TRANSPORT_SECURITY_TYPE: &str = "local";
+const X509_CN: &str = "x509_common_name";
+const X509_SAN: &str = "x509_subject_alternative_name";
+const X509_PEM_CERT: &str = "x509_pem_cert";
+
+/// The security information of the peer of a call.
+///
+/// It's only available for calls over a secure transport.
+pub struct AuthContext {
+    ctx: *mut grpc_auth_context,
+}
+
+impl AuthContext {
+    pub(crate) unsafe fn from_call_ptr(call: *mut grpc_call) -> Option<AuthContext> {
+        let ctx = grpc_sys::grpc_call_auth_context(call);
+        if ctx.is_null() {
+            None
+        } else {
+            Some(AuthContext { ctx })
+        }
+    }
+
+    /// Get the name of the property that identifies the peer, e.g.
+    /// `x509_subject_alternative_name`.
+    pub fn peer_identity_property_name(&self) -> Option<&str> {
+        unsafe {
+            let p = grpc_sys::grpc_auth_context_peer_identity_property_name(self.ctx);
+            if p.is_null() {
+                None
+            } else {
+                CStr::from_ptr(p).to_str().ok()
+            }
+        }
+    }
+
+    /// Check if the peer is authenticated.
+    pub fn peer_is_authenticated(&self) -> bool {
+        unsafe { grpc_sys::grpc_auth_context_peer_is_authenticated(self.ctx) != 0 }
+    }
+
+    /// Get the properties that identify the peer.
+    ///
+    /// It's empty if the peer is not authenticated.
+    pub fn peer_identity(&self) -> AuthPropertyIter<'_> {
+        let iter = unsafe { grpc_sys::grpc_auth_context_peer_identity(self.ctx) };
+        AuthPropertyIter::new(iter)
+    }
+
+    /// Get all properties in the context.
+    pub fn properties(&self) -> AuthPropertyIter<'_> {
+        let iter = unsafe { grpc_sys::grpc_auth_context_property_iterator(self.ctx) };
+        AuthPropertyIter::new(iter)
+    }
+
+    /// Get the properties named `name`.
+    pub fn find_properties(&self, name: &str) -> AuthPropertyIter<'_> {
+        // A name with nul bytes can't match any property.
+        let name = match CString::new(name) {
+            Ok(n) => n,
+            Err(_) => return AuthPropertyIter::empty(),
+        };
+        let iter =
+            unsafe { grpc_sys::grpc_auth_context_find_properties_by_name(self.ctx, name.as_ptr()) };
+        let mut iter = AuthPropertyIter::new(iter);
+        // The iterator compares properties with `name` on every step.
+        iter.name = Some(name);
+        iter
+    }
+
+    fn find_str(&self, name: &str) -> Option<&str> {
+        self.find_properties(name)
+            .next()
+            .and_then(|p| p.value_str())
+    }
+
+    /// Get the type of the transport security, e.g. `ssl`.
+    pub fn transport_security_type(&self) -> Option<&str> {
+        self.find_str(TRANSPORT_SECURITY_TYPE)
+    }
+
+    /// Check if the call comes from a local connection, i.e. the server
+    /// is built with local credentials.
+    pub fn is_local(&self) -> bool {
+        self.transport_security_type() == Some(LOCAL_TRANSPORT_SECURITY_TYPE)
+    }
+
+    /// Get the common name of the certificate of the peer.
+    pub fn x509_common_name(&self) -> Option<&str> {
+        self.find_str(X509_CN)
+    }
+
+    /// Get the subject alternative names of the certificate of the peer.
+    pub fn x509_subject_alternative_names(&self) -> Vec<&str> {
+        self.find_properties(X509_SAN)
+            .filter_map(|p| p.value_str())
+            .collect()
+    }
+
+    /// Get the certificate of the peer in PEM format.
+    pub fn x509_pem_cert(&self) -> Option<&str> {
+        self.find_str(X509_PEM_CERT)
+    }
+}
+
+impl fmt::Debug for AuthContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.properties().map(|p| (p.name(), p.value())))
+            .finish()
+    }
+}
+
+impl Drop for AuthContext {
+    fn drop(&mut self) {
+        unsafe { grpc_sys::grpc_auth_context_release(self.ctx) }
+    }
+}
+
+// The context is immutable once the call is created.
+unsafe impl Send for AuthContext {}
+unsafe impl Sync for AuthContext {}
+
+/// A property of an [`AuthContext`].
+#[derive(Clone, Copy)]
+pub struct AuthProperty<'a> {
+    name: &'a str,
+    value: &'a [u8],
+}
+
+impl<'a> AuthProperty<'a> {
+    /// Get the name of the property, e.g. `x509_common_name`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Get the raw value of the property.
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Get the value as a string, returns `None` if it's not valid UTF-8.
+    pub fn value_str(&self) -> Option<&'a str> {
+        str::from_utf8(self.value).ok()
+    }
+}
+
+impl fmt::Debug for AuthProperty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthProperty")
+            .field("name", &self.name)
+            .field("value", &String::from_utf8_lossy(self.value))
+            .finish()
+    }
+}
+
+/// An iterator over properties of an [`AuthContext`].
+pub struct AuthPropertyIter<'a> {
+    iter: Option<grpc_auth_property_iterator>,
+    /// The name the properties are filtered by, `iter` refers to it.
+    name: Option<CString>,
+    _lifetime: PhantomData<&'a AuthContext>,
+}
+
+impl<'a> AuthPropertyIter<'a> {
+    fn new(iter: grpc_auth_property_iterator) -> AuthPropertyIter<'a> {
+        AuthPropertyIter {
+            iter: Some(iter),
+            name: None,
+            _lifetime: PhantomData,
+        }
+    }
+
+    fn empty() -> AuthPropertyIter<'a> {
+        AuthPropertyIter {
+            iter: None,
+            name: None,
+            _lifetime: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for AuthPropertyIter<'a> {
+    type Item = AuthProperty<'a>;
+
+    fn next(&mut self) -> Option<AuthProperty<'a>> {
+        let iter = self.iter.as_mut()?;
+        loop {
+            unsafe {
+                let prop = grpc_sys::grpc_auth_property_iterator_next(iter);
+                if prop.is_null() {
+                    self.iter = None;
+                    self.name.take();
+                    return None;
+                }
+                // Properties can be added by custom auth metadata processors,
+                // skip the ones whose names are not valid UTF-8.
+                let name = match CStr::from_ptr((*prop).name).to_str() {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                let value = slice::from_raw_parts((*prop).value as *const u8, (*prop).value_length);
+                return Some(AuthProperty { name, value });
+            }
+        }
+    }
+}
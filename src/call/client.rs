@@ -1,19 +1,24 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::ptr;
 use std::sync::Arc;
 use std::time::Duration;
+use std::{cmp, ptr};
 
 use crate::grpc_sys;
 use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
 
+use super::retry::{HedgingPolicy, RetryPolicy, UnaryAttempts};
+use super::server::Deadline;
 use super::{ShareCall, ShareCallHolder, SinkBase, WriteFlags};
-use crate::call::{check_run, Call, MessageReader, Method};
+use crate::call::{run_batch, Call, MessageReader, Method, RpcStatus};
 use crate::channel::Channel;
+use crate::client::CallObserver;
 use crate::codec::{DeserializeFn, SerializeFn};
+#[cfg(feature = "secure")]
+use crate::credentials::CallCredentials;
 use crate::error::{Error, Result};
 use crate::metadata::Metadata;
-use crate::task::{BatchFuture, BatchType, SpinLock};
+use crate::task::{BatchFuture, BatchType, CallTag, SpinLock};
 
 /// Update the flag bit in res.
 #[inline]
@@ -29,9 +34,14 @@ pub fn change_flag(res: &mut u32, flag: u32, set: bool) {
 #[derive(Clone, Default)]
 pub struct CallOption {
     timeout: Option<Duration>,
+    deadline: Option<Deadline>,
     write_flags: WriteFlags,
     call_flags: u32,
     headers: Option<Metadata>,
+    retry_policy: Option<RetryPolicy>,
+    hedging_policy: Option<HedgingPolicy>,
+    #[cfg(feature = "secure")]
+    call_credentials: Option<Arc<CallCredentials>>,
 }
 
 impl CallOption {
@@ -77,9 +87,23 @@ impl CallOption {
         self
     }
 
-    /// Get the timeout.
+    /// Propagate the deadline of an incoming call to the call.
+    ///
+    /// The call is given the time left before `deadline`, or the timeout
+    /// if it's shorter, so that it doesn't outlive the incoming call.
+    pub fn deadline_from(mut self, deadline: &Deadline) -> CallOption {
+        if !deadline.is_infinite() {
+            self.deadline = Some(*deadline);
+        }
+        self
+    }
+
+    /// Get the timeout, taking the propagated deadline into account.
     pub fn get_timeout(&self) -> Option<Duration> {
-        self.timeout
+        match (self.timeout, self.deadline.map(|d| d.remaining())) {
+            (Some(t), Some(d)) => Some(cmp::min(t, d)),
+            (t, d) => t.or(d),
+        }
     }
 
     /// Set the headers to be sent with the call.
@@ -92,6 +116,99 @@ impl CallOption {
     pub fn get_headers(&self) -> Option<&Metadata> {
         self.headers.as_ref()
     }
+
+    /// Set the policy for retrying the call if it's a unary call.
+    ///
+    /// It overrides the default policy of the channel.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> CallOption {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Get the retry policy.
+    pub fn get_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Set the policy for hedging the call if it's an idempotent unary call.
+    ///
+    /// It overrides the default policy of the channel, and takes precedence
+    /// over the retry policy.
+    pub fn hedging_policy(mut self, policy: HedgingPolicy) -> CallOption {
+        self.hedging_policy = Some(policy);
+        self
+    }
+
+    /// Get the hedging policy.
+    pub fn get_hedging_policy(&self) -> Option<&HedgingPolicy> {
+        self.hedging_policy.as_ref()
+    }
+
+    /// Set the credentials of the call.
+    ///
+    /// They are combined with the call credentials of the channel if any.
+    /// Note that call credentials are only sent over secure channels.
+    #[cfg(feature = "secure")]
+    pub fn call_credentials(mut self, creds: CallCredentials) -> CallOption {
+        self.call_credentials = Some(Arc::new(creds));
+        self
+    }
+
+    /// Get the credentials of the call.
+    #[cfg(feature = "secure")]
+    pub fn get_call_credentials(&self) -> Option<&CallCredentials> {
+        self.call_credentials.as_ref().map(|c| &**c)
+    }
+
+    pub(crate) fn is_idempotent(&self) -> bool {
+        self.call_flags & grpc_sys::GRPC_INITIAL_METADATA_IDEMPOTENT_REQUEST != 0
+    }
+}
+
+#[derive(Default)]
+struct MetadataSlots {
+    headers: Option<Metadata>,
+    trailers: Option<Metadata>,
+}
+
+/// A handle to the metadata sent by the server in response to a call.
+///
+/// It can be obtained from any of the client receivers, and stays valid
+/// after the receiver is consumed.
+#[derive(Clone)]
+pub struct ResponseMetadata {
+    slots: Arc<SpinLock<MetadataSlots>>,
+}
+
+impl ResponseMetadata {
+    pub(crate) fn new() -> ResponseMetadata {
+        ResponseMetadata {
+            slots: Arc::new(SpinLock::new(MetadataSlots::default())),
+        }
+    }
+
+    /// Get the initial metadata (headers) sent by the server.
+    ///
+    /// `None` is returned if the headers have not been received yet.
+    pub fn headers(&self) -> Option<Metadata> {
+        self.slots.lock().headers.clone()
+    }
+
+    /// Get the trailing metadata (trailers) sent by the server.
+    ///
+    /// `None` is returned if the call has not finished yet. Trailers are
+    /// also available when the call fails with an error status.
+    pub fn trailers(&self) -> Option<Metadata> {
+        self.slots.lock().trailers.clone()
+    }
+
+    pub(crate) fn set_headers(&self, headers: Metadata) {
+        self.slots.lock().headers = Some(headers);
+    }
+
+    pub(crate) fn set_trailers(&self, trailers: Metadata) {
+        self.slots.lock().trailers = Some(trailers);
+    }
 }
 
 impl Call {
@@ -99,12 +216,47 @@ impl Call {
         channel: &Channel,
         method: &Method<Req, Resp>,
         req: &Req,
-        mut opt: CallOption,
+        opt: CallOption,
+        observer: Option<CallObserver>,
     ) -> Result<ClientUnaryReceiver<Resp>> {
-        let call = channel.create_call(method, &opt)?;
         let mut payload = vec![];
         (method.req_ser())(req, &mut payload);
-        let cq_f = check_run(BatchType::CheckRead, |ctx, tag| unsafe {
+        Call::unary_async_by_path(
+            channel,
+            method.name,
+            payload,
+            method.resp_de(),
+            opt,
+            observer,
+        )
+    }
+
+    /// Start a unary call to the method at `path` with a serialized request.
+    pub fn unary_async_by_path<Resp>(
+        channel: &Channel,
+        path: &str,
+        payload: Vec<u8>,
+        resp_de: DeserializeFn<Resp>,
+        opt: CallOption,
+        observer: Option<CallObserver>,
+    ) -> Result<ClientUnaryReceiver<Resp>> {
+        let meta = ResponseMetadata::new();
+        let attempts = UnaryAttempts::start(channel, path, payload, opt, &meta)?;
+        Ok(ClientUnaryReceiver::new(attempts, resp_de, meta, observer))
+    }
+
+    /// Start an attempt of a unary call.
+    pub(crate) fn start_unary(
+        channel: &Channel,
+        method: &str,
+        payload: &[u8],
+        mut opt: CallOption,
+        meta: &ResponseMetadata,
+    ) -> Result<(Call, BatchFuture)> {
+        let call = channel.create_call(method, &opt)?;
+        let tag_pair =
+            CallTag::batch_pair_with_metadata(BatchType::CheckRead, Some(meta), Some(meta));
+        let cq_f = run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_start_unary(
                 call.call,
                 ctx,
@@ -118,16 +270,20 @@ impl Call {
                 tag,
             )
         });
-        Ok(ClientUnaryReceiver::new(call, cq_f, method.resp_de()))
+        Ok((call, cq_f))
     }
 
     pub fn client_streaming<Req, Resp>(
         channel: &Channel,
         method: &Method<Req, Resp>,
         mut opt: CallOption,
+        observer: Option<CallObserver>,
     ) -> Result<(ClientCStreamSender<Req>, ClientCStreamReceiver<Resp>)> {
-        let call = channel.create_call(method, &opt)?;
-        let cq_f = check_run(BatchType::CheckRead, |ctx, tag| unsafe {
+        let call = channel.create_call(method.name, &opt)?;
+        let meta = ResponseMetadata::new();
+        let tag_pair =
+            CallTag::batch_pair_with_metadata(BatchType::CheckRead, Some(&meta), Some(&meta));
+        let cq_f = run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_start_client_streaming(
                 call.call,
                 ctx,
@@ -139,12 +295,15 @@ impl Call {
             )
         });
 
-        let share_call = Arc::new(SpinLock::new(ShareCall::new(call, cq_f)));
+        let mut share_call = ShareCall::new(call, cq_f);
+        share_call.observer = observer;
+        let share_call = Arc::new(SpinLock::new(share_call));
         let sink = ClientCStreamSender::new(share_call.clone(), method.req_ser());
         let recv = ClientCStreamReceiver {
             call: share_call,
             resp_de: method.resp_de(),
             finished: false,
+            meta,
         };
         Ok((sink, recv))
     }
@@ -154,11 +313,14 @@ impl Call {
         method: &Method<Req, Resp>,
         req: &Req,
         mut opt: CallOption,
+        observer: Option<CallObserver>,
     ) -> Result<ClientSStreamReceiver<Resp>> {
-        let call = channel.create_call(method, &opt)?;
+        let call = channel.create_call(method.name, &opt)?;
         let mut payload = vec![];
         (method.req_ser())(req, &mut payload);
-        let cq_f = check_run(BatchType::Finish, |ctx, tag| unsafe {
+        let meta = ResponseMetadata::new();
+        let tag_pair = CallTag::batch_pair_with_metadata(BatchType::Finish, None, Some(&meta));
+        let cq_f = run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_start_server_streaming(
                 call.call,
                 ctx,
@@ -173,21 +335,49 @@ impl Call {
             )
         });
 
-        // TODO: handle header
-        check_run(BatchType::Finish, |ctx, tag| unsafe {
+        let tag_pair = CallTag::batch_pair_with_metadata(BatchType::Finish, Some(&meta), None);
+        run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_recv_initial_metadata(call.call, ctx, tag)
         });
 
-        Ok(ClientSStreamReceiver::new(call, cq_f, method.resp_de()))
+        Ok(ClientSStreamReceiver::new(
+            call,
+            cq_f,
+            method.resp_de(),
+            meta,
+            observer,
+        ))
     }
 
     pub fn duplex_streaming<Req, Resp>(
         channel: &Channel,
         method: &Method<Req, Resp>,
+        opt: CallOption,
+        observer: Option<CallObserver>,
+    ) -> Result<(ClientDuplexSender<Req>, ClientDuplexReceiver<Resp>)> {
+        Call::duplex_streaming_by_path(
+            channel,
+            method.name,
+            method.req_ser(),
+            method.resp_de(),
+            opt,
+            observer,
+        )
+    }
+
+    /// Start a duplex streaming call to the method at `path`.
+    pub fn duplex_streaming_by_path<Req, Resp>(
+        channel: &Channel,
+        path: &str,
+        req_ser: SerializeFn<Req>,
+        resp_de: DeserializeFn<Resp>,
         mut opt: CallOption,
+        observer: Option<CallObserver>,
     ) -> Result<(ClientDuplexSender<Req>, ClientDuplexReceiver<Resp>)> {
-        let call = channel.create_call(method, &opt)?;
-        let cq_f = check_run(BatchType::Finish, |ctx, tag| unsafe {
+        let call = channel.create_call(path, &opt)?;
+        let meta = ResponseMetadata::new();
+        let tag_pair = CallTag::batch_pair_with_metadata(BatchType::Finish, None, Some(&meta));
+        let cq_f = run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_start_duplex_streaming(
                 call.call,
                 ctx,
@@ -199,14 +389,16 @@ impl Call {
             )
         });
 
-        // TODO: handle header.
-        check_run(BatchType::Finish, |ctx, tag| unsafe {
+        let tag_pair = CallTag::batch_pair_with_metadata(BatchType::Finish, Some(&meta), None);
+        run_batch(tag_pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_recv_initial_metadata(call.call, ctx, tag)
         });
 
-        let share_call = Arc::new(SpinLock::new(ShareCall::new(call, cq_f)));
-        let sink = ClientDuplexSender::new(share_call.clone(), method.req_ser());
-        let recv = ClientDuplexReceiver::new(share_call, method.resp_de());
+        let mut share_call = ShareCall::new(call, cq_f);
+        share_call.observer = observer;
+        let share_call = Arc::new(SpinLock::new(share_call));
+        let sink = ClientDuplexSender::new(share_call.clone(), req_ser);
+        let recv = ClientDuplexReceiver::new(share_call, resp_de, meta);
         Ok((sink, recv))
     }
 }
@@ -216,24 +408,38 @@ impl Call {
 /// The future is resolved once response is received.
 #[must_use = "if unused the ClientUnaryReceiver may immediately cancel the RPC"]
 pub struct ClientUnaryReceiver<T> {
-    call: Call,
-    resp_f: BatchFuture,
+    attempts: UnaryAttempts,
     resp_de: DeserializeFn<T>,
+    meta: ResponseMetadata,
+    observer: Option<CallObserver>,
 }
 
 impl<T> ClientUnaryReceiver<T> {
-    fn new(call: Call, resp_f: BatchFuture, resp_de: DeserializeFn<T>) -> ClientUnaryReceiver<T> {
+    fn new(
+        attempts: UnaryAttempts,
+        resp_de: DeserializeFn<T>,
+        meta: ResponseMetadata,
+        observer: Option<CallObserver>,
+    ) -> ClientUnaryReceiver<T> {
         ClientUnaryReceiver {
-            call,
-            resp_f,
+            attempts,
             resp_de,
+            meta,
+            observer,
         }
     }
 
     /// Cancel the call.
+    ///
+    /// All attempts in flight are cancelled, and no more retries are made.
     #[inline]
     pub fn cancel(&mut self) {
-        self.call.cancel()
+        self.attempts.cancel()
+    }
+
+    /// Get the handle to the response metadata of the call.
+    pub fn response_metadata(&self) -> ResponseMetadata {
+        self.meta.clone()
     }
 
     #[inline]
@@ -247,9 +453,18 @@ impl<T> Future for ClientUnaryReceiver<T> {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<T, Error> {
-        let data = try_ready!(self.resp_f.poll());
-        let t = self.resp_de(data.unwrap())?;
-        Ok(Async::Ready(t))
+        let res = match self.attempts.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(data)) => self.resp_de(data.unwrap()),
+            Err(e) => Err(e),
+        };
+        if let Some(observer) = self.observer.take() {
+            match res {
+                Ok(_) => observer.on_finish(&RpcStatus::ok()),
+                Err(ref e) => observer.on_error(e),
+            }
+        }
+        res.map(Async::Ready)
     }
 }
 
@@ -265,6 +480,7 @@ pub struct ClientCStreamReceiver<T> {
     call: Arc<SpinLock<ShareCall>>,
     resp_de: DeserializeFn<T>,
     finished: bool,
+    meta: ResponseMetadata,
 }
 
 impl<T> ClientCStreamReceiver<T> {
@@ -274,6 +490,11 @@ impl<T> ClientCStreamReceiver<T> {
         lock.call.cancel()
     }
 
+    /// Get the handle to the response metadata of the call.
+    pub fn response_metadata(&self) -> ResponseMetadata {
+        self.meta.clone()
+    }
+
     #[inline]
     pub fn resp_de(&self, reader: MessageReader) -> Result<T> {
         (self.resp_de)(reader)
@@ -479,6 +700,7 @@ impl<H: ShareCallHolder, T> ResponseStreamImpl<H, T> {
 #[must_use = "if unused the ClientSStreamReceiver may immediately cancel the RPC"]
 pub struct ClientSStreamReceiver<Resp> {
     imp: ResponseStreamImpl<ShareCall, Resp>,
+    meta: ResponseMetadata,
 }
 
 impl<Resp> ClientSStreamReceiver<Resp> {
@@ -486,16 +708,25 @@ impl<Resp> ClientSStreamReceiver<Resp> {
         call: Call,
         finish_f: BatchFuture,
         de: DeserializeFn<Resp>,
+        meta: ResponseMetadata,
+        observer: Option<CallObserver>,
     ) -> ClientSStreamReceiver<Resp> {
-        let share_call = ShareCall::new(call, finish_f);
+        let mut share_call = ShareCall::new(call, finish_f);
+        share_call.observer = observer;
         ClientSStreamReceiver {
             imp: ResponseStreamImpl::new(share_call, de),
+            meta,
         }
     }
 
     pub fn cancel(&mut self) {
         self.imp.cancel()
     }
+
+    /// Get the handle to the response metadata of the call.
+    pub fn response_metadata(&self) -> ResponseMetadata {
+        self.meta.clone()
+    }
 }
 
 impl<Resp> Stream for ClientSStreamReceiver<Resp> {
@@ -517,18 +748,29 @@ impl<Resp> Stream for ClientSStreamReceiver<Resp> {
 #[must_use = "if unused the ClientDuplexReceiver may immediately cancel the RPC"]
 pub struct ClientDuplexReceiver<Resp> {
     imp: ResponseStreamImpl<Arc<SpinLock<ShareCall>>, Resp>,
+    meta: ResponseMetadata,
 }
 
 impl<Resp> ClientDuplexReceiver<Resp> {
-    fn new(call: Arc<SpinLock<ShareCall>>, de: DeserializeFn<Resp>) -> ClientDuplexReceiver<Resp> {
+    fn new(
+        call: Arc<SpinLock<ShareCall>>,
+        de: DeserializeFn<Resp>,
+        meta: ResponseMetadata,
+    ) -> ClientDuplexReceiver<Resp> {
         ClientDuplexReceiver {
             imp: ResponseStreamImpl::new(call, de),
+            meta,
         }
     }
 
     pub fn cancel(&mut self) {
         self.imp.cancel()
     }
+
+    /// Get the handle to the response metadata of the call.
+    pub fn response_metadata(&self) -> ResponseMetadata {
+        self.meta.clone()
+    }
 }
 
 impl<Resp> Drop for ClientDuplexReceiver<Resp> {
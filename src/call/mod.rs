@@ -1,10 +1,11 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
 pub mod client;
+pub mod retry;
 pub mod server;
 
 use std::sync::Arc;
-use std::{ptr, slice};
+use std::{mem, ptr, slice};
 
 use crate::cq::CompletionQueue;
 use crate::grpc_sys::{self, grpc_call, grpc_call_error, grpcwrap_batch_context};
@@ -12,14 +13,21 @@ use futures::{Async, Future, Poll};
 use libc::c_void;
 
 use crate::buf::{GrpcByteBuffer, GrpcByteBufferReader};
+use crate::call::server::CloseNotifier;
+use crate::client::CallObserver;
 use crate::codec::{DeserializeFn, Marshaller, SerializeFn};
 use crate::error::{Error, Result};
 use crate::grpc_sys::grpc_status_code::*;
+use crate::metadata::{Metadata, MetadataBuilder};
+use crate::server::TrackedCall;
 use crate::task::{self, BatchFuture, BatchType, CallTag, SpinLock};
 
 // By default buffers in `SinkBase` will be shrink to 4K size.
 const BUF_SHRINK_SIZE: usize = 4 * 1024;
 
+// The trailer key that carries a serialized `google.rpc.Status`.
+const STATUS_DETAILS_KEY: &str = "grpc-status-details-bin";
+
 /// An gRPC status code structure.
 /// This type contains constants for all gRPC status codes.
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -44,9 +52,19 @@ macro_rules! status_codes {
         )+
     ) => {
         impl RpcStatusCode {
-        $(
-            pub const $konst: RpcStatusCode = RpcStatusCode($num);
-        )+
+            $(
+                pub const $konst: RpcStatusCode = RpcStatusCode($num);
+            )+
+
+            /// Returns the canonical name of the code, e.g. `UNAVAILABLE`.
+            pub(crate) fn name(self) -> Option<&'static str> {
+                $(
+                    if self == RpcStatusCode::$konst {
+                        return Some(stringify!($konst));
+                    }
+                )+
+                None
+            }
         }
     }
 }
@@ -138,14 +156,27 @@ pub struct RpcStatus {
 
     /// Optional detail string.
     pub details: Option<String>,
+
+    /// Rich error details, see [`status_details`](#method.status_details).
+    status_details: Vec<u8>,
 }
 
 impl RpcStatus {
     /// Create a new [`RpcStatus`].
     pub fn new<T: Into<RpcStatusCode>>(code: T, details: Option<String>) -> RpcStatus {
+        RpcStatus::with_status_details(code, details, Vec::new())
+    }
+
+    /// Create a new [`RpcStatus`] with rich error details.
+    pub fn with_status_details<T: Into<RpcStatusCode>>(
+        code: T,
+        details: Option<String>,
+        status_details: Vec<u8>,
+    ) -> RpcStatus {
         RpcStatus {
             status: code.into(),
             details,
+            status_details,
         }
     }
 
@@ -153,6 +184,15 @@ impl RpcStatus {
     pub fn ok() -> RpcStatus {
         RpcStatus::new(RpcStatusCode::OK, None)
     }
+
+    /// Get the rich error details, usually a serialized `google.rpc.Status`
+    /// message.
+    ///
+    /// They are transferred in the `grpc-status-details-bin` trailer. Empty
+    /// means there are no rich error details.
+    pub fn status_details(&self) -> &[u8] {
+        &self.status_details
+    }
 }
 
 pub type MessageReader = GrpcByteBufferReader;
@@ -188,21 +228,34 @@ impl BatchContext {
             grpc_sys::grpcwrap_batch_context_recv_status_on_client_status(self.ctx)
         });
 
-        let details = if status == RpcStatusCode::OK {
-            None
-        } else {
-            unsafe {
-                let mut details_len = 0;
-                let details_ptr = grpc_sys::grpcwrap_batch_context_recv_status_on_client_details(
-                    self.ctx,
-                    &mut details_len,
-                );
-                let details_slice = slice::from_raw_parts(details_ptr as *const _, details_len);
-                Some(String::from_utf8_lossy(details_slice).into_owned())
-            }
-        };
+        if status == RpcStatusCode::OK {
+            return RpcStatus::new(status, None);
+        }
+
+        unsafe {
+            let mut details_len = 0;
+            let details_ptr = grpc_sys::grpcwrap_batch_context_recv_status_on_client_details(
+                self.ctx,
+                &mut details_len,
+            );
+            let details_slice = slice::from_raw_parts(details_ptr as *const _, details_len);
+            let details = Some(String::from_utf8_lossy(details_slice).into_owned());
+
+            let trailers = Metadata::from_raw_ref(
+                grpc_sys::grpcwrap_batch_context_recv_status_on_client_trailing_metadata(self.ctx),
+            );
+            let status_details = trailers
+                .iter()
+                .find(|(k, _)| *k == STATUS_DETAILS_KEY)
+                .map_or_else(Vec::new, |(_, v)| v.to_vec());
+            RpcStatus::with_status_details(status, details, status_details)
+        }
+    }
 
-        RpcStatus::new(status, details)
+    /// Check if the server call is cancelled, only meaningful once the close
+    /// is received.
+    pub fn recv_close_on_server_cancelled(&self) -> bool {
+        unsafe { grpc_sys::grpcwrap_batch_context_recv_close_on_server_cancelled(self.ctx) != 0 }
     }
 
     /// Fetch the response bytes of the rpc call.
@@ -210,6 +263,23 @@ impl BatchContext {
         let buf = self.take_recv_message()?;
         Some(GrpcByteBufferReader::new(buf))
     }
+
+    /// Get a copy of the initial metadata received from the server.
+    pub fn recv_initial_metadata(&self) -> Metadata {
+        unsafe {
+            let arr = grpc_sys::grpcwrap_batch_context_recv_initial_metadata(self.ctx);
+            Metadata::copy_from_raw(arr)
+        }
+    }
+
+    /// Get a copy of the trailing metadata received from the server.
+    pub fn recv_trailing_metadata(&self) -> Metadata {
+        unsafe {
+            let arr =
+                grpc_sys::grpcwrap_batch_context_recv_status_on_client_trailing_metadata(self.ctx);
+            Metadata::copy_from_raw(arr)
+        }
+    }
 }
 
 impl Drop for BatchContext {
@@ -227,12 +297,34 @@ fn box_batch_tag(tag: CallTag) -> (*mut grpcwrap_batch_context, *mut c_void) {
     )
 }
 
+/// Attach the rich error details of `status` to the trailing metadata.
+///
+/// `None` is returned if there is nothing to attach.
+fn status_trailers(status: &RpcStatus, trailing_meta: Option<&Metadata>) -> Option<Metadata> {
+    if status.status_details.is_empty() {
+        return None;
+    }
+    let meta = trailing_meta.map_or_else(|| MetadataBuilder::new().build(), Metadata::clone);
+    let mut builder = MetadataBuilder::from_metadata(meta);
+    builder
+        .add_bytes(STATUS_DETAILS_KEY, &status.status_details)
+        .unwrap();
+    Some(builder.build())
+}
+
 /// A helper function that runs the batch call and checks the result.
 fn check_run<F>(bt: BatchType, f: F) -> BatchFuture
 where
     F: FnOnce(*mut grpcwrap_batch_context, *mut c_void) -> grpc_call_error,
 {
-    let (cq_f, tag) = CallTag::batch_pair(bt);
+    run_batch(CallTag::batch_pair(bt), f)
+}
+
+/// Similar to `check_run`, but uses the given Future/CallTag pair.
+fn run_batch<F>((cq_f, tag): (BatchFuture, CallTag), f: F) -> BatchFuture
+where
+    F: FnOnce(*mut grpcwrap_batch_context, *mut c_void) -> grpc_call_error,
+{
     let (batch_ptr, tag_ptr) = box_batch_tag(tag);
     let code = f(batch_ptr, tag_ptr);
     if code != grpc_call_error::GRPC_CALL_OK {
@@ -263,14 +355,15 @@ impl Call {
     }
 
     /// Send a message asynchronously.
+    ///
+    /// If `initial_meta` is given, it's sent as the initial metadata along with the message.
     pub fn start_send_message(
         &mut self,
         msg: &[u8],
         write_flags: u32,
-        initial_meta: bool,
+        initial_meta: Option<&mut Metadata>,
     ) -> Result<BatchFuture> {
         let _cq_ref = self.cq.borrow()?;
-        let i = if initial_meta { 1 } else { 0 };
         let f = check_run(BatchType::Finish, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_send_message(
                 self.call,
@@ -278,7 +371,7 @@ impl Call {
                 msg.as_ptr() as _,
                 msg.len(),
                 write_flags,
-                i,
+                initial_meta.map_or_else(ptr::null_mut, |m| m as *mut _ as _),
                 tag,
             )
         });
@@ -306,24 +399,35 @@ impl Call {
     /// Start handling from server side.
     ///
     /// Future will finish once close is received by the server.
-    pub fn start_server_side(&mut self) -> Result<BatchFuture> {
+    ///
+    /// `tracked` is released and `close` is notified once the call is finished.
+    pub fn start_server_side(
+        &mut self,
+        tracked: TrackedCall,
+        close: CloseNotifier,
+    ) -> Result<BatchFuture> {
         let _cq_ref = self.cq.borrow()?;
-        let f = check_run(BatchType::Finish, |ctx, tag| unsafe {
+        let pair = CallTag::server_finish_pair(tracked, close);
+        let f = run_batch(pair, |ctx, tag| unsafe {
             grpc_sys::grpcwrap_call_start_serverside(self.call, ctx, tag)
         });
         Ok(f)
     }
 
     /// Send a status from server.
+    ///
+    /// If `initial_meta` is given, it's sent as the initial metadata before the status.
     pub fn start_send_status_from_server(
         &mut self,
         status: &RpcStatus,
-        send_empty_metadata: bool,
+        initial_meta: Option<&mut Metadata>,
+        trailing_meta: Option<&mut Metadata>,
         payload: &Option<Vec<u8>>,
         write_flags: u32,
     ) -> Result<BatchFuture> {
         let _cq_ref = self.cq.borrow()?;
-        let send_empty_metadata = if send_empty_metadata { 1 } else { 0 };
+        let mut trailers = status_trailers(status, trailing_meta.as_ref().map(|m| &**m));
+        let trailing_meta = trailers.as_mut().or(trailing_meta);
         let (payload_ptr, payload_len) = payload
             .as_ref()
             .map_or((ptr::null(), 0), |b| (b.as_ptr(), b.len()));
@@ -339,8 +443,8 @@ impl Call {
                 status.status.into(),
                 details_ptr,
                 details_len,
-                ptr::null_mut(),
-                send_empty_metadata,
+                trailing_meta.map_or_else(ptr::null_mut, |m| m as *mut _ as _),
+                initial_meta.map_or_else(ptr::null_mut, |m| m as *mut _ as _),
                 payload_ptr as _,
                 payload_len,
                 write_flags,
@@ -361,6 +465,8 @@ impl Call {
         let call_ptr = self.call;
         let tag = CallTag::abort(self);
         let (batch_ptr, tag_ptr) = box_batch_tag(tag);
+        let mut initial_meta = MetadataBuilder::new().build();
+        let mut trailers = status_trailers(status, None);
 
         let code = unsafe {
             let details_ptr = status
@@ -374,8 +480,10 @@ impl Call {
                 status.status.into(),
                 details_ptr,
                 details_len,
-                ptr::null_mut(),
-                1,
+                trailers
+                    .as_mut()
+                    .map_or_else(ptr::null_mut, |m| m as *mut _ as _),
+                &mut initial_meta as *mut _ as _,
                 ptr::null(),
                 0,
                 0,
@@ -420,6 +528,7 @@ struct ShareCall {
     close_f: BatchFuture,
     finished: bool,
     status: Option<RpcStatus>,
+    observer: Option<CallObserver>,
 }
 
 impl ShareCall {
@@ -429,6 +538,7 @@ impl ShareCall {
             close_f,
             finished: false,
             status: None,
+            observer: None,
         }
     }
 
@@ -449,6 +559,12 @@ impl ShareCall {
             res => res,
         };
 
+        if let Some(observer) = self.observer.take() {
+            match res {
+                Ok(_) => observer.on_finish(&RpcStatus::ok()),
+                Err(ref e) => observer.on_error(e),
+            }
+        }
         self.finished = true;
         res
     }
@@ -603,6 +719,7 @@ struct SinkBase {
     batch_f: Option<BatchFuture>,
     buf: Vec<u8>,
     send_metadata: bool,
+    headers: Metadata,
 }
 
 impl SinkBase {
@@ -611,6 +728,16 @@ impl SinkBase {
             batch_f: None,
             buf: Vec::new(),
             send_metadata,
+            headers: MetadataBuilder::new().build(),
+        }
+    }
+
+    /// Get the initial metadata that should be sent with the next batch, if any.
+    fn initial_metadata(&mut self) -> Option<&mut Metadata> {
+        if self.send_metadata {
+            Some(&mut self.headers)
+        } else {
+            None
         }
     }
 
@@ -635,10 +762,11 @@ impl SinkBase {
             // temporary fix: buffer hint with send meta will not send out any metadata.
             flags = flags.buffer_hint(false);
         }
-        let write_f = call.call(|c| {
-            c.call
-                .start_send_message(&self.buf, flags.flags, self.send_metadata)
-        })?;
+        let buf = mem::take(&mut self.buf);
+        let headers = self.initial_metadata();
+        let res = call.call(|c| c.call.start_send_message(&buf, flags.flags, headers));
+        self.buf = buf;
+        let write_f = res?;
         // NOTE: Content of `self.buf` is copied into grpc internal.
         if self.buf.capacity() > BUF_SHRINK_SIZE {
             self.buf.truncate(BUF_SHRINK_SIZE);
@@ -0,0 +1,516 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Retry and hedging of unary calls.
+//!
+//! The behavior follows [gRFC A6], except that the policies are applied by
+//! the client instead of being loaded from the service config. A call is
+//! committed once an attempt receives response headers, and is not retried
+//! after that.
+//!
+//! [gRFC A6]: https://github.com/grpc/proposal/blob/master/A6-client-retries.md
+
+use std::cell::Cell;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll};
+
+use super::client::{CallOption, ResponseMetadata};
+use super::{Call, MessageReader, RpcStatus, RpcStatusCode};
+use crate::channel::Channel;
+use crate::error::{Error, Result};
+use crate::task::{BatchFuture, Delay};
+
+/// Policy for retrying unary calls that fail with transient errors.
+///
+/// The `n`th retry is made after a random delay between zero and
+/// `min(initial_backoff * backoff_multiplier^(n - 1), max_backoff)`.
+/// The call is not retried once its deadline is exceeded.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) backoff_multiplier: f64,
+    pub(crate) retryable_codes: Vec<RpcStatusCode>,
+}
+
+impl RetryPolicy {
+    /// Create a policy that makes at most `max_attempts` attempts, including
+    /// the original one.
+    ///
+    /// By default, only calls that fail with `UNAVAILABLE` are retried.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: cmp::max(max_attempts, 1),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            backoff_multiplier: 2.0,
+            retryable_codes: vec![RpcStatusCode::UNAVAILABLE],
+        }
+    }
+
+    /// Set the upper bound of the delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound of the delay before any retry.
+    pub fn max_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the multiplier the backoff grows by after each retry.
+    pub fn backoff_multiplier(mut self, multiplier: f64) -> RetryPolicy {
+        self.backoff_multiplier = multiplier;
+        self
+    }
+
+    /// Set the status codes that are considered transient.
+    pub fn retryable_status_codes(mut self, codes: Vec<RpcStatusCode>) -> RetryPolicy {
+        self.retryable_codes = codes;
+        self
+    }
+
+    /// Returns the backoff to use after `backoff`.
+    fn next_backoff(&self, backoff: Duration) -> Duration {
+        let next = backoff.as_secs_f64() * self.backoff_multiplier;
+        if next >= self.max_backoff.as_secs_f64() {
+            self.max_backoff
+        } else {
+            Duration::from_secs_f64(next)
+        }
+    }
+}
+
+/// Policy for hedging idempotent unary calls.
+///
+/// The request is sent again every `hedging_delay` until a response is
+/// received or `max_attempts` attempts are made. The first successful
+/// response is used and all other attempts are cancelled.
+///
+/// An attempt failed with a non-fatal status code triggers the next attempt
+/// immediately. Any other failure fails the call.
+#[derive(Clone, Debug)]
+pub struct HedgingPolicy {
+    max_attempts: u32,
+    hedging_delay: Duration,
+    non_fatal_codes: Vec<RpcStatusCode>,
+}
+
+impl HedgingPolicy {
+    /// Create a policy that makes at most `max_attempts` attempts, including
+    /// the original one.
+    ///
+    /// By default, only `UNAVAILABLE` is considered non-fatal.
+    pub fn new(max_attempts: u32, hedging_delay: Duration) -> HedgingPolicy {
+        HedgingPolicy {
+            max_attempts: cmp::max(max_attempts, 1),
+            hedging_delay,
+            non_fatal_codes: vec![RpcStatusCode::UNAVAILABLE],
+        }
+    }
+
+    /// Set the status codes that are considered non-fatal.
+    pub fn non_fatal_status_codes(mut self, codes: Vec<RpcStatusCode>) -> HedgingPolicy {
+        self.non_fatal_codes = codes;
+        self
+    }
+}
+
+/// Stops retries and hedging of a channel once too many attempts fail.
+///
+/// Every failed attempt takes one token away, and every successful call
+/// gives back `token_ratio` tokens. Retries are only allowed while there
+/// are more than half of `max_tokens` tokens left.
+#[derive(Debug)]
+pub(crate) struct RetryThrottle {
+    max_tokens: f64,
+    token_ratio: f64,
+    tokens: Mutex<f64>,
+}
+
+impl RetryThrottle {
+    pub fn new(max_tokens: u32, token_ratio: f64) -> RetryThrottle {
+        let max_tokens = f64::from(max_tokens);
+        RetryThrottle {
+            max_tokens,
+            token_ratio,
+            tokens: Mutex::new(max_tokens),
+        }
+    }
+
+    fn on_success(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.token_ratio).min(self.max_tokens);
+    }
+
+    /// Returns whether retries are still allowed.
+    fn on_failure(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens - 1.0).max(0.0);
+        *tokens > self.max_tokens / 2.0
+    }
+}
+
+/// The default retry settings of all calls on a channel.
+#[derive(Clone, Default)]
+pub(crate) struct RetryConfig {
+    pub retry_policy: Option<RetryPolicy>,
+    pub hedging_policy: Option<HedgingPolicy>,
+    pub throttle: Option<Arc<RetryThrottle>>,
+}
+
+thread_local! {
+    /// State of a xorshift64* generator. `RandomState` is only used to get a
+    /// random seed.
+    static RNG: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// A random duration between zero and `max`.
+fn jitter(max: Duration) -> Duration {
+    let rand = RNG.with(|rng| {
+        let mut x = rng.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        rng.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    });
+    max.mul_f64((rand >> 11) as f64 / (1u64 << 53) as f64)
+}
+
+enum Mode {
+    Retry {
+        policy: RetryPolicy,
+        backoff: Duration,
+    },
+    Hedge(HedgingPolicy),
+}
+
+impl Mode {
+    fn max_attempts(&self) -> u32 {
+        match self {
+            Mode::Retry { policy, .. } => policy.max_attempts,
+            Mode::Hedge(policy) => policy.max_attempts,
+        }
+    }
+
+    fn can_retry(&self, code: RpcStatusCode) -> bool {
+        match self {
+            Mode::Retry { policy, .. } => policy.retryable_codes.contains(&code),
+            Mode::Hedge(policy) => policy.non_fatal_codes.contains(&code),
+        }
+    }
+}
+
+/// An attempt of a unary call.
+struct Attempt {
+    call: Call,
+    f: BatchFuture,
+    /// The metadata received by the attempt, which is copied to the call's
+    /// once the attempt decides the result of the call.
+    meta: ResponseMetadata,
+}
+
+impl Attempt {
+    fn start(
+        channel: &Channel,
+        method: &str,
+        payload: &[u8],
+        opt: CallOption,
+        meta: ResponseMetadata,
+    ) -> Result<Attempt> {
+        let (call, f) = Call::start_unary(channel, method, payload, opt, &meta)?;
+        Ok(Attempt { call, f, meta })
+    }
+
+    /// Whether the server has sent response headers, which commits the call.
+    fn committed(&self) -> bool {
+        self.meta.headers().map_or(false, |h| !h.is_empty())
+    }
+}
+
+/// Everything needed to start new attempts of a call.
+struct Retry {
+    channel: Channel,
+    method: String,
+    payload: Vec<u8>,
+    opt: CallOption,
+    deadline: Option<Instant>,
+    mode: Mode,
+    throttle: Option<Arc<RetryThrottle>>,
+    started: u32,
+    /// Starts the next attempt when fired.
+    delay: Option<Delay>,
+}
+
+impl Retry {
+    fn start_attempt(&mut self) -> Result<Attempt> {
+        let mut opt = self.opt.clone();
+        if let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                let status = RpcStatus::new(RpcStatusCode::DEADLINE_EXCEEDED, None);
+                return Err(Error::RpcFailure(status));
+            }
+            opt = opt.timeout(deadline - now);
+        }
+        self.started += 1;
+        let meta = ResponseMetadata::new();
+        Attempt::start(&self.channel, &self.method, &self.payload, opt, meta)
+    }
+
+    fn schedule_hedge(&mut self) {
+        if let Mode::Hedge(ref policy) = self.mode {
+            if self.started < policy.max_attempts {
+                self.delay = Some(Delay::new(policy.hedging_delay));
+            }
+        }
+    }
+}
+
+/// All attempts of a unary call.
+pub(crate) struct UnaryAttempts {
+    /// There can be more than one attempt in flight only when hedging.
+    inflight: Vec<Attempt>,
+    retry: Option<Box<Retry>>,
+    meta: ResponseMetadata,
+}
+
+impl UnaryAttempts {
+    /// Start the first attempt of the call.
+    ///
+    /// Policies in `opt` take precedence over the defaults of the channel.
+    /// Hedging is only used for idempotent calls.
+    pub fn start(
+        channel: &Channel,
+        method: &str,
+        payload: Vec<u8>,
+        opt: CallOption,
+        meta: &ResponseMetadata,
+    ) -> Result<UnaryAttempts> {
+        let config = channel.retry_config();
+        let hedging_policy = if opt.is_idempotent() {
+            opt.get_hedging_policy().or(config.hedging_policy.as_ref())
+        } else {
+            None
+        };
+        let mode = match hedging_policy {
+            Some(policy) => Mode::Hedge(policy.clone()),
+            None => match opt.get_retry_policy().or(config.retry_policy.as_ref()) {
+                Some(policy) => Mode::Retry {
+                    backoff: policy.initial_backoff,
+                    policy: policy.clone(),
+                },
+                None => {
+                    let attempt = Attempt::start(channel, method, &payload, opt, meta.clone())?;
+                    return Ok(UnaryAttempts {
+                        inflight: vec![attempt],
+                        retry: None,
+                        meta: meta.clone(),
+                    });
+                }
+            },
+        };
+
+        let mut retry = Box::new(Retry {
+            channel: channel.clone(),
+            method: method.to_owned(),
+            payload,
+            deadline: opt.get_timeout().map(|t| Instant::now() + t),
+            opt,
+            mode,
+            throttle: config.throttle.clone(),
+            started: 0,
+            delay: None,
+        });
+        let attempt = retry.start_attempt()?;
+        retry.schedule_hedge();
+        Ok(UnaryAttempts {
+            inflight: vec![attempt],
+            retry: Some(retry),
+            meta: meta.clone(),
+        })
+    }
+
+    /// Cancel all attempts in flight, and stop making new ones.
+    pub fn cancel(&mut self) {
+        for attempt in self.inflight.drain(..) {
+            attempt.call.cancel();
+        }
+        self.retry = None;
+    }
+
+    /// Makes the metadata received by `attempt` visible to the call.
+    fn use_metadata(&self, attempt: &Attempt) {
+        if let Some(headers) = attempt.meta.headers() {
+            self.meta.set_headers(headers);
+        }
+        if let Some(trailers) = attempt.meta.trailers() {
+            self.meta.set_trailers(trailers);
+        }
+    }
+
+    /// Decides what to do after `attempt` fails.
+    ///
+    /// `e` is returned if the call should fail.
+    fn on_failure(&mut self, attempt: Attempt, e: Error) -> Result<()> {
+        let retry = match self.retry {
+            Some(ref mut r) => r,
+            None => return Err(e),
+        };
+        let can_retry = match e {
+            Error::RpcFailure(ref status) => retry.mode.can_retry(status.status),
+            _ => false,
+        };
+        if !can_retry || attempt.committed() {
+            self.use_metadata(&attempt);
+            return Err(e);
+        }
+        let throttled = retry.throttle.as_ref().map_or(false, |t| !t.on_failure());
+        if throttled || retry.started >= retry.mode.max_attempts() {
+            // Hedged attempts that are still in flight may succeed.
+            retry.delay = None;
+            return if self.inflight.is_empty() {
+                self.use_metadata(&attempt);
+                Err(e)
+            } else {
+                Ok(())
+            };
+        }
+        match retry.mode {
+            Mode::Retry {
+                ref policy,
+                ref mut backoff,
+            } => {
+                retry.delay = Some(Delay::new(jitter(*backoff)));
+                *backoff = policy.next_backoff(*backoff);
+            }
+            Mode::Hedge(_) => {
+                let attempt = retry.start_attempt()?;
+                self.inflight.push(attempt);
+                retry.schedule_hedge();
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_inflight(&mut self) -> Result<Option<Option<MessageReader>>> {
+        let mut i = 0;
+        while i < self.inflight.len() {
+            let e = match self.inflight[i].f.poll() {
+                Ok(Async::NotReady) => {
+                    i += 1;
+                    continue;
+                }
+                Ok(Async::Ready(data)) => {
+                    if let Some(t) = self.retry.as_ref().and_then(|r| r.throttle.as_ref()) {
+                        t.on_success();
+                    }
+                    let attempt = self.inflight.swap_remove(i);
+                    self.use_metadata(&attempt);
+                    self.cancel();
+                    return Ok(Some(data));
+                }
+                Err(e) => e,
+            };
+            let attempt = self.inflight.swap_remove(i);
+            self.on_failure(attempt, e)?;
+        }
+        Ok(None)
+    }
+
+    /// Returns true if a new attempt is started.
+    fn poll_delay(&mut self) -> Result<bool> {
+        let retry = match self.retry {
+            Some(ref mut r) => r,
+            None => return Ok(false),
+        };
+        if !retry.delay.as_mut().map_or(false, Delay::poll) {
+            return Ok(false);
+        }
+        retry.delay = None;
+        let attempt = retry.start_attempt()?;
+        self.inflight.push(attempt);
+        retry.schedule_hedge();
+        Ok(true)
+    }
+}
+
+impl Future for UnaryAttempts {
+    type Item = Option<MessageReader>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<MessageReader>, Error> {
+        loop {
+            let res = self.poll_inflight().and_then(|data| match data {
+                Some(data) => Ok(Some(data)),
+                None => self
+                    .poll_delay()
+                    .map(|started| if started { None } else { Some(None) }),
+            });
+            match res {
+                Ok(Some(Some(data))) => return Ok(Async::Ready(Some(data))),
+                Ok(Some(None)) => return Ok(Async::NotReady),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.cancel();
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_throttle() {
+        let throttle = RetryThrottle::new(4, 0.5);
+        assert!(throttle.on_failure());
+        // 2 tokens left, which is not more than half of the max tokens.
+        assert!(!throttle.on_failure());
+        for _ in 0..3 {
+            throttle.on_success();
+        }
+        assert!(throttle.on_failure());
+
+        // Tokens never drop below zero or grow beyond the max tokens.
+        for _ in 0..10 {
+            throttle.on_failure();
+        }
+        for _ in 0..3 {
+            throttle.on_success();
+        }
+        assert!(!throttle.on_failure());
+        for _ in 0..20 {
+            throttle.on_success();
+        }
+        assert_eq!(*throttle.tokens.lock().unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(5)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300))
+            .backoff_multiplier(2.0);
+        let backoff = policy.next_backoff(policy.initial_backoff);
+        assert_eq!(backoff, Duration::from_millis(200));
+        let backoff = policy.next_backoff(backoff);
+        assert_eq!(backoff, Duration::from_millis(300));
+        assert_eq!(policy.next_backoff(backoff), Duration::from_millis(300));
+
+        for _ in 0..100 {
+            assert!(jitter(backoff) <= backoff);
+        }
+    }
+}
@@ -1,25 +1,31 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
 use std::ffi::CStr;
-use std::sync::Arc;
-use std::{result, slice};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{mem, result, slice};
 
 use crate::grpc_sys::{
     self, gpr_clock_type, gpr_timespec, grpc_call_error, grpcwrap_request_call_context,
 };
+use futures::task::{self, Task};
 use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
 
 use super::{RpcStatus, ShareCall, ShareCallHolder, WriteFlags};
+#[cfg(feature = "secure")]
+use crate::auth_context::AuthContext;
 use crate::call::{
     BatchContext, Call, MessageReader, MethodType, RpcStatusCode, SinkBase, StreamingBase,
 };
 use crate::codec::{DeserializeFn, SerializeFn};
 use crate::cq::CompletionQueue;
-use crate::error::Error;
-use crate::metadata::Metadata;
-use crate::server::{BoxHandler, RequestCallContext};
+use crate::error::{Error, Result};
+use crate::metadata::{Metadata, MetadataBuilder};
+use crate::server::{BoxHandler, CallCounter, RequestCallContext, TrackedCall};
 use crate::task::{BatchFuture, CallTag, Executor, Kicker, SpinLock};
 
+#[derive(Clone, Copy)]
 pub struct Deadline {
     spec: gpr_timespec,
 }
@@ -40,12 +46,58 @@ impl Deadline {
             grpc_sys::gpr_time_cmp(now, self.spec) >= 0
         }
     }
+
+    /// Check if the client sets no deadline.
+    pub fn is_infinite(&self) -> bool {
+        self.spec.tv_sec == i64::MAX
+    }
+
+    /// Get the time left before the deadline, zero if it's exceeded.
+    ///
+    /// It's very long if the deadline is infinite.
+    pub fn remaining(&self) -> Duration {
+        let left = unsafe {
+            let now = grpc_sys::gpr_now(gpr_clock_type::GPR_CLOCK_REALTIME);
+            grpc_sys::gpr_time_sub(self.spec, now)
+        };
+        if left.tv_sec < 0 {
+            return Duration::from_secs(0);
+        }
+        Duration::new(left.tv_sec as u64, left.tv_nsec as u32)
+    }
+
+    /// Convert the deadline to an `Instant`, returns `None` if it's infinite.
+    pub fn to_instant(&self) -> Option<Instant> {
+        if self.is_infinite() {
+            return None;
+        }
+        Instant::now().checked_add(self.remaining())
+    }
+
+    /// Convert the deadline to a `SystemTime`, returns `None` if it's infinite.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        if self.is_infinite() || self.spec.tv_sec < 0 {
+            return None;
+        }
+        let since_epoch = Duration::new(self.spec.tv_sec as u64, self.spec.tv_nsec as u32);
+        UNIX_EPOCH.checked_add(since_epoch)
+    }
+}
+
+impl Debug for Deadline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.to_system_time() {
+            Some(t) => write!(f, "Deadline({:?})", t),
+            None => write!(f, "Deadline(inf)"),
+        }
+    }
 }
 
 /// Context for accepting a request.
 pub struct RequestContext {
     ctx: *mut grpcwrap_request_call_context,
     request_call: Option<RequestCallContext>,
+    calls: CallCounter,
 }
 
 impl RequestContext {
@@ -54,6 +106,7 @@ impl RequestContext {
 
         RequestContext {
             ctx,
+            calls: rc.call_counter().clone(),
             request_call: Some(rc),
         }
     }
@@ -112,6 +165,15 @@ impl RequestContext {
         self.ctx
     }
 
+    fn track_call(&self) -> TrackedCall {
+        self.calls.track()
+    }
+
+    fn close_notifier(&self) -> CloseNotifier {
+        // Nobody waits for calls that are not handled.
+        CloseNotifier::default()
+    }
+
     fn call(&self, cq: CompletionQueue) -> Call {
         unsafe {
             // It is okay to use a mutable pointer on a immutable reference, `self`,
@@ -163,6 +225,14 @@ impl RequestContext {
             peer
         }
     }
+
+    #[cfg(feature = "secure")]
+    fn auth_context(&self) -> Option<AuthContext> {
+        unsafe {
+            let call = grpc_sys::grpcwrap_request_call_context_get_call(self.ctx);
+            AuthContext::from_call_ptr(call)
+        }
+    }
 }
 
 impl Drop for RequestContext {
@@ -301,6 +371,8 @@ macro_rules! impl_unary_sink {
             call: Option<$holder>,
             write_flags: u32,
             ser: SerializeFn<T>,
+            headers: Option<Metadata>,
+            trailers: Option<Metadata>,
         }
 
         impl<T> $t<T> {
@@ -309,9 +381,21 @@ macro_rules! impl_unary_sink {
                     call: Some(call),
                     write_flags: 0,
                     ser: ser,
+                    headers: None,
+                    trailers: None,
                 }
             }
 
+            /// Set the initial metadata (headers) that is sent with the response.
+            pub fn set_headers(&mut self, meta: Metadata) {
+                self.headers = Some(meta);
+            }
+
+            /// Set the trailing metadata (trailers) that is sent with the status.
+            pub fn set_trailers(&mut self, meta: Metadata) {
+                self.trailers = Some(meta);
+            }
+
             pub fn success(self, t: T) -> $rt {
                 self.complete(RpcStatus::ok(), Some(t))
             }
@@ -328,9 +412,19 @@ macro_rules! impl_unary_sink {
                 });
 
                 let write_flags = self.write_flags;
+                let mut headers = self
+                    .headers
+                    .take()
+                    .unwrap_or_else(|| MetadataBuilder::new().build());
+                let trailers = self.trailers.as_mut();
                 let res = self.call.as_mut().unwrap().call(|c| {
-                    c.call
-                        .start_send_status_from_server(&status, true, &data, write_flags)
+                    c.call.start_send_status_from_server(
+                        &status,
+                        Some(&mut headers),
+                        trailers,
+                        &data,
+                        write_flags,
+                    )
                 });
 
                 let (cq_f, err) = match res {
@@ -383,6 +477,12 @@ impl_unary_sink!(
     Arc<SpinLock<ShareCall>>
 );
 
+/// The error of sending headers or a status more than once, which is the same
+/// as the one reported by gRPC core.
+fn too_many_operations() -> Error {
+    Error::CallFailure(grpc_call_error::GRPC_CALL_ERROR_TOO_MANY_OPERATIONS)
+}
+
 // A macro helper to implement server side streaming sink.
 macro_rules! impl_stream_sink {
     ($(#[$attr:meta])* $t:ident, $ft:ident, $holder:ty) => {
@@ -392,6 +492,7 @@ macro_rules! impl_stream_sink {
             base: SinkBase,
             flush_f: Option<BatchFuture>,
             status: RpcStatus,
+            trailers: Option<Metadata>,
             flushed: bool,
             closed: bool,
             ser: SerializeFn<T>,
@@ -404,6 +505,7 @@ macro_rules! impl_stream_sink {
                     base: SinkBase::new(true),
                     flush_f: None,
                     status: RpcStatus::ok(),
+                    trailers: None,
                     flushed: false,
                     closed: false,
                     ser: ser,
@@ -415,12 +517,41 @@ macro_rules! impl_stream_sink {
                 self.status = status;
             }
 
+            /// Set the initial metadata (headers) that is sent before the first message.
+            ///
+            /// Returns an error if the headers have been sent already.
+            pub fn set_headers(&mut self, meta: Metadata) -> Result<()> {
+                if !self.base.send_metadata {
+                    return Err(too_many_operations());
+                }
+                self.base.headers = meta;
+                Ok(())
+            }
+
+            /// Set the trailing metadata (trailers) that is sent with the status.
+            ///
+            /// Returns an error if the sink is being closed already.
+            pub fn set_trailers(&mut self, meta: Metadata) -> Result<()> {
+                if self.flush_f.is_some() {
+                    return Err(too_many_operations());
+                }
+                self.trailers = Some(meta);
+                Ok(())
+            }
+
             pub fn fail(mut self, status: RpcStatus) -> $ft {
-                assert!(self.flush_f.is_none());
-                let send_metadata = self.base.send_metadata;
+                if self.flush_f.is_some() {
+                    return $ft {
+                        call: self.call.take().unwrap(),
+                        fail_f: None,
+                        err: Some(too_many_operations()),
+                    };
+                }
+                let headers = self.base.initial_metadata();
+                let trailers = self.trailers.as_mut();
                 let res = self.call.as_mut().unwrap().call(|c| {
                     c.call
-                        .start_send_status_from_server(&status, send_metadata, &None, 0)
+                        .start_send_status_from_server(&status, headers, trailers, &None, 0)
                 });
 
                 let (fail_f, err) = match res {
@@ -481,11 +612,12 @@ macro_rules! impl_stream_sink {
                 if self.flush_f.is_none() {
                     try_ready!(self.base.poll_complete());
 
-                    let send_metadata = self.base.send_metadata;
+                    let headers = self.base.initial_metadata();
+                    let trailers = self.trailers.as_mut();
                     let status = &self.status;
                     let flush_f = self.call.as_mut().unwrap().call(|c| {
                         c.call
-                            .start_send_status_from_server(status, send_metadata, &None, 0)
+                            .start_send_status_from_server(status, headers, trailers, &None, 0)
                     })?;
                     self.flush_f = Some(flush_f);
                 }
@@ -566,6 +698,7 @@ pub struct RpcContext<'a> {
     ctx: RequestContext,
     executor: Executor<'a>,
     deadline: Deadline,
+    close: CloseNotifier,
 }
 
 impl<'a> RpcContext<'a> {
@@ -574,6 +707,7 @@ impl<'a> RpcContext<'a> {
             deadline: ctx.deadline(),
             ctx,
             executor: Executor::new(cq),
+            close: CloseNotifier::default(),
         }
     }
 
@@ -586,6 +720,14 @@ impl<'a> RpcContext<'a> {
         self.ctx.call(self.executor.cq().clone())
     }
 
+    fn track_call(&self) -> TrackedCall {
+        self.ctx.track_call()
+    }
+
+    fn close_notifier(&self) -> CloseNotifier {
+        self.close.clone()
+    }
+
     pub fn method(&self) -> &[u8] {
         self.ctx.method()
     }
@@ -607,6 +749,29 @@ impl<'a> RpcContext<'a> {
         self.ctx.peer()
     }
 
+    /// Get the security information of the client.
+    ///
+    /// Returns `None` if the call is not over a secure transport.
+    #[cfg(feature = "secure")]
+    pub fn auth_context(&self) -> Option<AuthContext> {
+        self.ctx.auth_context()
+    }
+
+    /// Check if the call is cancelled by the client, or its deadline is
+    /// exceeded.
+    pub fn is_cancelled(&self) -> bool {
+        self.close.is_cancelled()
+    }
+
+    /// Get a future that resolves when the call is cancelled.
+    ///
+    /// It can be used to abort long running work early.
+    pub fn cancelled(&self) -> CancellationFuture {
+        CancellationFuture {
+            close: self.close.clone(),
+        }
+    }
+
     /// Spawn the future into current gRPC poll thread.
     ///
     /// This can reduce a lot of context switching, but please make
@@ -617,13 +782,160 @@ impl<'a> RpcContext<'a> {
     {
         self.executor.spawn(f, self.kicker())
     }
+
+    /// Spawn the std future into current gRPC poll thread.
+    ///
+    /// It works the same as [`spawn`](#method.spawn).
+    #[cfg(feature = "std-future")]
+    pub fn spawn_std<F>(&self, f: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.spawn(crate::std_future::into_future_01(f))
+    }
+}
+
+/// An owned context for rpc handling.
+///
+/// Unlike [`RpcContext`], it can be moved into the futures returned by
+/// async handlers.
+#[cfg(feature = "std-future")]
+pub struct AsyncRpcContext {
+    method: Vec<u8>,
+    host: Vec<u8>,
+    peer: String,
+    deadline: Deadline,
+    request_headers: Metadata,
+    #[cfg(feature = "secure")]
+    auth_context: Option<AuthContext>,
+    close: CloseNotifier,
+}
+
+#[cfg(feature = "std-future")]
+impl AsyncRpcContext {
+    pub(crate) fn new(ctx: &RpcContext<'_>) -> AsyncRpcContext {
+        AsyncRpcContext {
+            method: ctx.method().to_vec(),
+            host: ctx.host().to_vec(),
+            peer: ctx.peer(),
+            deadline: *ctx.deadline(),
+            request_headers: ctx.request_headers().clone(),
+            #[cfg(feature = "secure")]
+            auth_context: ctx.auth_context(),
+            close: ctx.close.clone(),
+        }
+    }
+
+    pub fn method(&self) -> &[u8] {
+        &self.method
+    }
+
+    pub fn host(&self) -> &[u8] {
+        &self.host
+    }
+
+    pub fn deadline(&self) -> &Deadline {
+        &self.deadline
+    }
+
+    /// Get the initial metadata sent by client.
+    pub fn request_headers(&self) -> &Metadata {
+        &self.request_headers
+    }
+
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    /// Get the security information of the client.
+    ///
+    /// Returns `None` if the call is not over a secure transport.
+    #[cfg(feature = "secure")]
+    pub fn auth_context(&self) -> Option<&AuthContext> {
+        self.auth_context.as_ref()
+    }
+
+    /// Check if the call is cancelled by the client, or its deadline is
+    /// exceeded.
+    pub fn is_cancelled(&self) -> bool {
+        self.close.is_cancelled()
+    }
+
+    /// Get a future that resolves when the call is cancelled.
+    pub fn cancelled(&self) -> CancellationFuture {
+        CancellationFuture {
+            close: self.close.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CloseState {
+    closed: bool,
+    cancelled: bool,
+    tasks: Vec<Task>,
+}
+
+/// Notifies the context of a server call when the call is closed.
+#[derive(Clone, Default)]
+pub struct CloseNotifier {
+    state: Arc<Mutex<CloseState>>,
+}
+
+impl CloseNotifier {
+    pub fn close(&self, cancelled: bool) {
+        let tasks = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.cancelled = cancelled;
+            mem::take(&mut state.tasks)
+        };
+        for t in tasks {
+            t.notify();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    fn poll_cancelled(&self) -> Async<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.cancelled {
+            return Async::Ready(());
+        }
+        // Calls that finish normally are never cancelled.
+        if !state.closed && !state.tasks.iter().any(Task::will_notify_current) {
+            state.tasks.push(task::current());
+        }
+        Async::NotReady
+    }
+}
+
+/// A future that resolves when a server call is cancelled.
+///
+/// A call is cancelled if the client cancels it, its deadline is exceeded,
+/// or the connection is broken. The future never resolves if the call
+/// finishes normally.
+#[must_use = "futures do nothing unless polled"]
+pub struct CancellationFuture {
+    close: CloseNotifier,
+}
+
+impl Future for CancellationFuture {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        Ok(self.close.poll_cancelled())
+    }
 }
 
 // Following four helper functions are used to create a callback closure.
 
 macro_rules! accept_call {
-    ($call:expr) => {
-        match $call.start_server_side() {
+    ($ctx:expr, $call:expr) => {
+        match $call.start_server_side($ctx.track_call(), $ctx.close_notifier()) {
             Err(Error::QueueShutdown) => return,
             Err(e) => panic!("unexpected error when trying to accept request: {:?}", e),
             Ok(f) => f,
@@ -642,7 +954,7 @@ pub fn execute_unary<P, Q, F>(
     F: FnMut(RpcContext<'_>, P, UnarySink<Q>),
 {
     let mut call = ctx.call();
-    let close_f = accept_call!(call);
+    let close_f = accept_call!(ctx, call);
     let request = match de(payload) {
         Ok(f) => f,
         Err(e) => {
@@ -668,7 +980,7 @@ pub fn execute_client_streaming<P, Q, F>(
     F: FnMut(RpcContext<'_>, RequestStream<P>, ClientStreamingSink<Q>),
 {
     let mut call = ctx.call();
-    let close_f = accept_call!(call);
+    let close_f = accept_call!(ctx, call);
     let call = Arc::new(SpinLock::new(ShareCall::new(call, close_f)));
 
     let req_s = RequestStream::new(call.clone(), de);
@@ -687,7 +999,7 @@ pub fn execute_server_streaming<P, Q, F>(
     F: FnMut(RpcContext<'_>, P, ServerStreamingSink<Q>),
 {
     let mut call = ctx.call();
-    let close_f = accept_call!(call);
+    let close_f = accept_call!(ctx, call);
 
     let request = match de(payload) {
         Ok(t) => t,
@@ -715,7 +1027,7 @@ pub fn execute_duplex_streaming<P, Q, F>(
     F: FnMut(RpcContext<'_>, RequestStream<P>, DuplexSink<Q>),
 {
     let mut call = ctx.call();
-    let close_f = accept_call!(call);
+    let close_f = accept_call!(ctx, call);
     let call = Arc::new(SpinLock::new(ShareCall::new(call, close_f)));
 
     let req_s = RequestStream::new(call.clone(), de);
@@ -723,13 +1035,31 @@ pub fn execute_duplex_streaming<P, Q, F>(
     f(ctx, req_s, sink)
 }
 
-// A helper function used to handle all undefined rpc calls.
+// Accept the call and then fail it with the status, so it's tracked and
+// closed like any other call.
+macro_rules! reject_call {
+    ($ctx:expr, $call:expr, $status:expr) => {{
+        let mut call = $call;
+        accept_call!($ctx, call);
+        call.abort($status)
+    }};
+}
+
+// A helper function used to handle all undefined rpc calls when there is
+// no fallback handler.
 pub fn execute_unimplemented(ctx: RequestContext, cq: CompletionQueue) {
     // Suppress needless-pass-by-value.
     let ctx = ctx;
-    let mut call = ctx.call(cq);
-    accept_call!(call);
-    call.abort(&RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None))
+    reject_call!(
+        ctx,
+        ctx.call(cq),
+        &RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None)
+    )
+}
+
+// A helper function used to fail calls that are rejected by interceptors.
+pub fn execute_rejected(ctx: &RpcContext<'_>, status: &RpcStatus) {
+    reject_call!(ctx, ctx.call(), status)
 }
 
 // Helper function to call handler.
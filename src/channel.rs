@@ -4,22 +4,29 @@ use std::borrow::Cow;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{cmp, i32, ptr};
 
+use futures::{Async, Future, Poll, Stream};
+
 use crate::grpc_sys::{
     self, gpr_timespec, grpc_arg_pointer_vtable, grpc_channel, grpc_channel_args,
 };
 use libc::{self, c_char, c_int};
 
-use crate::call::{Call, Method};
+use crate::call::retry::{HedgingPolicy, RetryConfig, RetryPolicy, RetryThrottle};
+use crate::call::Call;
+use crate::client::ClientInterceptor;
 use crate::cq::CompletionQueue;
 use crate::env::Environment;
-use crate::error::Result;
-use crate::task::Kicker;
+use crate::error::{Error, Result};
+use crate::resolver::{self, Resolution, Resolver, ResolverFactory, ResolverObserver};
+use crate::task::{CallTag, CqFuture, Kicker};
 use crate::CallOption;
 use crate::ResourceQuota;
+use crate::{RpcStatus, RpcStatusCode, ServiceConfig};
 
 pub use crate::grpc_sys::{
     grpc_compression_algorithm as CompressionAlgorithms,
@@ -101,6 +108,8 @@ pub enum LbPolicy {
 pub struct ChannelBuilder {
     env: Arc<Environment>,
     options: HashMap<Cow<'static, [u8]>, Options>,
+    interceptors: Vec<Arc<dyn ClientInterceptor>>,
+    retry: RetryConfig,
 }
 
 impl ChannelBuilder {
@@ -109,9 +118,52 @@ impl ChannelBuilder {
         ChannelBuilder {
             env,
             options: HashMap::new(),
+            interceptors: Vec::new(),
+            retry: RetryConfig::default(),
         }
     }
 
+    /// Add an interceptor that is invoked for all calls made on the channel.
+    ///
+    /// Interceptors are invoked in the order they are added.
+    pub fn add_interceptor<I>(mut self, interceptor: I) -> ChannelBuilder
+    where
+        I: ClientInterceptor + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Set the default policy for retrying unary calls made on the channel.
+    ///
+    /// It can be overridden by [`CallOption::retry_policy`].
+    ///
+    /// [`CallOption::retry_policy`]: ./struct.CallOption.html#method.retry_policy
+    pub fn default_retry_policy(mut self, policy: RetryPolicy) -> ChannelBuilder {
+        self.retry.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set the default policy for hedging idempotent unary calls made on the channel.
+    ///
+    /// It can be overridden by [`CallOption::hedging_policy`].
+    ///
+    /// [`CallOption::hedging_policy`]: ./struct.CallOption.html#method.hedging_policy
+    pub fn default_hedging_policy(mut self, policy: HedgingPolicy) -> ChannelBuilder {
+        self.retry.hedging_policy = Some(policy);
+        self
+    }
+
+    /// Throttle retries and hedging of all calls made on the channel.
+    ///
+    /// Every failed attempt takes away one of the `max_tokens` tokens, and every
+    /// successful call gives back `token_ratio` tokens. No more retries or hedged
+    /// attempts are made while there are no more than half of `max_tokens` tokens.
+    pub fn retry_throttling(mut self, max_tokens: u32, token_ratio: f64) -> ChannelBuilder {
+        self.retry.throttle = Some(Arc::new(RetryThrottle::new(max_tokens, token_ratio)));
+        self
+    }
+
     /// Set default authority to pass if none specified on call construction.
     pub fn default_authority<S: Into<Vec<u8>>>(mut self, authority: S) -> ChannelBuilder {
         let authority = CString::new(authority).unwrap();
@@ -399,6 +451,28 @@ impl ChannelBuilder {
         self
     }
 
+    /// Set the service config of the channel.
+    ///
+    /// It's used unless the name resolver provides one. Retry policies in the
+    /// config are applied by gRPC core, separately from the policies set by
+    /// [`ChannelBuilder::default_retry_policy`].
+    ///
+    /// [`ChannelBuilder::default_retry_policy`]: #method.default_retry_policy
+    pub fn service_config(mut self, config: &ServiceConfig) -> ChannelBuilder {
+        let json = CString::new(config.as_json()).unwrap();
+        self.options.insert(
+            Cow::Borrowed(grpc_sys::GRPC_ARG_SERVICE_CONFIG),
+            Options::String(json),
+        );
+        if config.enable_retries() {
+            self.options.insert(
+                Cow::Borrowed(grpc_sys::GRPC_ARG_ENABLE_RETRIES),
+                Options::Integer(1),
+            );
+        }
+        self
+    }
+
     /// Set a raw integer configuration.
     ///
     /// This method is only for bench usage, users should use the encapsulated API instead.
@@ -461,15 +535,113 @@ impl ChannelBuilder {
         self.build_args()
     }
 
+    /// Get the resolver registered for the scheme of `target`.
+    fn resolver_factory<'a>(&self, target: &'a str) -> Option<(Arc<dyn ResolverFactory>, &'a str)> {
+        let (scheme, endpoint) = resolver::parse_target(target)?;
+        let factory = self.env.resolver(scheme)?;
+        Some((factory.clone(), endpoint))
+    }
+
+    /// Build a [`Channel`] whose addresses are resolved by `factory`.
+    fn connect_resolved(
+        mut self,
+        target: &str,
+        factory: Arc<dyn ResolverFactory>,
+        endpoint: &str,
+        connector: Connector,
+    ) -> Channel {
+        if let Entry::Vacant(e) = self.options.entry(Cow::Borrowed(PRIMARY_USER_AGENT_STRING)) {
+            e.insert(Options::String(format_user_agent_string("")));
+        }
+        let default_config = match self.options.remove(&grpc_sys::GRPC_ARG_SERVICE_CONFIG[..]) {
+            Some(Options::String(config)) => Some(config),
+            _ => None,
+        };
+        let default_retries = match self.options.remove(&grpc_sys::GRPC_ARG_ENABLE_RETRIES[..]) {
+            Some(Options::Integer(enabled)) => enabled != 0,
+            _ => false,
+        };
+        // gRPC core only sees the resolved addresses, so the authority is
+        // taken from the endpoint unless it's set explicitly.
+        if !endpoint.is_empty() {
+            self.options
+                .entry(Cow::Borrowed(OPT_DEFAULT_AUTHORITY))
+                .or_insert_with(|| Options::String(CString::new(endpoint).unwrap()));
+        }
+        let env = self.env.clone();
+        let cq = env.pick_cq();
+        let interceptors = self.interceptors.clone();
+        let retry = self.retry.clone();
+        let target = CString::new(target).unwrap();
+        let pending = lame_channel(
+            &target,
+            RpcStatusCode::UNAVAILABLE,
+            "waiting for name resolution",
+        );
+        let resolved = Arc::new(ResolvedChannel {
+            current: Mutex::new(Arc::new(ChannelInner {
+                _env: env,
+                channel: pending,
+            })),
+            state: Mutex::new(ResolveState {
+                builder: self,
+                connector,
+                target,
+                default_config,
+                default_retries,
+                last: None,
+            }),
+            resolver: Mutex::new(None),
+            reresolution_requested: AtomicBool::new(false),
+        });
+        let observer = ResolverObserver::new(Arc::downgrade(&resolved));
+        let r = factory.build(endpoint, observer);
+        *resolved.resolver.lock().unwrap() = Some(r);
+
+        Channel {
+            inner: RawChannel::Resolved(resolved),
+            cq,
+            interceptors,
+            retry,
+        }
+    }
+
     /// Build an insecure [`Channel`] that connects to a specific address.
+    ///
+    /// If a resolver is registered for the scheme of `addr`, addresses are
+    /// resolved by the resolver.
     pub fn connect(mut self, addr: &str) -> Channel {
+        if let Some((factory, endpoint)) = self.resolver_factory(addr) {
+            return self.connect_resolved(addr, factory, endpoint, Connector::Insecure);
+        }
         let args = self.prepare_connect_args();
         let addr = CString::new(addr).unwrap();
         let addr_ptr = addr.as_ptr();
         let channel =
             unsafe { grpc_sys::grpc_insecure_channel_create(addr_ptr, args.args, ptr::null_mut()) };
 
-        Channel::new(self.env.pick_cq(), self.env, channel)
+        Channel::new(
+            self.env.pick_cq(),
+            self.env,
+            channel,
+            self.interceptors,
+            self.retry,
+        )
+    }
+
+    /// Build an insecure [`Channel`] that connects to a unix domain socket
+    /// at the given path.
+    pub fn connect_unix(self, path: &str) -> Channel {
+        self.connect(&format!("unix:{}", path))
+    }
+
+    /// Build an insecure [`Channel`] that connects to a unix domain socket
+    /// in the abstract namespace.
+    ///
+    /// Abstract sockets are only available on Linux, and require the gRPC C
+    /// core to support the `unix-abstract` scheme.
+    pub fn connect_unix_abstract(self, name: &str) -> Channel {
+        self.connect(&format!("unix-abstract:{}", name))
     }
 }
 
@@ -482,8 +654,9 @@ mod secure_channel {
     use crate::grpc_sys;
 
     use crate::credentials::ChannelCredentials;
+    use crate::resolver;
 
-    use super::{Channel, ChannelBuilder, Options};
+    use super::{Channel, ChannelBuilder, Connector, Options};
 
     const OPT_SSL_TARGET_NAME_OVERRIDE: &[u8] = b"grpc.ssl_target_name_override\0";
 
@@ -503,7 +676,22 @@ mod secure_channel {
         }
 
         /// Build a secure [`Channel`] that connects to a specific address.
+        ///
+        /// If a resolver is registered for the scheme of `addr`, addresses are
+        /// resolved by the resolver.
         pub fn secure_connect(mut self, addr: &str, mut creds: ChannelCredentials) -> Channel {
+            if let Some((factory, endpoint)) = self.resolver_factory(addr) {
+                // Certificates are checked against the host of the endpoint
+                // instead of the resolved addresses.
+                let host = resolver::host_of(endpoint);
+                if !host.is_empty() {
+                    self.options
+                        .entry(Cow::Borrowed(OPT_SSL_TARGET_NAME_OVERRIDE))
+                        .or_insert_with(|| Options::String(CString::new(host).unwrap()));
+                }
+                let connector = Connector::Secure(creds);
+                return self.connect_resolved(addr, factory, endpoint, connector);
+            }
             let args = self.prepare_connect_args();
             let addr = CString::new(addr).unwrap();
             let addr_ptr = addr.as_ptr();
@@ -516,7 +704,13 @@ mod secure_channel {
                 )
             };
 
-            Channel::new(self.env.pick_cq(), self.env, channel)
+            Channel::new(
+                self.env.pick_cq(),
+                self.env,
+                channel,
+                self.interceptors,
+                self.retry,
+            )
         }
     }
 }
@@ -559,6 +753,162 @@ impl Drop for ChannelInner {
     }
 }
 
+/// Create a channel that fails all calls with the given status.
+fn lame_channel(target: &CStr, code: RpcStatusCode, msg: &str) -> *mut grpc_channel {
+    let msg = CString::new(msg).unwrap();
+    unsafe { grpc_sys::grpc_lame_client_channel_create(target.as_ptr(), code.into(), msg.as_ptr()) }
+}
+
+/// Creates the underlying channels of a [`ResolvedChannel`].
+enum Connector {
+    Insecure,
+    #[cfg(feature = "secure")]
+    Secure(crate::credentials::ChannelCredentials),
+}
+
+impl Connector {
+    fn connect(&mut self, target: &CStr, args: &ChannelArgs) -> *mut grpc_channel {
+        match *self {
+            Connector::Insecure => unsafe {
+                grpc_sys::grpc_insecure_channel_create(target.as_ptr(), args.args, ptr::null_mut())
+            },
+            #[cfg(feature = "secure")]
+            Connector::Secure(ref mut creds) => unsafe {
+                grpc_sys::grpc_secure_channel_create(
+                    creds.as_mut_ptr(),
+                    target.as_ptr(),
+                    args.args,
+                    ptr::null_mut(),
+                )
+            },
+        }
+    }
+}
+
+struct ResolveState {
+    builder: ChannelBuilder,
+    connector: Connector,
+    target: CString,
+    /// The service config used when the resolver doesn't provide one.
+    default_config: Option<CString>,
+    /// Whether retries are enabled when the resolver doesn't provide a config.
+    default_retries: bool,
+    /// The addresses, service config and retries flag of the current channel,
+    /// `None` if calls are failed by a lame channel.
+    last: Option<(CString, Option<CString>, bool)>,
+}
+
+/// A channel whose addresses are resolved by a [`Resolver`].
+///
+/// It's not a resolver plugged into gRPC core: core gets a list of addresses,
+/// and a new underlying channel is created whenever the addresses or the
+/// service config change, which drops existing connections and the states of
+/// load balancing. Re-resolution is only requested when the channel is found
+/// in `TRANSIENT_FAILURE`.
+pub(crate) struct ResolvedChannel {
+    current: Mutex<Arc<ChannelInner>>,
+    state: Mutex<ResolveState>,
+    resolver: Mutex<Option<Box<dyn Resolver>>>,
+    reresolution_requested: AtomicBool,
+}
+
+unsafe impl Send for ResolvedChannel {}
+unsafe impl Sync for ResolvedChannel {}
+
+impl ResolvedChannel {
+    fn current(&self) -> Arc<ChannelInner> {
+        let current = self.current.lock().unwrap().clone();
+        let state = current.check_connectivity_state(false);
+        if state != ConnectivityState::GRPC_CHANNEL_TRANSIENT_FAILURE {
+            self.reresolution_requested.store(false, Ordering::SeqCst);
+        } else if !self.reresolution_requested.swap(true, Ordering::SeqCst) {
+            if let Some(ref r) = *self.resolver.lock().unwrap() {
+                r.request_reresolution();
+            }
+        }
+        current
+    }
+
+    fn replace(&self, state: &ResolveState, channel: *mut grpc_channel) {
+        let inner = Arc::new(ChannelInner {
+            _env: state.builder.env.clone(),
+            channel,
+        });
+        *self.current.lock().unwrap() = inner;
+        self.reresolution_requested.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn update(&self, resolution: Resolution) {
+        let mut state = self.state.lock().unwrap();
+        if resolution.addresses().is_empty() {
+            let channel = lame_channel(
+                &state.target,
+                RpcStatusCode::UNAVAILABLE,
+                "no address is resolved",
+            );
+            state.last = None;
+            self.replace(&state, channel);
+            return;
+        }
+
+        let (config, retries) = match resolution.get_service_config() {
+            Some(config) => (
+                Some(CString::new(config.as_json()).unwrap()),
+                config.enable_retries(),
+            ),
+            None => (state.default_config.clone(), state.default_retries),
+        };
+        let target = CString::new(resolver::format_addresses(resolution.addresses())).unwrap();
+        if let Some((ref t, ref c, r)) = state.last {
+            if *t == target && *c == config && r == retries {
+                // Nothing is changed, keep the connections.
+                return;
+            }
+        }
+        state.last = Some((target.clone(), config.clone(), retries));
+
+        let options = &mut state.builder.options;
+        let key: Cow<'static, [u8]> = Cow::Borrowed(grpc_sys::GRPC_ARG_SERVICE_CONFIG);
+        match config {
+            Some(config) => options.insert(key, Options::String(config)),
+            None => options.remove(&key),
+        };
+        let key: Cow<'static, [u8]> = Cow::Borrowed(grpc_sys::GRPC_ARG_ENABLE_RETRIES);
+        if retries {
+            options.insert(key, Options::Integer(1));
+        } else {
+            options.remove(&key);
+        }
+        let args = state.builder.build_args();
+        let channel = state.connector.connect(&target, &args);
+        self.replace(&state, channel);
+    }
+
+    pub(crate) fn fail(&self, status: RpcStatus) {
+        let mut state = self.state.lock().unwrap();
+        state.last = None;
+        let msg = status.details.as_ref().map_or("", |s| s.as_str());
+        let channel = lame_channel(&state.target, status.status, msg);
+        self.replace(&state, channel);
+    }
+}
+
+/// The underlying channel of a [`Channel`].
+#[derive(Clone)]
+enum RawChannel {
+    Fixed(Arc<ChannelInner>),
+    Resolved(Arc<ResolvedChannel>),
+}
+
+impl RawChannel {
+    fn get(&self) -> Arc<ChannelInner> {
+        match *self {
+            RawChannel::Fixed(ref inner) => inner.clone(),
+            RawChannel::Resolved(ref resolved) => resolved.current(),
+        }
+    }
+}
+
 /// A gRPC channel.
 ///
 /// Channels are an abstraction of long-lived connections to remote servers. More client objects
@@ -567,32 +917,110 @@ impl Drop for ChannelInner {
 /// Use [`ChannelBuilder`] to build a [`Channel`].
 #[derive(Clone)]
 pub struct Channel {
-    inner: Arc<ChannelInner>,
+    inner: RawChannel,
     cq: CompletionQueue,
+    interceptors: Vec<Arc<dyn ClientInterceptor>>,
+    retry: RetryConfig,
 }
 
 unsafe impl Send for Channel {}
 unsafe impl Sync for Channel {}
 
 impl Channel {
-    fn new(cq: CompletionQueue, env: Arc<Environment>, channel: *mut grpc_channel) -> Channel {
+    fn new(
+        cq: CompletionQueue,
+        env: Arc<Environment>,
+        channel: *mut grpc_channel,
+        interceptors: Vec<Arc<dyn ClientInterceptor>>,
+        retry: RetryConfig,
+    ) -> Channel {
         Channel {
-            inner: Arc::new(ChannelInner { _env: env, channel }),
+            inner: RawChannel::Fixed(Arc::new(ChannelInner { _env: env, channel })),
             cq,
+            interceptors,
+            retry,
         }
     }
 
     // If try_to_connect is true, the channel will try to establish a connection, potentially
     // changing the state.
     pub fn check_connectivity_state(&self, try_to_connect: bool) -> ConnectivityState {
-        self.inner.check_connectivity_state(try_to_connect)
+        self.inner.get().check_connectivity_state(try_to_connect)
+    }
+
+    fn watch_connectivity_state(
+        &self,
+        last_observed: ConnectivityState,
+        deadline: gpr_timespec,
+    ) -> StateChangeFuture {
+        // The completion queue is shutdown if it can't be borrowed, in which
+        // case the state will never change.
+        let cq_f = self.cq.borrow().ok().map(|cq_ref| {
+            let (cq_f, prom) = CallTag::action_pair();
+            let tag = Box::into_raw(Box::new(prom));
+            let inner = self.inner.get();
+            unsafe {
+                grpc_sys::grpc_channel_watch_connectivity_state(
+                    inner.channel,
+                    last_observed,
+                    deadline,
+                    cq_ref.as_ptr(),
+                    tag as *mut _,
+                )
+            }
+            cq_f
+        });
+        StateChangeFuture { cq_f }
+    }
+
+    /// Wait for the connectivity state of the channel to change from `last_observed`.
+    ///
+    /// The future resolves to `true` if the state changes before `deadline`,
+    /// or `false` otherwise.
+    pub fn wait_for_state_change(
+        &self,
+        last_observed: ConnectivityState,
+        deadline: Instant,
+    ) -> StateChangeFuture {
+        let now = Instant::now();
+        let timeout = if deadline > now {
+            deadline - now
+        } else {
+            Duration::from_secs(0)
+        };
+        self.watch_connectivity_state(last_observed, timeout.into())
+    }
+
+    /// Wait for the channel to be connected, trying to connect if it's idle.
+    ///
+    /// The future resolves to `true` if the channel is ready before `timeout`,
+    /// or `false` otherwise.
+    pub fn wait_for_connected(&self, timeout: Duration) -> ConnectedFuture {
+        ConnectedFuture {
+            channel: self.clone(),
+            deadline: Instant::now() + timeout,
+            watch: None,
+        }
+    }
+
+    /// Get a stream of the connectivity states of the channel.
+    ///
+    /// The current state is yielded first, followed by every state the
+    /// channel transits to. The stream ends after the channel is shutdown.
+    pub fn connectivity_state_stream(&self) -> ConnectivityStateStream {
+        ConnectivityStateStream {
+            channel: self.clone(),
+            last: None,
+            watch: None,
+        }
     }
 
     /// Create a Kicker.
     pub(crate) fn create_kicker(&self) -> Result<Kicker> {
         let cq_ref = self.cq.borrow()?;
+        let inner = self.inner.get();
         let raw_call = unsafe {
-            let ch = self.inner.channel;
+            let ch = inner.channel;
             let cq = cq_ref.as_ptr();
             // Do not timeout.
             let timeout = gpr_timespec::inf_future();
@@ -613,17 +1041,14 @@ impl Channel {
     }
 
     /// Create a call using the method and option.
-    pub(crate) fn create_call<Req, Resp>(
-        &self,
-        method: &Method<Req, Resp>,
-        opt: &CallOption,
-    ) -> Result<Call> {
+    pub(crate) fn create_call(&self, method: &str, opt: &CallOption) -> Result<Call> {
         let cq_ref = self.cq.borrow()?;
+        let inner = self.inner.get();
         let raw_call = unsafe {
-            let ch = self.inner.channel;
+            let ch = inner.channel;
             let cq = cq_ref.as_ptr();
-            let method_ptr = method.name.as_ptr();
-            let method_len = method.name.len();
+            let method_ptr = method.as_ptr();
+            let method_len = method.len();
             let timeout = opt
                 .get_timeout()
                 .map_or_else(gpr_timespec::inf_future, gpr_timespec::from);
@@ -640,10 +1065,118 @@ impl Channel {
             )
         };
 
-        unsafe { Ok(Call::from_raw(raw_call, self.cq.clone())) }
+        let call = unsafe { Call::from_raw(raw_call, self.cq.clone()) };
+        #[cfg(feature = "secure")]
+        {
+            if let Some(creds) = opt.get_call_credentials() {
+                let code =
+                    unsafe { grpc_sys::grpc_call_set_credentials(call.call, creds.as_ptr()) };
+                if code != grpc_sys::grpc_call_error::GRPC_CALL_OK {
+                    return Err(Error::CallFailure(code));
+                }
+            }
+        }
+        Ok(call)
     }
 
     pub(crate) fn cq(&self) -> &CompletionQueue {
         &self.cq
     }
+
+    pub(crate) fn interceptors(&self) -> &[Arc<dyn ClientInterceptor>] {
+        &self.interceptors
+    }
+
+    pub(crate) fn retry_config(&self) -> &RetryConfig {
+        &self.retry
+    }
+}
+
+/// A future that resolves when the connectivity state of a channel changes.
+///
+/// It resolves to `false` if the deadline is exceeded before the state changes.
+#[must_use = "if unused the StateChangeFuture may immediately cancel"]
+pub struct StateChangeFuture {
+    cq_f: Option<CqFuture<bool>>,
+}
+
+impl Future for StateChangeFuture {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<bool, Error> {
+        match self.cq_f {
+            Some(ref mut f) => f.poll(),
+            None => Ok(Async::Ready(false)),
+        }
+    }
+}
+
+/// A future that resolves when a channel is connected.
+///
+/// It resolves to `false` if the channel is shutdown or the timeout is
+/// exceeded before the channel is ready.
+#[must_use = "if unused the ConnectedFuture may immediately cancel"]
+pub struct ConnectedFuture {
+    channel: Channel,
+    deadline: Instant,
+    watch: Option<StateChangeFuture>,
+}
+
+impl Future for ConnectedFuture {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<bool, Error> {
+        loop {
+            if let Some(ref mut watch) = self.watch {
+                if !try_ready!(watch.poll()) {
+                    return Ok(Async::Ready(false));
+                }
+            }
+            let state = self.channel.check_connectivity_state(true);
+            match state {
+                ConnectivityState::GRPC_CHANNEL_READY => return Ok(Async::Ready(true)),
+                ConnectivityState::GRPC_CHANNEL_SHUTDOWN => return Ok(Async::Ready(false)),
+                _ => {}
+            }
+            self.watch = Some(self.channel.wait_for_state_change(state, self.deadline));
+        }
+    }
+}
+
+/// A stream of the connectivity states of a channel.
+#[must_use = "streams do nothing unless polled"]
+pub struct ConnectivityStateStream {
+    channel: Channel,
+    last: Option<ConnectivityState>,
+    watch: Option<StateChangeFuture>,
+}
+
+impl Stream for ConnectivityStateStream {
+    type Item = ConnectivityState;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<ConnectivityState>, Error> {
+        loop {
+            if self.last == Some(ConnectivityState::GRPC_CHANNEL_SHUTDOWN) {
+                return Ok(Async::Ready(None));
+            }
+            if let Some(ref mut watch) = self.watch {
+                if !try_ready!(watch.poll()) {
+                    // The completion queue is shutdown.
+                    return Ok(Async::Ready(None));
+                }
+            }
+            let state = self.channel.check_connectivity_state(false);
+            self.watch = Some(
+                self.channel
+                    .watch_connectivity_state(state, gpr_timespec::inf_future()),
+            );
+            if self.last != Some(state) {
+                self.last = Some(state);
+                return Ok(Async::Ready(Some(state)));
+            }
+        }
+    }
 }
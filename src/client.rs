@@ -1,17 +1,99 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+use std::sync::Arc;
+
 use futures::Future;
 
 use crate::call::client::{
     CallOption, ClientCStreamReceiver, ClientCStreamSender, ClientDuplexReceiver,
     ClientDuplexSender, ClientSStreamReceiver, ClientUnaryReceiver,
 };
-use crate::call::{Call, Method};
+use crate::call::{Call, Method, RpcStatus, RpcStatusCode};
 use crate::channel::Channel;
+use crate::codec::raw_codec;
 use crate::task::Executor;
 use crate::task::Kicker;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+
+/// A hook that is invoked around every call made by a [`Client`].
+///
+/// Interceptors can be registered on a [`ChannelBuilder`] so that all clients
+/// built on top of the channel, including generated ones, pick them up, or on
+/// a single [`Client`] via [`Client::add_interceptor`].
+///
+/// [`ChannelBuilder`]: ./struct.ChannelBuilder.html
+pub trait ClientInterceptor: Send + Sync {
+    /// Invoked before the call is started.
+    ///
+    /// The interceptor can modify the option of the call, for example to inject headers
+    /// or adjust the timeout. Returning an error fails the call with the given status
+    /// without sending anything to the server.
+    fn before_call(
+        &self,
+        _method: &str,
+        _opt: &mut CallOption,
+    ) -> std::result::Result<(), RpcStatus> {
+        Ok(())
+    }
+
+    /// Invoked once the call finishes.
+    ///
+    /// It's invoked exactly once for every started call. Failures that happen
+    /// locally are reported with a status describing the error, for example
+    /// `INTERNAL` if the response can't be decoded, and calls that are dropped
+    /// before they finish are reported as `CANCELLED`.
+    fn on_finish(&self, _method: &str, _status: &RpcStatus) {}
+}
+
+/// Reports the final status of a call to interceptors.
+///
+/// If it's dropped before any status is reported, the call is reported
+/// as cancelled.
+pub(crate) struct CallObserver {
+    method: String,
+    interceptors: Vec<Arc<dyn ClientInterceptor>>,
+    notified: bool,
+}
+
+impl CallObserver {
+    pub fn on_finish(mut self, status: &RpcStatus) {
+        self.notify(status)
+    }
+
+    /// Report the status of a call that fails with `err`.
+    pub fn on_error(self, err: &Error) {
+        let (code, details) = match *err {
+            Error::RpcFailure(ref status) => return self.on_finish(status),
+            Error::RpcFinished(Some(ref status)) => return self.on_finish(status),
+            Error::Codec(_) => (RpcStatusCode::INTERNAL, err.to_string()),
+            Error::RemoteStopped | Error::QueueShutdown => {
+                (RpcStatusCode::UNAVAILABLE, err.to_string())
+            }
+            _ => (RpcStatusCode::UNKNOWN, err.to_string()),
+        };
+        self.on_finish(&RpcStatus::new(code, Some(details)))
+    }
+
+    fn notify(&mut self, status: &RpcStatus) {
+        self.notified = true;
+        for interceptor in &self.interceptors {
+            interceptor.on_finish(&self.method, status);
+        }
+    }
+}
+
+impl Drop for CallObserver {
+    fn drop(&mut self) {
+        if !self.notified {
+            let status = RpcStatus::new(
+                RpcStatusCode::CANCELLED,
+                Some("call is dropped before it finishes".to_owned()),
+            );
+            self.notify(&status);
+        }
+    }
+}
 
 /// A generic client for making RPC calls.
 #[derive(Clone)]
@@ -19,13 +101,50 @@ pub struct Client {
     channel: Channel,
     // Used to kick its completion queue.
     kicker: Kicker,
+    interceptors: Vec<Arc<dyn ClientInterceptor>>,
 }
 
 impl Client {
     /// Initialize a new [`Client`].
+    ///
+    /// Interceptors registered on the channel are applied to all calls made by the client.
     pub fn new(channel: Channel) -> Client {
         let kicker = channel.create_kicker().unwrap();
-        Client { channel, kicker }
+        let interceptors = channel.interceptors().to_vec();
+        Client {
+            channel,
+            kicker,
+            interceptors,
+        }
+    }
+
+    /// Add an interceptor that is invoked for all calls made by this client.
+    ///
+    /// Interceptors are invoked in the order they are added, after the ones
+    /// registered on the channel.
+    pub fn add_interceptor<I>(mut self, interceptor: I) -> Client
+    where
+        I: ClientInterceptor + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Run the interceptors before starting a call.
+    fn intercept(&self, method: &str, opt: &mut CallOption) -> Result<Option<CallObserver>> {
+        if self.interceptors.is_empty() {
+            return Ok(None);
+        }
+        for interceptor in &self.interceptors {
+            if let Err(status) = interceptor.before_call(method, opt) {
+                return Err(Error::RpcFailure(status));
+            }
+        }
+        Ok(Some(CallObserver {
+            method: method.to_owned(),
+            interceptors: self.interceptors.clone(),
+            notified: false,
+        }))
     }
 
     /// Create a synchronized unary RPC call.
@@ -44,9 +163,10 @@ impl Client {
         &self,
         method: &Method<Req, Resp>,
         req: &Req,
-        opt: CallOption,
+        mut opt: CallOption,
     ) -> Result<ClientUnaryReceiver<Resp>> {
-        Call::unary_async(&self.channel, method, req, opt)
+        let observer = self.intercept(method.name, &mut opt)?;
+        Call::unary_async(&self.channel, method, req, opt, observer)
     }
 
     /// Create an asynchronized client streaming call.
@@ -55,9 +175,10 @@ impl Client {
     pub fn client_streaming<Req, Resp>(
         &self,
         method: &Method<Req, Resp>,
-        opt: CallOption,
+        mut opt: CallOption,
     ) -> Result<(ClientCStreamSender<Req>, ClientCStreamReceiver<Resp>)> {
-        Call::client_streaming(&self.channel, method, opt)
+        let observer = self.intercept(method.name, &mut opt)?;
+        Call::client_streaming(&self.channel, method, opt, observer)
     }
 
     /// Create an asynchronized server streaming call.
@@ -67,9 +188,10 @@ impl Client {
         &self,
         method: &Method<Req, Resp>,
         req: &Req,
-        opt: CallOption,
+        mut opt: CallOption,
     ) -> Result<ClientSStreamReceiver<Resp>> {
-        Call::server_streaming(&self.channel, method, req, opt)
+        let observer = self.intercept(method.name, &mut opt)?;
+        Call::server_streaming(&self.channel, method, req, opt, observer)
     }
 
     /// Create an asynchronized duplex streaming call.
@@ -80,9 +202,52 @@ impl Client {
     pub fn duplex_streaming<Req, Resp>(
         &self,
         method: &Method<Req, Resp>,
-        opt: CallOption,
+        mut opt: CallOption,
     ) -> Result<(ClientDuplexSender<Req>, ClientDuplexReceiver<Resp>)> {
-        Call::duplex_streaming(&self.channel, method, opt)
+        let observer = self.intercept(method.name, &mut opt)?;
+        Call::duplex_streaming(&self.channel, method, opt, observer)
+    }
+
+    /// Create a synchronized unary RPC call with a serialized request.
+    ///
+    /// `path` is the full path of the method, e.g. `/helloworld.Greeter/SayHello`,
+    /// and the response is returned as it's received.
+    pub fn unary_call_raw(&self, path: &str, req: Vec<u8>, opt: CallOption) -> Result<Vec<u8>> {
+        let f = self.unary_call_raw_async(path, req, opt)?;
+        f.wait()
+    }
+
+    /// Create an asynchronized unary RPC call with a serialized request.
+    pub fn unary_call_raw_async(
+        &self,
+        path: &str,
+        req: Vec<u8>,
+        mut opt: CallOption,
+    ) -> Result<ClientUnaryReceiver<Vec<u8>>> {
+        let observer = self.intercept(path, &mut opt)?;
+        Call::unary_async_by_path(&self.channel, path, req, raw_codec::de, opt, observer)
+    }
+
+    /// Create an asynchronized duplex streaming call with serialized messages.
+    ///
+    /// All kinds of methods can be called this way, as they are the same on the
+    /// wire. For example, a server streaming method expects exactly one request
+    /// before the request stream is closed.
+    #[allow(clippy::type_complexity)]
+    pub fn duplex_streaming_raw(
+        &self,
+        path: &str,
+        mut opt: CallOption,
+    ) -> Result<(ClientDuplexSender<Vec<u8>>, ClientDuplexReceiver<Vec<u8>>)> {
+        let observer = self.intercept(path, &mut opt)?;
+        Call::duplex_streaming_by_path(
+            &self.channel,
+            path,
+            raw_codec::ser,
+            raw_codec::de,
+            opt,
+            observer,
+        )
     }
 
     /// Spawn the future into current gRPC poll thread.
@@ -96,4 +261,15 @@ impl Client {
         let kicker = self.kicker.clone();
         Executor::new(self.channel.cq()).spawn(f, kicker)
     }
+
+    /// Spawn the std future into current gRPC poll thread.
+    ///
+    /// It works the same as [`spawn`](#method.spawn).
+    #[cfg(feature = "std-future")]
+    pub fn spawn_std<F>(&self, f: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.spawn(crate::std_future::into_future_01(f))
+    }
 }
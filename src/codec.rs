@@ -24,6 +24,32 @@ pub struct Marshaller<T> {
     pub de: DeserializeFn<T>,
 }
 
+/// Passes messages through as raw bytes.
+///
+/// It's used by calls and handlers that don't know the types of messages,
+/// for example proxies.
+pub mod raw_codec {
+    use std::io::Read;
+
+    use super::MessageReader;
+    use crate::error::{Error, Result};
+
+    #[inline]
+    #[allow(clippy::ptr_arg)]
+    pub fn ser(t: &Vec<u8>, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(t)
+    }
+
+    #[inline]
+    pub fn de(mut reader: MessageReader) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        reader
+            .read_to_end(&mut buf)
+            .map_err(|e| Error::Codec(Box::new(e)))?;
+        Ok(buf)
+    }
+}
+
 #[cfg(feature = "protobuf-codec")]
 pub mod pb_codec {
     use protobuf::{CodedInputStream, Message};
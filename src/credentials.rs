@@ -1,11 +1,23 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::ffi::CString;
+use std::error::Error as StdError;
+use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
+use crate::call::{RpcStatus, RpcStatusCode};
 use crate::error::{Error, Result};
-use crate::grpc_sys::{self, grpc_channel_credentials, grpc_server_credentials};
-use libc::c_char;
+use crate::grpc_sys::{
+    self, grpc_auth_metadata_context, grpc_call_credentials, grpc_channel_credentials,
+    grpc_credentials_plugin_metadata_cb, grpc_local_connect_type, grpc_metadata,
+    grpc_metadata_credentials_plugin, grpc_server_credentials,
+    grpc_ssl_certificate_config_reload_status, grpc_ssl_client_certificate_request_type,
+    grpc_ssl_pem_key_cert_pair, grpc_ssl_server_certificate_config, grpc_status_code,
+};
+use crate::metadata::Metadata;
+use libc::{c_char, c_int, c_void};
 
 fn clear_key_securely(key: &mut [u8]) {
     unsafe {
@@ -21,6 +33,7 @@ pub struct ServerCredentialsBuilder {
     cert_chains: Vec<*mut c_char>,
     private_keys: Vec<*mut c_char>,
     force_client_auth: bool,
+    request_type: Option<CertificateRequestType>,
 }
 
 impl ServerCredentialsBuilder {
@@ -31,11 +44,15 @@ impl ServerCredentialsBuilder {
             cert_chains: vec![],
             private_keys: vec![],
             force_client_auth: false,
+            request_type: None,
         }
     }
 
     /// Set the PEM encoded client root certificate to verify client's identity. If
     /// `force_client_auth` is set to `true`, the authenticity of client check will be enforced.
+    ///
+    /// `force_client_auth` is ignored if the type is set by
+    /// [`client_certificate_request`](#method.client_certificate_request).
     pub fn root_cert<S: Into<Vec<u8>>>(
         mut self,
         cert: S,
@@ -46,7 +63,28 @@ impl ServerCredentialsBuilder {
         self
     }
 
+    /// Set whether client certificates are requested, and whether they are
+    /// verified against the root certificate.
+    ///
+    /// By default, client certificates are required and verified if
+    /// `force_client_auth` is passed to [`root_cert`](#method.root_cert),
+    /// and not requested otherwise.
+    pub fn client_certificate_request(
+        mut self,
+        request_type: CertificateRequestType,
+    ) -> ServerCredentialsBuilder {
+        self.request_type = Some(request_type);
+        self
+    }
+
     /// Add a PEM encoded server side certificate and key.
+    ///
+    /// It can be called multiple times to serve several hostnames on the same
+    /// port. During the handshake, the certificate is selected by matching the
+    /// server name indicated by the client (SNI) against the common name and
+    /// subject alternative names of every certificate, wildcards included. The
+    /// first certificate added is served if nothing matches or the client
+    /// doesn't indicate a server name, e.g. when it targets an IP address.
     pub fn add_cert(mut self, cert: Vec<u8>, mut private_key: Vec<u8>) -> ServerCredentialsBuilder {
         if private_key.capacity() == private_key.len() {
             let mut nil_key = Vec::with_capacity(private_key.len() + 1);
@@ -62,32 +100,48 @@ impl ServerCredentialsBuilder {
     }
 
     /// Finalize the [`ServerCredentialsBuilder`] and build the [`ServerCredentials`].
-    pub fn build(mut self) -> ServerCredentials {
-        let root_cert = self
-            .root
-            .take()
-            .map_or_else(ptr::null_mut, CString::into_raw);
-        let cert_chains = self.cert_chains.as_mut_ptr();
-        let private_keys = self.private_keys.as_mut_ptr();
-        let force_auth = if self.force_client_auth { 1 } else { 0 };
+    pub fn build(self) -> ServerCredentials {
+        let request_type = self.request_type.unwrap_or_else(|| {
+            if self.root.is_some() && self.force_client_auth {
+                CertificateRequestType::RequestAndRequireClientCertificateAndVerify
+            } else {
+                CertificateRequestType::DontRequestClientCertificate
+            }
+        });
 
         let credentials = unsafe {
-            grpc_sys::grpcwrap_ssl_server_credentials_create(
-                root_cert,
-                cert_chains as _,
-                private_keys as _,
-                self.cert_chains.len(),
-                force_auth,
-            )
+            let opts = grpc_sys::grpc_ssl_server_credentials_create_options_using_config(
+                request_type.to_raw(),
+                self.build_config(),
+            );
+            grpc_sys::grpc_ssl_server_credentials_create_with_options(opts)
         };
 
-        if !root_cert.is_null() {
-            unsafe {
-                CString::from_raw(root_cert);
-            }
+        ServerCredentials {
+            creds: credentials,
+            _fetcher: None,
         }
+    }
 
-        ServerCredentials { creds: credentials }
+    fn build_config(&self) -> *mut grpc_ssl_server_certificate_config {
+        let root_cert = self.root.as_ref().map_or_else(ptr::null, |c| c.as_ptr());
+        let pairs: Vec<_> = self
+            .cert_chains
+            .iter()
+            .zip(&self.private_keys)
+            .map(|(cert, key)| grpc_ssl_pem_key_cert_pair {
+                private_key: *key,
+                cert_chain: *cert,
+            })
+            .collect();
+        // All strings are copied by gRPC core.
+        unsafe {
+            grpc_sys::grpc_ssl_server_certificate_config_create(
+                root_cert,
+                pairs.as_ptr(),
+                pairs.len(),
+            )
+        }
     }
 }
 
@@ -105,14 +159,160 @@ impl Drop for ServerCredentialsBuilder {
     }
 }
 
-/// Server-side SSL credentials.
+/// Determines whether a server requests certificates from clients, and
+/// whether the certificates are verified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertificateRequestType {
+    /// Client certificates are neither requested nor checked.
+    DontRequestClientCertificate,
+    /// Client certificates are requested but not required or verified.
+    ///
+    /// Certificates presented by clients are available in the
+    /// [`AuthContext`](./struct.AuthContext.html) of calls.
+    RequestClientCertificateButDontVerify,
+    /// Client certificates are requested but not required. Certificates
+    /// presented by clients are verified.
+    RequestClientCertificateAndVerify,
+    /// Client certificates are required but not verified.
+    RequestAndRequireClientCertificateButDontVerify,
+    /// Client certificates are required and verified.
+    RequestAndRequireClientCertificateAndVerify,
+}
+
+impl CertificateRequestType {
+    fn to_raw(self) -> grpc_ssl_client_certificate_request_type {
+        use self::grpc_ssl_client_certificate_request_type::*;
+        match self {
+            CertificateRequestType::DontRequestClientCertificate => {
+                GRPC_SSL_DONT_REQUEST_CLIENT_CERTIFICATE
+            }
+            CertificateRequestType::RequestClientCertificateButDontVerify => {
+                GRPC_SSL_REQUEST_CLIENT_CERTIFICATE_BUT_DONT_VERIFY
+            }
+            CertificateRequestType::RequestClientCertificateAndVerify => {
+                GRPC_SSL_REQUEST_CLIENT_CERTIFICATE_AND_VERIFY
+            }
+            CertificateRequestType::RequestAndRequireClientCertificateButDontVerify => {
+                GRPC_SSL_REQUEST_AND_REQUIRE_CLIENT_CERTIFICATE_BUT_DONT_VERIFY
+            }
+            CertificateRequestType::RequestAndRequireClientCertificateAndVerify => {
+                GRPC_SSL_REQUEST_AND_REQUIRE_CLIENT_CERTIFICATE_AND_VERIFY
+            }
+        }
+    }
+}
+
+/// Provides certificates of a server that can be changed at runtime.
+pub trait ServerCredentialsFetcher: Send + Sync {
+    /// Retrieve the latest certificates, keys and client root certificate.
+    ///
+    /// It's called when the server starts and before every handshake, so it
+    /// should return quickly. Returns `Ok(None)` if nothing has changed.
+    /// New certificates only apply to new connections, and gRPC keeps using
+    /// the previous ones if an error is returned. The client certificate
+    /// request type of the returned builder is ignored.
+    fn fetch(&self) -> std::result::Result<Option<ServerCredentialsBuilder>, Box<dyn StdError>>;
+}
+
+type BoxFetcher = Box<dyn ServerCredentialsFetcher>;
+
+extern "C" fn fetch_server_certificate_config(
+    user_data: *mut c_void,
+    config: *mut *mut grpc_ssl_server_certificate_config,
+) -> grpc_ssl_certificate_config_reload_status {
+    use self::grpc_ssl_certificate_config_reload_status::*;
+    let fetcher = unsafe { &*(user_data as *const BoxFetcher) };
+    // Panics must not unwind into gRPC core.
+    let res = match panic::catch_unwind(AssertUnwindSafe(|| fetcher.fetch())) {
+        Ok(res) => res,
+        Err(_) => {
+            error!("server credentials fetcher panicked");
+            return GRPC_SSL_CERTIFICATE_CONFIG_RELOAD_FAIL;
+        }
+    };
+    match res {
+        Ok(None) => GRPC_SSL_CERTIFICATE_CONFIG_RELOAD_UNCHANGED,
+        Ok(Some(ref builder)) if builder.cert_chains.is_empty() => {
+            error!("server credentials fetcher returned no certificate");
+            GRPC_SSL_CERTIFICATE_CONFIG_RELOAD_FAIL
+        }
+        Ok(Some(builder)) => {
+            unsafe { *config = builder.build_config() };
+            GRPC_SSL_CERTIFICATE_CONFIG_RELOAD_NEW
+        }
+        Err(e) => {
+            error!("failed to fetch server credentials: {}", e);
+            GRPC_SSL_CERTIFICATE_CONFIG_RELOAD_FAIL
+        }
+    }
+}
+
+/// The type of connections that local credentials apply to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalConnectType {
+    /// Unix domain sockets.
+    Uds,
+    /// TCP connections over the loopback interface.
+    LocalTcp,
+}
+
+impl LocalConnectType {
+    fn to_raw(self) -> grpc_local_connect_type {
+        match self {
+            LocalConnectType::Uds => grpc_local_connect_type::UDS,
+            LocalConnectType::LocalTcp => grpc_local_connect_type::LOCAL_TCP,
+        }
+    }
+}
+
+/// Server-side credentials.
 ///
 /// Use [`ServerCredentialsBuilder`] to build a [`ServerCredentials`].
 pub struct ServerCredentials {
     creds: *mut grpc_server_credentials,
+    // gRPC core holds a pointer to the fetcher, so it's boxed twice.
+    _fetcher: Option<Box<BoxFetcher>>,
 }
 
 impl ServerCredentials {
+    /// Create credentials that only accept connections of `connect_type`
+    /// from the same host, without encrypting the traffic.
+    ///
+    /// The transport security type in the
+    /// [`AuthContext`](./struct.AuthContext.html) of calls is `local`.
+    pub fn local(connect_type: LocalConnectType) -> ServerCredentials {
+        let creds =
+            unsafe { grpc_sys::grpc_local_server_credentials_create(connect_type.to_raw()) };
+        ServerCredentials {
+            creds,
+            _fetcher: None,
+        }
+    }
+
+    /// Create credentials whose certificates are provided by `fetcher`.
+    ///
+    /// Certificates can be rotated without restarting the server or
+    /// dropping existing connections. Binding fails if the first fetch
+    /// doesn't return any certificate.
+    pub fn with_fetcher<F: ServerCredentialsFetcher + 'static>(
+        fetcher: F,
+        request_type: CertificateRequestType,
+    ) -> ServerCredentials {
+        let fetcher: Box<BoxFetcher> = Box::new(Box::new(fetcher));
+        let creds = unsafe {
+            let opts = grpc_sys::grpc_ssl_server_credentials_create_options_using_config_fetcher(
+                request_type.to_raw(),
+                Some(fetch_server_certificate_config),
+                &*fetcher as *const BoxFetcher as *mut c_void,
+            );
+            grpc_sys::grpc_ssl_server_credentials_create_with_options(opts)
+        };
+        ServerCredentials {
+            creds,
+            _fetcher: Some(fetcher),
+        }
+    }
+
     pub fn as_mut_ptr(&mut self) -> *mut grpc_server_credentials {
         self.creds
     }
@@ -200,7 +400,7 @@ impl Drop for ChannelCredentialsBuilder {
     }
 }
 
-/// Client-side SSL credentials.
+/// Client-side credentials.
 ///
 /// Use [`ChannelCredentialsBuilder`] or [`ChannelCredentials::google_default_credentials`] to
 /// build a [`ChannelCredentials`].
@@ -213,6 +413,15 @@ impl ChannelCredentials {
         self.creds
     }
 
+    /// Build a [`ChannelCredentials`] that connects to servers on the same host
+    /// through connections of `connect_type`, without encrypting the traffic.
+    ///
+    /// Calls fail with `UNAVAILABLE` if the target is not local.
+    pub fn local(connect_type: LocalConnectType) -> ChannelCredentials {
+        let creds = unsafe { grpc_sys::grpc_local_credentials_create(connect_type.to_raw()) };
+        ChannelCredentials { creds }
+    }
+
     /// Try to build a [`ChannelCredentials`] to authenticate with Google OAuth credentials.
     pub fn google_default_credentials() -> Result<ChannelCredentials> {
         // Initialize the runtime here. Because this is an associated method
@@ -230,8 +439,298 @@ impl ChannelCredentials {
     }
 }
 
+impl ChannelCredentials {
+    /// Combine the credentials with call credentials, which are used by all
+    /// calls made on the channel.
+    pub fn compose(self, creds: CallCredentials) -> ChannelCredentials {
+        let creds = unsafe {
+            grpc_sys::grpc_composite_channel_credentials_create(
+                self.creds,
+                creds.as_ptr(),
+                ptr::null_mut(),
+            )
+        };
+        ChannelCredentials { creds }
+    }
+}
+
 impl Drop for ChannelCredentials {
     fn drop(&mut self) {
         unsafe { grpc_sys::grpc_channel_credentials_release(self.creds) }
     }
 }
+
+/// Context of the call that an [`AuthMetadataPlugin`] provides metadata for.
+#[derive(Debug)]
+pub struct AuthMetadataContext {
+    service_url: String,
+    method_name: String,
+}
+
+impl AuthMetadataContext {
+    /// The fully qualified url of the service, e.g. `https://host/helloworld.Greeter`.
+    pub fn service_url(&self) -> &str {
+        &self.service_url
+    }
+
+    /// The name of the method being called, e.g. `SayHello`.
+    pub fn method_name(&self) -> &str {
+        &self.method_name
+    }
+}
+
+/// Provides auth metadata, e.g. tokens, for calls.
+pub trait AuthMetadataPlugin: Send + Sync + 'static {
+    /// Provide the metadata of the call described by `ctx`.
+    ///
+    /// It's called for every call, so it must not block. `callback` can be
+    /// invoked before returning or later from any thread, e.g. after a token
+    /// is fetched asynchronously.
+    fn get_metadata(&self, ctx: AuthMetadataContext, callback: AuthMetadataCallback);
+}
+
+struct SyncResult {
+    returned: bool,
+    result: Option<std::result::Result<Metadata, RpcStatus>>,
+}
+
+/// A request for metadata made by gRPC core.
+struct PluginRequest {
+    cb: grpc_credentials_plugin_metadata_cb,
+    user_data: *mut c_void,
+    /// gRPC core doesn't allow `cb` to be invoked before `get_metadata`
+    /// returns, so results available by then are returned directly.
+    sync: Mutex<SyncResult>,
+}
+
+unsafe impl Send for PluginRequest {}
+unsafe impl Sync for PluginRequest {}
+
+impl PluginRequest {
+    fn invoke(&self, result: std::result::Result<Metadata, RpcStatus>) {
+        let cb = self.cb.unwrap();
+        match result {
+            Ok(meta) => unsafe {
+                let entries = meta.raw_entries();
+                cb(
+                    self.user_data,
+                    entries.as_ptr(),
+                    entries.len(),
+                    RpcStatusCode::OK.into(),
+                    ptr::null(),
+                )
+            },
+            Err(status) => {
+                let details = details_to_cstring(status.details);
+                unsafe {
+                    cb(
+                        self.user_data,
+                        ptr::null(),
+                        0,
+                        status.status.into(),
+                        details.as_ptr(),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Finishes a request made to an [`AuthMetadataPlugin`].
+///
+/// The call fails if the callback is dropped without being invoked.
+pub struct AuthMetadataCallback {
+    request: Option<Arc<PluginRequest>>,
+}
+
+impl AuthMetadataCallback {
+    fn finish(&mut self, result: std::result::Result<Metadata, RpcStatus>) {
+        let request = self.request.take().unwrap();
+        {
+            let mut sync = request.sync.lock().unwrap();
+            if !sync.returned {
+                sync.result = Some(result);
+                return;
+            }
+        }
+        request.invoke(result);
+    }
+
+    /// Attach `metadata` to the call.
+    pub fn success(mut self, metadata: Metadata) {
+        self.finish(Ok(metadata));
+    }
+
+    /// Fail the call with `status`.
+    pub fn fail(mut self, status: RpcStatus) {
+        self.finish(Err(status));
+    }
+}
+
+impl Drop for AuthMetadataCallback {
+    fn drop(&mut self) {
+        if self.request.is_some() {
+            let status = RpcStatus::new(
+                RpcStatusCode::INTERNAL,
+                Some("auth metadata callback is dropped".to_owned()),
+            );
+            self.finish(Err(status));
+        }
+    }
+}
+
+/// C strings can't contain NUL bytes, so they are stripped.
+fn details_to_cstring(details: Option<String>) -> CString {
+    let mut details = details.unwrap_or_default().into_bytes();
+    details.retain(|b| *b != 0);
+    CString::new(details).unwrap()
+}
+
+unsafe fn str_from_raw(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "C" fn plugin_get_metadata(
+    state: *mut c_void,
+    context: grpc_auth_metadata_context,
+    cb: grpc_credentials_plugin_metadata_cb,
+    user_data: *mut c_void,
+    creds_md: *mut grpc_metadata,
+    num_creds_md: *mut usize,
+    status: *mut grpc_status_code::Type,
+    error_details: *mut *const c_char,
+) -> c_int {
+    let plugin = unsafe { &*(state as *const Box<dyn AuthMetadataPlugin>) };
+    let ctx = unsafe {
+        AuthMetadataContext {
+            service_url: str_from_raw(context.service_url),
+            method_name: str_from_raw(context.method_name),
+        }
+    };
+    let request = Arc::new(PluginRequest {
+        cb,
+        user_data,
+        sync: Mutex::new(SyncResult {
+            returned: false,
+            result: None,
+        }),
+    });
+    let callback = AuthMetadataCallback {
+        request: Some(request.clone()),
+    };
+    // Panics must not unwind into gRPC core.
+    if panic::catch_unwind(AssertUnwindSafe(|| plugin.get_metadata(ctx, callback))).is_err() {
+        error!("auth metadata plugin panicked");
+    }
+
+    let result = {
+        let mut sync = request.sync.lock().unwrap();
+        sync.returned = true;
+        sync.result.take()
+    };
+    match result {
+        None => 0,
+        Some(Ok(ref meta))
+            if meta.len() > grpc_sys::GRPC_METADATA_CREDENTIALS_PLUGIN_SYNC_MAX as usize =>
+        {
+            let meta = meta.clone();
+            // `cb` can't be invoked before returning, so it's invoked by
+            // another thread.
+            let res = thread::Builder::new()
+                .name("grpc-auth-plugin".to_owned())
+                .spawn(move || request.invoke(Ok(meta)));
+            match res {
+                Ok(_) => 0,
+                Err(e) => unsafe {
+                    let details =
+                        details_to_cstring(Some(format!("failed to deliver auth metadata: {}", e)));
+                    *num_creds_md = 0;
+                    *status = RpcStatusCode::INTERNAL.into();
+                    *error_details = grpc_sys::gpr_strdup(details.as_ptr());
+                    1
+                },
+            }
+        }
+        Some(Ok(meta)) => unsafe {
+            // gRPC core takes the ownership of the returned entries.
+            for (i, entry) in meta.raw_entries().iter().enumerate() {
+                let mut md = *entry;
+                md.key = grpc_sys::grpc_slice_ref(entry.key);
+                md.value = grpc_sys::grpc_slice_ref(entry.value);
+                *creds_md.add(i) = md;
+            }
+            *num_creds_md = meta.len();
+            *status = RpcStatusCode::OK.into();
+            *error_details = ptr::null();
+            1
+        },
+        Some(Err(s)) => unsafe {
+            let details = details_to_cstring(s.details);
+            *num_creds_md = 0;
+            *status = s.status.into();
+            *error_details = grpc_sys::gpr_strdup(details.as_ptr());
+            1
+        },
+    }
+}
+
+extern "C" fn plugin_destroy(state: *mut c_void) {
+    unsafe { drop(Box::from_raw(state as *mut Box<dyn AuthMetadataPlugin>)) };
+}
+
+/// Credentials that are attached to calls, e.g. auth tokens.
+///
+/// They can be set for a call by [`CallOption::call_credentials`], or for all
+/// calls on a channel by [`ChannelCredentials::compose`].
+///
+/// [`CallOption::call_credentials`]: ./struct.CallOption.html#method.call_credentials
+pub struct CallCredentials {
+    creds: *mut grpc_call_credentials,
+}
+
+unsafe impl Send for CallCredentials {}
+unsafe impl Sync for CallCredentials {}
+
+impl CallCredentials {
+    /// Create credentials whose metadata are provided by `plugin`.
+    pub fn from_plugin<P: AuthMetadataPlugin>(plugin: P) -> CallCredentials {
+        let state: Box<Box<dyn AuthMetadataPlugin>> = Box::new(Box::new(plugin));
+        let plugin = grpc_metadata_credentials_plugin {
+            get_metadata: Some(plugin_get_metadata),
+            destroy: Some(plugin_destroy),
+            state: Box::into_raw(state) as *mut c_void,
+            type_: b"grpcio_plugin\0".as_ptr() as *const c_char,
+        };
+        let creds = unsafe {
+            grpc_sys::grpc_metadata_credentials_create_from_plugin(plugin, ptr::null_mut())
+        };
+        CallCredentials { creds }
+    }
+
+    /// Combine the credentials with `other`, the metadata of both are attached
+    /// to calls.
+    pub fn compose(self, other: CallCredentials) -> CallCredentials {
+        let creds = unsafe {
+            grpc_sys::grpc_composite_call_credentials_create(
+                self.creds,
+                other.creds,
+                ptr::null_mut(),
+            )
+        };
+        CallCredentials { creds }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut grpc_call_credentials {
+        self.creds
+    }
+}
+
+impl Drop for CallCredentials {
+    fn drop(&mut self) {
+        unsafe { grpc_sys::grpc_call_credentials_release(self.creds) }
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -8,6 +9,7 @@ use std::thread::{Builder as ThreadBuilder, JoinHandle};
 use crate::grpc_sys;
 
 use crate::cq::{CompletionQueue, CompletionQueueHandle, EventType, WorkQueue};
+use crate::resolver::ResolverFactory;
 use crate::task::CallTag;
 
 // event loop
@@ -38,6 +40,7 @@ fn poll_queue(tx: mpsc::Sender<CompletionQueue>) {
 pub struct EnvBuilder {
     cq_count: usize,
     name_prefix: Option<String>,
+    resolvers: HashMap<String, Arc<dyn ResolverFactory>>,
 }
 
 impl EnvBuilder {
@@ -46,6 +49,7 @@ impl EnvBuilder {
         EnvBuilder {
             cq_count: unsafe { grpc_sys::gpr_cpu_num_cores() as usize },
             name_prefix: None,
+            resolvers: HashMap::new(),
         }
     }
 
@@ -67,6 +71,16 @@ impl EnvBuilder {
         self
     }
 
+    /// Register a resolver for targets of the scheme of `factory`.
+    ///
+    /// Channels created with the environment will use it to resolve targets
+    /// like `scheme:///endpoint`, instead of the resolvers of gRPC core.
+    pub fn register_resolver<F: ResolverFactory + 'static>(mut self, factory: F) -> EnvBuilder {
+        self.resolvers
+            .insert(factory.scheme().to_owned(), Arc::new(factory));
+        self
+    }
+
     /// Finalize the [`EnvBuilder`], build the [`Environment`] and initialize the gRPC library.
     pub fn build(self) -> Environment {
         unsafe {
@@ -92,6 +106,7 @@ impl EnvBuilder {
             cqs,
             idx: AtomicUsize::new(0),
             _handles: handles,
+            resolvers: self.resolvers,
         }
     }
 }
@@ -101,6 +116,7 @@ pub struct Environment {
     cqs: Vec<CompletionQueue>,
     idx: AtomicUsize,
     _handles: Vec<JoinHandle<()>>,
+    resolvers: HashMap<String, Arc<dyn ResolverFactory>>,
 }
 
 impl Environment {
@@ -129,6 +145,10 @@ impl Environment {
         let idx = self.idx.fetch_add(1, Ordering::Relaxed);
         self.cqs[idx % self.cqs.len()].clone()
     }
+
+    pub(crate) fn resolver(&self, scheme: &str) -> Option<&Arc<dyn ResolverFactory>> {
+        self.resolvers.get(scheme)
+    }
 }
 
 impl Drop for Environment {
@@ -34,6 +34,8 @@ pub enum Error {
     GoogleAuthenticationFailed,
     /// Invalid format of metadata.
     InvalidMetadata(String),
+    /// Invalid service config.
+    InvalidServiceConfig(String),
 }
 
 impl Display for Error {
@@ -55,6 +57,7 @@ impl error::Error for Error {
             Error::QueueShutdown => "gRPC completion queue shutdown",
             Error::GoogleAuthenticationFailed => "Could not create google default credentials.",
             Error::InvalidMetadata(_) => "invalid format of metadata",
+            Error::InvalidServiceConfig(_) => "invalid service config",
         }
     }
 
@@ -28,6 +28,8 @@ use grpcio_sys as grpc_sys;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "secure")]
+mod auth_context;
 mod buf;
 mod call;
 mod channel;
@@ -41,38 +43,65 @@ mod error;
 mod log_util;
 mod metadata;
 mod quota;
+mod resolver;
 mod server;
+mod service_config;
+#[cfg(feature = "std-future")]
+mod std_future;
 mod task;
 
+#[cfg(feature = "secure")]
+pub use crate::auth_context::{AuthContext, AuthProperty, AuthPropertyIter};
 pub use crate::call::client::{
     CallOption, ClientCStreamReceiver, ClientCStreamSender, ClientDuplexReceiver,
-    ClientDuplexSender, ClientSStreamReceiver, ClientUnaryReceiver, StreamingCallSink,
+    ClientDuplexSender, ClientSStreamReceiver, ClientUnaryReceiver, ResponseMetadata,
+    StreamingCallSink,
 };
+pub use crate::call::retry::{HedgingPolicy, RetryPolicy};
+#[cfg(feature = "std-future")]
+pub use crate::call::server::AsyncRpcContext;
 pub use crate::call::server::{
-    ClientStreamingSink, ClientStreamingSinkResult, Deadline, DuplexSink, DuplexSinkFailure,
-    RequestStream, RpcContext, ServerStreamingSink, ServerStreamingSinkFailure, UnarySink,
-    UnarySinkResult,
+    CancellationFuture, ClientStreamingSink, ClientStreamingSinkResult, Deadline, DuplexSink,
+    DuplexSinkFailure, RequestStream, RpcContext, ServerStreamingSink, ServerStreamingSinkFailure,
+    UnarySink, UnarySinkResult,
 };
 pub use crate::call::{MessageReader, Method, MethodType, RpcStatus, RpcStatusCode, WriteFlags};
 pub use crate::channel::{
-    Channel, ChannelBuilder, CompressionAlgorithms, CompressionLevel, ConnectivityState, LbPolicy,
-    OptTarget,
+    Channel, ChannelBuilder, CompressionAlgorithms, CompressionLevel, ConnectedFuture,
+    ConnectivityState, ConnectivityStateStream, LbPolicy, OptTarget, StateChangeFuture,
 };
-pub use crate::client::Client;
+pub use crate::client::{Client, ClientInterceptor};
 
 #[cfg(feature = "protobuf-codec")]
 pub use crate::codec::pb_codec::{de as pb_de, ser as pb_ser};
 #[cfg(feature = "prost-codec")]
 pub use crate::codec::pr_codec::{de as pr_de, ser as pr_ser};
+pub use crate::codec::raw_codec::{de as raw_de, ser as raw_ser};
 
 pub use crate::codec::Marshaller;
 #[cfg(feature = "secure")]
 pub use crate::credentials::{
-    ChannelCredentials, ChannelCredentialsBuilder, ServerCredentials, ServerCredentialsBuilder,
+    AuthMetadataCallback, AuthMetadataContext, AuthMetadataPlugin, CallCredentials,
+    CertificateRequestType, ChannelCredentials, ChannelCredentialsBuilder, LocalConnectType,
+    ServerCredentials, ServerCredentialsBuilder, ServerCredentialsFetcher,
 };
 pub use crate::env::{EnvBuilder, Environment};
 pub use crate::error::{Error, Result};
 pub use crate::log_util::redirect_log;
 pub use crate::metadata::{Metadata, MetadataBuilder, MetadataIter};
 pub use crate::quota::ResourceQuota;
-pub use crate::server::{Server, ServerBuilder, Service, ServiceBuilder, ShutdownFuture};
+pub use crate::resolver::{
+    Resolution, Resolver, ResolverFactory, ResolverObserver, StaticResolverFactory,
+};
+pub use crate::server::{
+    CheckResult, GracefulShutdownFuture, ListeningAddr, Server, ServerBuilder, ServerInterceptor,
+    Service, ServiceBuilder, ShutdownFuture,
+};
+pub use crate::service_config::{MethodConfig, ServiceConfig, ServiceConfigBuilder};
+#[cfg(feature = "std-future")]
+pub use crate::std_future::ResponseStream;
+
+/// Re-exported so that code generated in async mode doesn't need to depend
+/// on `async-trait` directly.
+#[cfg(feature = "std-future")]
+pub use async_trait::async_trait;
@@ -1,6 +1,6 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use crate::grpc_sys::{self, grpc_metadata_array};
+use crate::grpc_sys::{self, grpc_metadata, grpc_metadata_array};
 use std::borrow::Cow;
 use std::{mem, slice, str};
 
@@ -67,6 +67,11 @@ impl MetadataBuilder {
         }
     }
 
+    /// Create a builder that starts with the entries of `meta`.
+    pub(crate) fn from_metadata(meta: Metadata) -> MetadataBuilder {
+        MetadataBuilder { arr: meta }
+    }
+
     /// Add a metadata holding an ASCII value.
     ///
     /// `key` must not use suffix (-bin) indicating a binary valued metadata entry.
@@ -150,6 +155,16 @@ impl Metadata {
         }
     }
 
+    /// Borrow a metadata array that is owned by gRPC core.
+    pub(crate) unsafe fn from_raw_ref<'a>(arr: *const grpc_metadata_array) -> &'a Metadata {
+        &*(arr as *const Metadata)
+    }
+
+    /// Make a deep copy of a metadata array that is owned by gRPC core.
+    pub(crate) unsafe fn copy_from_raw(arr: *const grpc_metadata_array) -> Metadata {
+        Metadata::from_raw_ref(arr).clone()
+    }
+
     /// Returns the count of metadata entries.
     #[inline]
     pub fn len(&self) -> usize {
@@ -179,6 +194,15 @@ impl Metadata {
         }
     }
 
+    /// Returns the raw entries, whose slices are owned by the metadata.
+    #[cfg(feature = "secure")]
+    pub(crate) fn raw_entries(&self) -> &[grpc_metadata] {
+        if self.0.count == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.0.metadata, self.0.count) }
+    }
+
     /// Returns an iterator over the metadata entries.
     pub fn iter(&self) -> MetadataIter<'_> {
         MetadataIter {
@@ -0,0 +1,333 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Name resolvers implemented in Rust.
+//!
+//! A channel whose target has a scheme registered by
+//! [`EnvBuilder::register_resolver`] gets its addresses and service config
+//! from the resolver instead of gRPC core.
+//!
+//! They are not resolvers of gRPC core. Core only sees the resolved
+//! addresses, so a resolution that changes the addresses or the service
+//! config replaces the underlying connections of the channel, along with the
+//! states of load balancing; calls that are in flight are not affected.
+//! Re-resolution is requested only when the channel is found in
+//! `TRANSIENT_FAILURE`, resolvers that watch changes should push them
+//! actively.
+//!
+//! The endpoint of the target is used as the default authority, and the host
+//! of it is checked against the certificates of secure channels, unless they
+//! are set by [`ChannelBuilder::default_authority`] or overridden explicitly.
+//!
+//! [`EnvBuilder::register_resolver`]: ./struct.EnvBuilder.html#method.register_resolver
+//! [`ChannelBuilder::default_authority`]: ./struct.ChannelBuilder.html#method.default_authority
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::channel::ResolvedChannel;
+use crate::service_config::ServiceConfig;
+use crate::{RpcStatus, RpcStatusCode};
+
+/// The result of resolving a target.
+#[derive(Clone, Debug, Default)]
+pub struct Resolution {
+    addresses: Vec<SocketAddr>,
+    service_config: Option<ServiceConfig>,
+}
+
+impl Resolution {
+    /// Create a resolution with the addresses of backends.
+    pub fn new(addresses: Vec<SocketAddr>) -> Resolution {
+        Resolution {
+            addresses,
+            service_config: None,
+        }
+    }
+
+    /// Set the service config of the target.
+    ///
+    /// It takes precedence over the one set by [`ChannelBuilder::service_config`].
+    ///
+    /// [`ChannelBuilder::service_config`]: ./struct.ChannelBuilder.html#method.service_config
+    pub fn service_config(mut self, config: ServiceConfig) -> Resolution {
+        self.service_config = Some(config);
+        self
+    }
+
+    /// Get the addresses of backends.
+    pub fn addresses(&self) -> &[SocketAddr] {
+        &self.addresses
+    }
+
+    pub(crate) fn get_service_config(&self) -> Option<&ServiceConfig> {
+        self.service_config.as_ref()
+    }
+}
+
+/// Pushes resolutions of a target to its channel.
+///
+/// It can be cloned and used from any thread. Results pushed after the
+/// channel is dropped are ignored.
+#[derive(Clone)]
+pub struct ResolverObserver {
+    channel: Weak<ResolvedChannel>,
+}
+
+impl ResolverObserver {
+    pub(crate) fn new(channel: Weak<ResolvedChannel>) -> ResolverObserver {
+        ResolverObserver { channel }
+    }
+
+    /// Update the addresses and service config of the channel.
+    ///
+    /// Calls fail with `UNAVAILABLE` if there is no address.
+    pub fn update(&self, resolution: Resolution) {
+        if let Some(channel) = self.channel.upgrade() {
+            channel.update(resolution);
+        }
+    }
+
+    /// Report that the target can't be resolved.
+    ///
+    /// Calls fail with `status` until the next update.
+    pub fn fail(&self, status: RpcStatus) {
+        if let Some(channel) = self.channel.upgrade() {
+            channel.fail(status);
+        }
+    }
+
+    /// Check if the channel is still alive.
+    pub fn is_closed(&self) -> bool {
+        self.channel.upgrade().is_none()
+    }
+}
+
+/// Resolves the target of a channel.
+///
+/// The resolver is dropped along with the channel.
+pub trait Resolver: Send + Sync {
+    /// Called when the channel fails to connect to any of the resolved
+    /// addresses and a fresh resolution is likely needed.
+    ///
+    /// It's called at most once each time the channel enters
+    /// `TRANSIENT_FAILURE`, or until the next resolution that changes the
+    /// addresses is pushed.
+    fn request_reresolution(&self) {}
+}
+
+/// Creates [`Resolver`]s for targets of a scheme.
+pub trait ResolverFactory: Send + Sync {
+    /// The scheme handled by the factory, e.g. `myscheme` for `myscheme:///svc`.
+    fn scheme(&self) -> &str;
+
+    /// Create a resolver for the endpoint of a target, e.g. `svc` for
+    /// `myscheme:///svc`.
+    ///
+    /// Results should be pushed to `observer`, either in this method or later.
+    /// Calls fail with `UNAVAILABLE` until the first result is pushed.
+    fn build(&self, endpoint: &str, observer: ResolverObserver) -> Box<dyn Resolver>;
+}
+
+/// Splits `target` into its scheme and endpoint.
+///
+/// Both `scheme:endpoint` and `scheme://authority/endpoint` are accepted.
+pub(crate) fn parse_target(target: &str) -> Option<(&str, &str)> {
+    let pos = target.find(':')?;
+    let (scheme, rest) = (&target[..pos], &target[pos + 1..]);
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.';
+    if !scheme.starts_with(|c: char| c.is_ascii_alphabetic()) || !scheme.chars().all(valid) {
+        return None;
+    }
+    if let Some(rest) = rest.strip_prefix("//") {
+        let pos = rest.find('/')?;
+        return Some((scheme, &rest[pos + 1..]));
+    }
+    Some((scheme, rest))
+}
+
+/// Returns the host part of an endpoint like `host:port` or `[::1]:port`.
+#[cfg(feature = "secure")]
+pub(crate) fn host_of(endpoint: &str) -> &str {
+    if endpoint.starts_with('[') {
+        if let Some(end) = endpoint.find(']') {
+            return &endpoint[1..end];
+        }
+    }
+    match endpoint.rfind(':') {
+        // More than one colon means a bare IPv6 address.
+        Some(pos) if endpoint[..pos].find(':').is_none() => &endpoint[..pos],
+        _ => endpoint,
+    }
+}
+
+/// Formats `addresses` as a target understood by gRPC core.
+///
+/// IPv4 addresses are mapped to IPv6 ones if both are present.
+pub(crate) fn format_addresses(addresses: &[SocketAddr]) -> String {
+    let all_v4 = addresses.iter().all(SocketAddr::is_ipv4);
+    let mut target = String::from(if all_v4 { "ipv4:" } else { "ipv6:" });
+    for (i, addr) in addresses.iter().enumerate() {
+        if i > 0 {
+            target.push(',');
+        }
+        match *addr {
+            SocketAddr::V4(ref a) if !all_v4 => {
+                write!(target, "[{}]:{}", a.ip().to_ipv6_mapped(), a.port()).unwrap()
+            }
+            ref a => write!(target, "{}", a).unwrap(),
+        }
+    }
+    target
+}
+
+#[derive(Default)]
+struct StaticTargets {
+    resolutions: HashMap<String, Resolution>,
+    observers: HashMap<String, Vec<ResolverObserver>>,
+}
+
+/// A [`ResolverFactory`] that resolves targets from a table maintained by the
+/// application.
+///
+/// It's mostly useful for testing. Updates to the table are pushed to all
+/// channels of the target immediately. Targets that are not in the table are
+/// failed with `UNAVAILABLE`.
+#[derive(Clone)]
+pub struct StaticResolverFactory {
+    scheme: String,
+    targets: Arc<Mutex<StaticTargets>>,
+}
+
+impl StaticResolverFactory {
+    /// Create a factory for targets of `scheme`.
+    pub fn new(scheme: &str) -> StaticResolverFactory {
+        StaticResolverFactory {
+            scheme: scheme.to_owned(),
+            targets: Arc::default(),
+        }
+    }
+
+    /// Set the resolution of `endpoint`.
+    pub fn set(&self, endpoint: &str, resolution: Resolution) {
+        let mut targets = self.targets.lock().unwrap();
+        if let Some(observers) = targets.observers.get_mut(endpoint) {
+            observers.retain(|o| !o.is_closed());
+            for o in observers.iter() {
+                o.update(resolution.clone());
+            }
+        }
+        targets.resolutions.insert(endpoint.to_owned(), resolution);
+    }
+}
+
+impl ResolverFactory for StaticResolverFactory {
+    fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    fn build(&self, endpoint: &str, observer: ResolverObserver) -> Box<dyn Resolver> {
+        let resolver = StaticResolver {
+            endpoint: endpoint.to_owned(),
+            targets: self.targets.clone(),
+            observer: observer.clone(),
+        };
+        self.targets
+            .lock()
+            .unwrap()
+            .observers
+            .entry(endpoint.to_owned())
+            .or_default()
+            .push(observer);
+        resolver.resolve();
+        Box::new(resolver)
+    }
+}
+
+struct StaticResolver {
+    endpoint: String,
+    targets: Arc<Mutex<StaticTargets>>,
+    observer: ResolverObserver,
+}
+
+impl StaticResolver {
+    fn resolve(&self) {
+        let resolution = self
+            .targets
+            .lock()
+            .unwrap()
+            .resolutions
+            .get(&self.endpoint)
+            .cloned();
+        match resolution {
+            Some(r) => self.observer.update(r),
+            None => self.observer.fail(RpcStatus::new(
+                RpcStatusCode::UNAVAILABLE,
+                Some(format!("{} is not found", self.endpoint)),
+            )),
+        }
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn request_reresolution(&self) {
+        self.resolve();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let cases = vec![
+            ("myscheme:///svc", Some(("myscheme", "svc"))),
+            ("myscheme://authority/svc/a", Some(("myscheme", "svc/a"))),
+            ("myscheme:svc", Some(("myscheme", "svc"))),
+            ("my+scheme:svc", Some(("my+scheme", "svc"))),
+            ("myscheme://svc", None),
+            (":svc", None),
+            ("127.0.0.1", None),
+            ("127.0.0.1:80", None),
+            ("[::1]:80", None),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(parse_target(target), expected, "{}", target);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "secure")]
+    fn test_host_of() {
+        let cases = vec![
+            ("svc.example.com", "svc.example.com"),
+            ("svc.example.com:443", "svc.example.com"),
+            ("127.0.0.1:80", "127.0.0.1"),
+            ("[::1]:80", "::1"),
+            ("[::1]", "::1"),
+            ("::1", "::1"),
+            ("", ""),
+        ];
+        for (endpoint, host) in cases {
+            assert_eq!(host_of(endpoint), host, "{}", endpoint);
+        }
+    }
+
+    #[test]
+    fn test_format_addresses() {
+        let addrs: Vec<SocketAddr> = vec![
+            "127.0.0.1:80".parse().unwrap(),
+            "10.0.0.1:81".parse().unwrap(),
+        ];
+        assert_eq!(format_addresses(&addrs), "ipv4:127.0.0.1:80,10.0.0.1:81");
+
+        let addrs: Vec<SocketAddr> =
+            vec!["127.0.0.1:80".parse().unwrap(), "[::1]:81".parse().unwrap()];
+        assert_eq!(
+            format_addresses(&addrs),
+            "ipv6:[::ffff:127.0.0.1]:80,[::1]:81"
+        );
+    }
+}
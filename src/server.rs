@@ -6,20 +6,30 @@ use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::net::{IpAddr, SocketAddr};
 use std::ptr;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use crate::grpc_sys::{self, grpc_call_error, grpc_server};
+use futures::task::{self, Task};
 use futures::{Async, Future, Poll};
 
 use crate::call::server::*;
-use crate::call::{MessageReader, Method, MethodType};
+use crate::call::{MessageReader, Method, MethodType, RpcStatus};
 use crate::channel::ChannelArgs;
+use crate::codec::raw_codec;
 use crate::cq::CompletionQueue;
 use crate::env::Environment;
 use crate::error::{Error, Result};
-use crate::task::{CallTag, CqFuture};
+use crate::task::{CallTag, CqFuture, Delay};
 use crate::RpcContext;
+#[cfg(feature = "std-future")]
+use crate::{
+    std_future::{forward_duplex_streaming, forward_server_streaming, ResponseStream},
+    AsyncRpcContext,
+};
+#[cfg(feature = "std-future")]
+use std::future::Future as StdFuture;
 
 const DEFAULT_REQUEST_SLOTS_PER_CQ: usize = 1024;
 
@@ -62,44 +72,175 @@ where
     }
 }
 
-/// Given a host and port, creates a string of the form "host:port" or
-/// "[host]:port", depending on whether the host is an IPv6 literal.
-fn join_host_port(host: &str, port: u16) -> String {
-    if let Ok(ip) = host.parse::<IpAddr>() {
-        format!("{}\0", SocketAddr::new(ip, port))
-    } else {
-        format!("{}:{}\0", host, port)
+/// The result of a [`ServerInterceptor`] check.
+pub enum CheckResult {
+    /// Continue to handle the call.
+    Continue,
+    /// Reject the call with the given status. The handler will not be called.
+    Abort(RpcStatus),
+}
+
+/// A hook that is invoked before a call is dispatched to its handler.
+///
+/// Interceptors are replicated for every completion queue just like handlers,
+/// so state that needs to be shared between calls should be kept behind an `Arc`.
+pub trait ServerInterceptor: Send {
+    /// Inspect the call and decide whether it should be handled.
+    fn intercept(&mut self, ctx: &RpcContext<'_>) -> CheckResult;
+    fn box_clone(&self) -> Box<dyn ServerInterceptor>;
+}
+
+impl<F: 'static> ServerInterceptor for F
+where
+    F: FnMut(&RpcContext<'_>) -> CheckResult + Send + Clone,
+{
+    #[inline]
+    fn intercept(&mut self, ctx: &RpcContext<'_>) -> CheckResult {
+        self(ctx)
+    }
+
+    #[inline]
+    fn box_clone(&self) -> Box<dyn ServerInterceptor> {
+        Box::new(self.clone())
+    }
+}
+
+/// A handler that runs a chain of interceptors before the inner handler.
+struct InterceptedHandler {
+    interceptors: Vec<Box<dyn ServerInterceptor>>,
+    handler: BoxHandler,
+}
+
+impl CloneableHandler for InterceptedHandler {
+    fn handle(&mut self, ctx: RpcContext<'_>, reqs: Option<MessageReader>) {
+        for interceptor in &mut self.interceptors {
+            if let CheckResult::Abort(status) = interceptor.intercept(&ctx) {
+                execute_rejected(&ctx, &status);
+                return;
+            }
+        }
+        self.handler.handle(ctx, reqs)
+    }
+
+    fn box_clone(&self) -> Box<dyn CloneableHandler> {
+        Box::new(InterceptedHandler {
+            interceptors: self.interceptors.iter().map(|i| i.box_clone()).collect(),
+            handler: self.handler.box_clone(),
+        })
+    }
+
+    #[inline]
+    fn method_type(&self) -> MethodType {
+        self.handler.method_type()
+    }
+}
+
+/// Wrap the handler with the given interceptors.
+fn intercept_handler(
+    handler: BoxHandler,
+    interceptors: &[Box<dyn ServerInterceptor>],
+) -> BoxHandler {
+    Box::new(InterceptedHandler {
+        interceptors: interceptors.iter().map(|i| i.box_clone()).collect(),
+        handler,
+    })
+}
+
+/// Wrap all the handlers with the given interceptors.
+fn intercept_handlers(
+    handlers: HashMap<&'static [u8], BoxHandler>,
+    interceptors: &[Box<dyn ServerInterceptor>],
+) -> HashMap<&'static [u8], BoxHandler> {
+    if interceptors.is_empty() {
+        return handlers;
+    }
+    handlers
+        .into_iter()
+        .map(|(path, handler)| (path, intercept_handler(handler, interceptors)))
+        .collect()
+}
+
+/// Wrap the handlers and the fallback handler of a server with the given interceptors.
+fn intercept_registry(registry: Registry, interceptors: &[Box<dyn ServerInterceptor>]) -> Registry {
+    if interceptors.is_empty() {
+        return registry;
+    }
+    Registry {
+        handlers: intercept_handlers(registry.handlers, interceptors),
+        fallback: registry
+            .fallback
+            .map(|handler| intercept_handler(handler, interceptors)),
+    }
+}
+
+const UNIX_SCHEME: &str = "unix:";
+const UNIX_ABSTRACT_SCHEME: &str = "unix-abstract:";
+
+/// An address a [`Server`] listens on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListeningAddr {
+    /// A host and a port. The port is the one actually bound, which is
+    /// different from the requested one if 0 is requested.
+    Tcp(String, u16),
+    /// A unix domain socket at the given path.
+    Unix(String),
+    /// A unix domain socket in the abstract namespace.
+    UnixAbstract(String),
+}
+
+impl fmt::Display for ListeningAddr {
+    /// Formats the address in the form that can be used as a target of
+    /// [`ChannelBuilder::connect`].
+    ///
+    /// [`ChannelBuilder::connect`]: ./struct.ChannelBuilder.html#method.connect
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ListeningAddr::Tcp(host, port) => match host.parse::<IpAddr>() {
+                Ok(ip) => write!(f, "{}", SocketAddr::new(ip, *port)),
+                Err(_) => write!(f, "{}:{}", host, port),
+            },
+            ListeningAddr::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path),
+            ListeningAddr::UnixAbstract(name) => write!(f, "{}{}", UNIX_ABSTRACT_SCHEME, name),
+        }
     }
 }
 
 #[cfg(feature = "secure")]
 mod imp {
-    use super::join_host_port;
+    use super::ListeningAddr;
     use crate::credentials::ServerCredentials;
     use crate::grpc_sys::{self, grpc_server};
 
     pub struct Binder {
-        pub host: String,
-        pub port: u16,
+        pub addr: ListeningAddr,
+        c_addr: String,
         cred: Option<ServerCredentials>,
     }
 
     impl Binder {
-        pub fn new(host: String, port: u16) -> Binder {
-            let cred = None;
-            Binder { host, port, cred }
+        pub fn new(addr: ListeningAddr) -> Binder {
+            let c_addr = format!("{}\0", addr);
+            Binder {
+                addr,
+                c_addr,
+                cred: None,
+            }
         }
 
-        pub fn with_cred(host: String, port: u16, cred: ServerCredentials) -> Binder {
-            let cred = Some(cred);
-            Binder { host, port, cred }
+        pub fn with_cred(addr: ListeningAddr, cred: ServerCredentials) -> Binder {
+            let c_addr = format!("{}\0", addr);
+            Binder {
+                addr,
+                c_addr,
+                cred: Some(cred),
+            }
         }
 
         pub unsafe fn bind(&mut self, server: *mut grpc_server) -> u16 {
-            let addr = join_host_port(&self.host, self.port);
-            let port = match self.cred.take() {
+            let addr = &self.c_addr;
+            let port = match self.cred.as_mut() {
                 None => grpc_sys::grpc_server_add_insecure_http2_port(server, addr.as_ptr() as _),
-                Some(mut cert) => grpc_sys::grpc_server_add_secure_http2_port(
+                Some(cert) => grpc_sys::grpc_server_add_secure_http2_port(
                     server,
                     addr.as_ptr() as _,
                     cert.as_mut_ptr(),
@@ -112,22 +253,22 @@ mod imp {
 
 #[cfg(not(feature = "secure"))]
 mod imp {
-    use super::join_host_port;
+    use super::ListeningAddr;
     use crate::grpc_sys::{self, grpc_server};
 
     pub struct Binder {
-        pub host: String,
-        pub port: u16,
+        pub addr: ListeningAddr,
+        c_addr: String,
     }
 
     impl Binder {
-        pub fn new(host: String, port: u16) -> Binder {
-            Binder { host, port }
+        pub fn new(addr: ListeningAddr) -> Binder {
+            let c_addr = format!("{}\0", addr);
+            Binder { addr, c_addr }
         }
 
         pub unsafe fn bind(&mut self, server: *mut grpc_server) -> u16 {
-            let addr = join_host_port(&self.host, self.port);
-            grpc_sys::grpc_server_add_insecure_http2_port(server, addr.as_ptr() as _) as u16
+            grpc_sys::grpc_server_add_insecure_http2_port(server, self.c_addr.as_ptr() as _) as u16
         }
     }
 }
@@ -139,6 +280,7 @@ use self::imp::Binder;
 /// Use it to build a service which can be registered to a server.
 pub struct ServiceBuilder {
     handlers: HashMap<&'static [u8], BoxHandler>,
+    interceptors: Vec<Box<dyn ServerInterceptor>>,
 }
 
 impl ServiceBuilder {
@@ -146,9 +288,21 @@ impl ServiceBuilder {
     pub fn new() -> ServiceBuilder {
         ServiceBuilder {
             handlers: HashMap::new(),
+            interceptors: Vec::new(),
         }
     }
 
+    /// Add an interceptor that is invoked before every handler of the service.
+    ///
+    /// Interceptors are invoked in the order they are added.
+    pub fn add_interceptor<I>(mut self, interceptor: I) -> ServiceBuilder
+    where
+        I: ServerInterceptor + 'static,
+    {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
     /// Add a unary RPC call handler.
     pub fn add_unary_handler<Req, Resp, F>(
         mut self,
@@ -232,19 +386,156 @@ impl ServiceBuilder {
         self
     }
 
+    /// Add a unary RPC call handler implemented by an async function.
+    ///
+    /// The returned future is spawned into the gRPC poll thread, and its
+    /// result is sent back to the client.
+    #[cfg(feature = "std-future")]
+    pub fn add_async_unary_handler<Req, Resp, F, Fut>(
+        self,
+        method: &Method<Req, Resp>,
+        handler: F,
+    ) -> ServiceBuilder
+    where
+        Req: 'static,
+        Resp: Send + 'static,
+        F: Fn(AsyncRpcContext, Req) -> Fut + Send + Clone + 'static,
+        Fut: StdFuture<Output = std::result::Result<Resp, RpcStatus>> + Send + 'static,
+    {
+        self.add_unary_handler(method, move |ctx, req, sink| {
+            let f = handler(AsyncRpcContext::new(&ctx), req);
+            ctx.spawn_std(async move {
+                let res = match f.await {
+                    Ok(resp) => sink.success(resp).await,
+                    Err(status) => sink.fail(status).await,
+                };
+                if let Err(e) = res {
+                    debug!("failed to reply unary call: {:?}", e);
+                }
+            });
+        })
+    }
+
+    /// Add a client streaming RPC call handler implemented by an async function.
+    #[cfg(feature = "std-future")]
+    pub fn add_async_client_streaming_handler<Req, Resp, F, Fut>(
+        self,
+        method: &Method<Req, Resp>,
+        handler: F,
+    ) -> ServiceBuilder
+    where
+        Req: 'static,
+        Resp: Send + 'static,
+        F: Fn(AsyncRpcContext, RequestStream<Req>) -> Fut + Send + Clone + 'static,
+        Fut: StdFuture<Output = std::result::Result<Resp, RpcStatus>> + Send + 'static,
+    {
+        self.add_client_streaming_handler(method, move |ctx, stream, sink| {
+            let f = handler(AsyncRpcContext::new(&ctx), stream);
+            ctx.spawn_std(async move {
+                let res = match f.await {
+                    Ok(resp) => sink.success(resp).await,
+                    Err(status) => sink.fail(status).await,
+                };
+                if let Err(e) = res {
+                    debug!("failed to reply client streaming call: {:?}", e);
+                }
+            });
+        })
+    }
+
+    /// Add a server streaming RPC call handler implemented by an async function.
+    ///
+    /// All the items of the returned [`ResponseStream`] are sent to the client.
+    #[cfg(feature = "std-future")]
+    pub fn add_async_server_streaming_handler<Req, Resp, F, Fut>(
+        self,
+        method: &Method<Req, Resp>,
+        handler: F,
+    ) -> ServiceBuilder
+    where
+        Req: 'static,
+        Resp: Send + 'static,
+        F: Fn(AsyncRpcContext, Req) -> Fut + Send + Clone + 'static,
+        Fut: StdFuture<Output = std::result::Result<ResponseStream<Resp>, RpcStatus>>
+            + Send
+            + 'static,
+    {
+        self.add_server_streaming_handler(method, move |ctx, req, sink| {
+            let f = handler(AsyncRpcContext::new(&ctx), req);
+            ctx.spawn_std(async move {
+                let res = match f.await {
+                    Ok(stream) => forward_server_streaming(stream, sink).await,
+                    Err(status) => sink.fail(status).await,
+                };
+                if let Err(e) = res {
+                    debug!("failed to reply server streaming call: {:?}", e);
+                }
+            });
+        })
+    }
+
+    /// Add a duplex streaming RPC call handler implemented by an async function.
+    ///
+    /// All the items of the returned [`ResponseStream`] are sent to the client.
+    #[cfg(feature = "std-future")]
+    pub fn add_async_duplex_streaming_handler<Req, Resp, F, Fut>(
+        self,
+        method: &Method<Req, Resp>,
+        handler: F,
+    ) -> ServiceBuilder
+    where
+        Req: 'static,
+        Resp: Send + 'static,
+        F: Fn(AsyncRpcContext, RequestStream<Req>) -> Fut + Send + Clone + 'static,
+        Fut: StdFuture<Output = std::result::Result<ResponseStream<Resp>, RpcStatus>>
+            + Send
+            + 'static,
+    {
+        self.add_duplex_streaming_handler(method, move |ctx, stream, sink| {
+            let f = handler(AsyncRpcContext::new(&ctx), stream);
+            ctx.spawn_std(async move {
+                let res = match f.await {
+                    Ok(stream) => forward_duplex_streaming(stream, sink).await,
+                    Err(status) => sink.fail(status).await,
+                };
+                if let Err(e) = res {
+                    debug!("failed to reply duplex streaming call: {:?}", e);
+                }
+            });
+        })
+    }
+
     /// Finalize the [`ServiceBuilder`] and build the [`Service`].
     pub fn build(self) -> Service {
         Service {
-            handlers: self.handlers,
+            handlers: intercept_handlers(self.handlers, &self.interceptors),
+            shutdown_hooks: Vec::new(),
         }
     }
 }
 
+type ShutdownHook = Box<dyn FnOnce() + Send>;
+
 /// A gRPC service.
 ///
 /// Use [`ServiceBuilder`] to build a [`Service`].
 pub struct Service {
     handlers: HashMap<&'static [u8], BoxHandler>,
+    shutdown_hooks: Vec<ShutdownHook>,
+}
+
+impl Service {
+    /// Register a hook that is invoked when the server serving this service
+    /// begins to shutdown.
+    ///
+    /// Hooks are invoked only once, before the server stops accepting new calls.
+    pub fn on_shutdown<F>(mut self, hook: F) -> Service
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(hook));
+        self
+    }
 }
 
 /// [`Server`] factory in order to configure the properties.
@@ -254,6 +545,9 @@ pub struct ServerBuilder {
     args: Option<ChannelArgs>,
     slots_per_cq: usize,
     handlers: HashMap<&'static [u8], BoxHandler>,
+    fallback: Option<BoxHandler>,
+    interceptors: Vec<Box<dyn ServerInterceptor>>,
+    shutdown_hooks: Vec<ShutdownHook>,
 }
 
 impl ServerBuilder {
@@ -265,6 +559,9 @@ impl ServerBuilder {
             args: None,
             slots_per_cq: DEFAULT_REQUEST_SLOTS_PER_CQ,
             handlers: HashMap::new(),
+            fallback: None,
+            interceptors: Vec::new(),
+            shutdown_hooks: Vec::new(),
         }
     }
 
@@ -272,7 +569,25 @@ impl ServerBuilder {
     ///
     /// This function can be called multiple times to bind to multiple ports.
     pub fn bind<S: Into<String>>(mut self, host: S, port: u16) -> ServerBuilder {
-        self.binders.push(Binder::new(host.into(), port));
+        let addr = ListeningAddr::Tcp(host.into(), port);
+        self.binders.push(Binder::new(addr));
+        self
+    }
+
+    /// Bind to a unix domain socket at the given path.
+    pub fn bind_unix<S: Into<String>>(mut self, path: S) -> ServerBuilder {
+        let addr = ListeningAddr::Unix(path.into());
+        self.binders.push(Binder::new(addr));
+        self
+    }
+
+    /// Bind to a unix domain socket in the abstract namespace.
+    ///
+    /// Abstract sockets are only available on Linux, and require the gRPC C
+    /// core to support the `unix-abstract` scheme.
+    pub fn bind_unix_abstract<S: Into<String>>(mut self, name: S) -> ServerBuilder {
+        let addr = ListeningAddr::UnixAbstract(name.into());
+        self.binders.push(Binder::new(addr));
         self
     }
 
@@ -291,6 +606,77 @@ impl ServerBuilder {
     /// Register a service.
     pub fn register_service(mut self, service: Service) -> ServerBuilder {
         self.handlers.extend(service.handlers);
+        self.shutdown_hooks.extend(service.shutdown_hooks);
+        self
+    }
+
+    /// Set the handler for calls to methods that are not registered by any service.
+    ///
+    /// Messages are passed to and from the handler as raw bytes, and the path of
+    /// the method can be got from [`RpcContext::method`]. As the type of the method
+    /// is unknown, all calls are handled as duplex streaming ones: unary requests
+    /// arrive as a stream with a single message, and unary responses are sent as
+    /// a stream with a single message.
+    ///
+    /// It can be used to serve renamed methods, translate between versions of a
+    /// service, or forward calls to another server. Server-wide interceptors are
+    /// invoked before the handler. Calls to unknown methods are answered with
+    /// `UNIMPLEMENTED` if no handler is set, and the handler can do the same for
+    /// methods it doesn't recognize either.
+    ///
+    /// [`RpcContext::method`]: ./struct.RpcContext.html#method.method
+    pub fn fallback_handler<F>(mut self, mut handler: F) -> ServerBuilder
+    where
+        F: FnMut(RpcContext<'_>, RequestStream<Vec<u8>>, DuplexSink<Vec<u8>>)
+            + Send
+            + Clone
+            + 'static,
+    {
+        let h = move |ctx: RpcContext<'_>, _: Option<MessageReader>| {
+            execute_duplex_streaming(ctx, raw_codec::ser, raw_codec::de, &mut handler)
+        };
+        self.fallback = Some(Box::new(Handler::new(MethodType::Duplex, h)));
+        self
+    }
+
+    /// Set the handler for calls to methods that are not registered by any
+    /// service, implemented by an async function.
+    ///
+    /// All the items of the returned [`ResponseStream`] are sent to the client.
+    /// See [`ServerBuilder::fallback_handler`] for more details.
+    ///
+    /// [`ServerBuilder::fallback_handler`]: #method.fallback_handler
+    #[cfg(feature = "std-future")]
+    pub fn async_fallback_handler<F, Fut>(self, handler: F) -> ServerBuilder
+    where
+        F: Fn(AsyncRpcContext, RequestStream<Vec<u8>>) -> Fut + Send + Clone + 'static,
+        Fut: StdFuture<Output = std::result::Result<ResponseStream<Vec<u8>>, RpcStatus>>
+            + Send
+            + 'static,
+    {
+        self.fallback_handler(move |ctx, stream, sink| {
+            let f = handler(AsyncRpcContext::new(&ctx), stream);
+            ctx.spawn_std(async move {
+                let res = match f.await {
+                    Ok(stream) => forward_duplex_streaming(stream, sink).await,
+                    Err(status) => sink.fail(status).await,
+                };
+                if let Err(e) = res {
+                    debug!("failed to reply call to unknown method: {:?}", e);
+                }
+            });
+        })
+    }
+
+    /// Add an interceptor that is invoked before every handler of the server.
+    ///
+    /// Server-wide interceptors are invoked in the order they are added, and
+    /// before any interceptor registered on a [`ServiceBuilder`].
+    pub fn add_interceptor<I>(mut self, interceptor: I) -> ServerBuilder
+    where
+        I: ServerInterceptor + 'static,
+    {
+        self.interceptors.push(Box::new(interceptor));
         self
     }
 
@@ -303,14 +689,26 @@ impl ServerBuilder {
         unsafe {
             let server = grpc_sys::grpc_server_create(args, ptr::null_mut());
             let mut bind_addrs = Vec::with_capacity(self.binders.len());
-            for mut binder in self.binders.drain(..) {
+            let mut listening_addrs = Vec::with_capacity(self.binders.len());
+            for binder in &mut self.binders {
                 let bind_port = binder.bind(server);
                 if bind_port == 0 {
                     grpc_sys::grpc_server_destroy(server);
-                    return Err(Error::BindFail(binder.host, binder.port));
+                    return Err(match &binder.addr {
+                        ListeningAddr::Tcp(host, port) => Error::BindFail(host.clone(), *port),
+                        addr => Error::BindFail(addr.to_string(), 0),
+                    });
                 }
 
-                bind_addrs.push((binder.host, bind_port as u16));
+                let addr = match &binder.addr {
+                    ListeningAddr::Tcp(host, _) => {
+                        bind_addrs.push((host.clone(), bind_port));
+                        ListeningAddr::Tcp(host.clone(), bind_port)
+                    }
+                    // Unix domain sockets don't have ports.
+                    addr => addr.clone(),
+                };
+                listening_addrs.push(addr);
             }
 
             for cq in self.env.completion_queues() {
@@ -328,9 +726,19 @@ impl ServerBuilder {
                     server,
                     shutdown: AtomicBool::new(false),
                     bind_addrs,
+                    listening_addrs,
+                    _binders: self.binders,
                     slots_per_cq: self.slots_per_cq,
+                    calls: CallCounter::default(),
                 }),
-                handlers: self.handlers,
+                registry: intercept_registry(
+                    Registry {
+                        handlers: self.handlers,
+                        fallback: self.fallback,
+                    },
+                    &self.interceptors,
+                ),
+                shutdown_hooks: self.shutdown_hooks,
             })
         }
     }
@@ -340,7 +748,7 @@ impl ServerBuilder {
 mod secure_server {
     use crate::credentials::ServerCredentials;
 
-    use super::{Binder, ServerBuilder};
+    use super::{Binder, ListeningAddr, ServerBuilder};
 
     impl ServerBuilder {
         /// Bind to an address for secure connection.
@@ -352,7 +760,23 @@ mod secure_server {
             port: u16,
             c: ServerCredentials,
         ) -> ServerBuilder {
-            self.binders.push(Binder::with_cred(host.into(), port, c));
+            let addr = ListeningAddr::Tcp(host.into(), port);
+            self.binders.push(Binder::with_cred(addr, c));
+            self
+        }
+
+        /// Bind to a unix domain socket at the given path for secure connection.
+        ///
+        /// It's usually used with [`ServerCredentials::local`].
+        ///
+        /// [`ServerCredentials::local`]: ./struct.ServerCredentials.html#method.local
+        pub fn bind_unix_secure<S: Into<String>>(
+            mut self,
+            path: S,
+            c: ServerCredentials,
+        ) -> ServerBuilder {
+            let addr = ListeningAddr::Unix(path.into());
+            self.binders.push(Binder::with_cred(addr, c));
             self
         }
     }
@@ -361,8 +785,12 @@ mod secure_server {
 struct ServerCore {
     server: *mut grpc_server,
     bind_addrs: Vec<(String, u16)>,
+    listening_addrs: Vec<ListeningAddr>,
+    // Credentials may be used by gRPC core until the server is destroyed.
+    _binders: Vec<Binder>,
     slots_per_cq: usize,
     shutdown: AtomicBool,
+    calls: CallCounter,
 }
 
 impl Drop for ServerCore {
@@ -376,10 +804,99 @@ unsafe impl Sync for ServerCore {}
 
 pub type BoxHandler = Box<dyn CloneableHandler>;
 
+#[derive(Default)]
+struct CounterState {
+    count: AtomicUsize,
+    /// Whether in-progress calls are being cancelled by a graceful shutdown.
+    cancelling: AtomicBool,
+    cancelled: AtomicUsize,
+    /// The task to notify when no call is in progress.
+    idle: Mutex<Option<Task>>,
+}
+
+/// Counts the calls that are accepted by a server but not finished yet.
+#[derive(Clone, Default)]
+pub struct CallCounter {
+    state: Arc<CounterState>,
+}
+
+impl CallCounter {
+    /// Count a call until the returned value is dropped.
+    pub fn track(&self) -> TrackedCall {
+        self.state.count.fetch_add(1, Ordering::SeqCst);
+        TrackedCall {
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.state.count.load(Ordering::SeqCst)
+    }
+
+    /// Count the calls that are closed as cancelled from now on.
+    fn start_cancelling(&self) {
+        self.state.cancelling.store(true, Ordering::SeqCst);
+    }
+
+    fn cancelled(&self) -> usize {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns true if no call is in progress, otherwise the current task
+    /// will be notified when it happens.
+    fn poll_idle(&self) -> bool {
+        *self.state.idle.lock().unwrap() = Some(task::current());
+        self.count() == 0
+    }
+}
+
+pub struct TrackedCall {
+    state: Arc<CounterState>,
+}
+
+impl TrackedCall {
+    /// Stop counting the call as it's closed.
+    pub fn close(self, cancelled: bool) {
+        if cancelled && self.state.cancelling.load(Ordering::SeqCst) {
+            self.state.cancelled.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for TrackedCall {
+    fn drop(&mut self) {
+        if self.state.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            if let Some(task) = self.state.idle.lock().unwrap().take() {
+                task.notify();
+            }
+        }
+    }
+}
+
+/// All the handlers of a server.
+struct Registry {
+    handlers: HashMap<&'static [u8], BoxHandler>,
+    /// Handles calls to methods that are not in `handlers`.
+    fallback: Option<BoxHandler>,
+}
+
+impl Registry {
+    fn box_clone(&self) -> Registry {
+        Registry {
+            handlers: self
+                .handlers
+                .iter()
+                .map(|(k, v)| (k.to_owned(), v.box_clone()))
+                .collect(),
+            fallback: self.fallback.as_ref().map(|h| h.box_clone()),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RequestCallContext {
     server: Arc<ServerCore>,
-    registry: Arc<UnsafeCell<HashMap<&'static [u8], BoxHandler>>>,
+    registry: Arc<UnsafeCell<Registry>>,
 }
 
 impl RequestCallContext {
@@ -387,8 +904,12 @@ impl RequestCallContext {
     /// TODO: Is there a better way?
     #[inline]
     pub unsafe fn get_handler(&mut self, path: &[u8]) -> Option<&mut BoxHandler> {
-        let registry = &mut *self.registry.get();
-        registry.get_mut(path)
+        let Registry { handlers, fallback } = &mut *self.registry.get();
+        handlers.get_mut(path).or(fallback.as_mut())
+    }
+
+    pub fn call_counter(&self) -> &CallCounter {
+        &self.server.calls
     }
 }
 
@@ -440,6 +961,42 @@ impl Future for ShutdownFuture {
     }
 }
 
+/// A `Future` that resolves when a graceful shutdown completes.
+///
+/// It yields the number of calls that are closed as cancelled after the
+/// deadline, either by the server or by their clients.
+pub struct GracefulShutdownFuture {
+    shutdown_f: ShutdownFuture,
+    core: Arc<ServerCore>,
+    delay: Option<Delay>,
+}
+
+impl Future for GracefulShutdownFuture {
+    type Item = usize;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<usize, Error> {
+        if let Async::Ready(()) = self.shutdown_f.poll()? {
+            return Ok(Async::Ready(self.core.calls.cancelled()));
+        }
+        if let Some(delay) = self.delay.as_mut() {
+            if !delay.poll() {
+                return Ok(Async::NotReady);
+            }
+            self.delay = None;
+            self.core.calls.start_cancelling();
+            unsafe { grpc_sys::grpc_server_cancel_all_calls(self.core.server) }
+        }
+        // Handlers may hold cancelled calls for arbitrary long, so don't wait
+        // for the shutdown but the calls to be closed.
+        if self.core.calls.poll_idle() {
+            Ok(Async::Ready(self.core.calls.cancelled()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
 /// A gRPC server.
 ///
 /// A single server can serve arbitrary number of services and can listen on more than one port.
@@ -448,12 +1005,19 @@ impl Future for ShutdownFuture {
 pub struct Server {
     env: Arc<Environment>,
     core: Arc<ServerCore>,
-    handlers: HashMap<&'static [u8], BoxHandler>,
+    registry: Registry,
+    shutdown_hooks: Vec<ShutdownHook>,
 }
 
 impl Server {
     /// Shutdown the server asynchronously.
+    ///
+    /// Shutdown hooks registered by services are invoked before the server
+    /// stops accepting new calls.
     pub fn shutdown(&mut self) -> ShutdownFuture {
+        for hook in self.shutdown_hooks.drain(..) {
+            hook();
+        }
         let (cq_f, prom) = CallTag::shutdown_pair();
         let prom_box = Box::new(prom);
         let tag = Box::into_raw(prom_box);
@@ -470,6 +1034,23 @@ impl Server {
         ShutdownFuture { cq_f }
     }
 
+    /// Shutdown the server gracefully.
+    ///
+    /// The server stops accepting new calls and asks clients to stop sending
+    /// calls over existing connections. Calls in progress are allowed to finish
+    /// until `deadline`, and the remaining ones are cancelled then.
+    ///
+    /// After the deadline, the future resolves once all cancelled calls are
+    /// closed, even if their handlers still hold the sinks. The resources of
+    /// the server are released after the handlers drop them.
+    pub fn shutdown_graceful(&mut self, deadline: Instant) -> GracefulShutdownFuture {
+        GracefulShutdownFuture {
+            shutdown_f: self.shutdown(),
+            core: self.core.clone(),
+            delay: Some(Delay::at(deadline)),
+        }
+    }
+
     /// Cancel all in-progress calls.
     ///
     /// Only usable after shutdown.
@@ -484,14 +1065,9 @@ impl Server {
             for cq in self.env.completion_queues() {
                 // Handlers are Send and Clone, but not Sync. So we need to
                 // provide a replica for each completion queue.
-                let registry = self
-                    .handlers
-                    .iter()
-                    .map(|(k, v)| (k.to_owned(), v.box_clone()))
-                    .collect();
                 let rc = RequestCallContext {
                     server: self.core.clone(),
-                    registry: Arc::new(UnsafeCell::new(registry)),
+                    registry: Arc::new(UnsafeCell::new(self.registry.box_clone())),
                 };
                 for _ in 0..self.core.slots_per_cq {
                     request_call(rc.clone(), cq);
@@ -500,10 +1076,19 @@ impl Server {
         }
     }
 
-    /// Get binded addresses.
+    /// Get binded hosts and ports.
+    ///
+    /// Unix domain sockets are not included, use [`Server::listening_addrs`]
+    /// to get all the addresses.
     pub fn bind_addrs(&self) -> &[(String, u16)] {
         &self.core.bind_addrs
     }
+
+    /// Get all the addresses the server listens on, in the order they are
+    /// bound.
+    pub fn listening_addrs(&self) -> &[ListeningAddr] {
+        &self.core.listening_addrs
+    }
 }
 
 impl Drop for Server {
@@ -518,29 +1103,39 @@ impl Drop for Server {
 
 impl Debug for Server {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Server {:?}", self.core.bind_addrs)
+        write!(f, "Server {:?}", self.core.listening_addrs)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::join_host_port;
+    use super::ListeningAddr;
 
     #[test]
-    fn test_join_host_port() {
+    fn test_listening_addr_display() {
         let tbl = vec![
-            ("localhost", 0u16, "localhost:0\0"),
-            ("127.0.0.1", 100u16, "127.0.0.1:100\0"),
-            ("::1", 0u16, "[::1]:0\0"),
+            (ListeningAddr::Tcp("localhost".to_owned(), 0), "localhost:0"),
+            (
+                ListeningAddr::Tcp("127.0.0.1".to_owned(), 100),
+                "127.0.0.1:100",
+            ),
+            (ListeningAddr::Tcp("::1".to_owned(), 0), "[::1]:0"),
+            (
+                ListeningAddr::Tcp("fe80::7376:45d5:fb08:61e3".to_owned(), 10028),
+                "[fe80::7376:45d5:fb08:61e3]:10028",
+            ),
+            (
+                ListeningAddr::Unix("/tmp/grpc.sock".to_owned()),
+                "unix:/tmp/grpc.sock",
+            ),
             (
-                "fe80::7376:45d5:fb08:61e3",
-                10028u16,
-                "[fe80::7376:45d5:fb08:61e3]:10028\0",
+                ListeningAddr::UnixAbstract("grpc".to_owned()),
+                "unix-abstract:grpc",
             ),
         ];
 
-        for (h, p, e) in &tbl {
-            assert_eq!(join_host_port(h, *p), e.to_owned());
+        for (addr, e) in &tbl {
+            assert_eq!(addr.to_string(), *e);
         }
     }
 }
@@ -0,0 +1,428 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Typed builder of the [service config] accepted by gRPC core.
+//!
+//! [service config]: https://github.com/grpc/grpc/blob/master/doc/service_config.md
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::Duration;
+use std::{i32, u32};
+
+use crate::call::retry::RetryPolicy;
+use crate::channel::LbPolicy;
+use crate::error::{Error, Result};
+
+/// Settings of the calls to a set of methods.
+#[derive(Clone, Debug, Default)]
+pub struct MethodConfig {
+    names: Vec<(String, Option<String>)>,
+    wait_for_ready: Option<bool>,
+    timeout: Option<Duration>,
+    max_request_message_bytes: Option<usize>,
+    max_response_message_bytes: Option<usize>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl MethodConfig {
+    /// Create an empty method config that applies to no method.
+    pub fn new() -> MethodConfig {
+        MethodConfig::default()
+    }
+
+    /// Apply the config to all methods of `service`, e.g. `helloworld.Greeter`.
+    ///
+    /// Configs of specific methods take precedence.
+    pub fn service(mut self, service: &str) -> MethodConfig {
+        self.names.push((service.to_owned(), None));
+        self
+    }
+
+    /// Apply the config to `method` of `service`, e.g. `SayHello` of `helloworld.Greeter`.
+    pub fn method(mut self, service: &str, method: &str) -> MethodConfig {
+        self.names
+            .push((service.to_owned(), Some(method.to_owned())));
+        self
+    }
+
+    /// Set whether calls wait for the channel to become ready instead of
+    /// failing fast when it's in transient failure.
+    pub fn wait_for_ready(mut self, wait_for_ready: bool) -> MethodConfig {
+        self.wait_for_ready = Some(wait_for_ready);
+        self
+    }
+
+    /// Set the default timeout of calls.
+    ///
+    /// The smaller one is used if a call also specifies a timeout.
+    pub fn timeout(mut self, timeout: Duration) -> MethodConfig {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum size of a request message.
+    pub fn max_request_message_bytes(mut self, bytes: usize) -> MethodConfig {
+        self.max_request_message_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum size of a response message.
+    pub fn max_response_message_bytes(mut self, bytes: usize) -> MethodConfig {
+        self.max_response_message_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the policy for retrying calls.
+    ///
+    /// Retries are made by gRPC core, which requires `max_attempts` to be at
+    /// least 2 and caps it at 5.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> MethodConfig {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    fn validate(&self, names: &mut HashSet<(String, Option<String>)>) -> Result<()> {
+        if self.names.is_empty() {
+            return Err(invalid("method config doesn't apply to any method"));
+        }
+        for name in &self.names {
+            let valid = |s: &str| !s.is_empty() && !s.contains('/');
+            if !valid(&name.0) || !name.1.as_ref().map_or(true, |m| valid(m)) {
+                return Err(invalid(format!("invalid method name {:?}", name)));
+            }
+            if !names.insert(name.clone()) {
+                return Err(invalid(format!("duplicated method name {:?}", name)));
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            check_duration("timeout", timeout)?;
+        }
+        for bytes in &[
+            self.max_request_message_bytes,
+            self.max_response_message_bytes,
+        ] {
+            if bytes.map_or(false, |b| b > i32::MAX as usize) {
+                return Err(invalid(format!("message size {:?} is too large", bytes)));
+            }
+        }
+        if let Some(ref policy) = self.retry_policy {
+            if policy.max_attempts < 2 {
+                return Err(invalid("max attempts of retry policy must be at least 2"));
+            }
+            check_duration("initial backoff", policy.initial_backoff)?;
+            check_duration("max backoff", policy.max_backoff)?;
+            if !is_positive(policy.backoff_multiplier) {
+                return Err(invalid("backoff multiplier must be positive"));
+            }
+            if policy.retryable_codes.is_empty() {
+                return Err(invalid("retryable status codes must not be empty"));
+            }
+            for code in &policy.retryable_codes {
+                match code.name() {
+                    Some("OK") | Some("DO_NOT_USE") | None => {
+                        return Err(invalid(format!("{:?} is not retryable", code)));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"name\":[");
+        for (i, (service, method)) in self.names.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"service\":");
+            write_str(out, service);
+            if let Some(method) = method {
+                out.push_str(",\"method\":");
+                write_str(out, method);
+            }
+            out.push('}');
+        }
+        out.push(']');
+        if let Some(wait_for_ready) = self.wait_for_ready {
+            write!(out, ",\"waitForReady\":{}", wait_for_ready).unwrap();
+        }
+        if let Some(timeout) = self.timeout {
+            out.push_str(",\"timeout\":");
+            write_duration(out, timeout);
+        }
+        if let Some(bytes) = self.max_request_message_bytes {
+            write!(out, ",\"maxRequestMessageBytes\":{}", bytes).unwrap();
+        }
+        if let Some(bytes) = self.max_response_message_bytes {
+            write!(out, ",\"maxResponseMessageBytes\":{}", bytes).unwrap();
+        }
+        if let Some(ref policy) = self.retry_policy {
+            write!(
+                out,
+                ",\"retryPolicy\":{{\"maxAttempts\":{},\"initialBackoff\":",
+                policy.max_attempts
+            )
+            .unwrap();
+            write_duration(out, policy.initial_backoff);
+            out.push_str(",\"maxBackoff\":");
+            write_duration(out, policy.max_backoff);
+            write!(
+                out,
+                ",\"backoffMultiplier\":{:?},\"retryableStatusCodes\":[",
+                policy.backoff_multiplier
+            )
+            .unwrap();
+            for (i, code) in policy.retryable_codes.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(out, code.name().unwrap());
+            }
+            out.push_str("]}");
+        }
+        out.push('}');
+    }
+}
+
+/// Builder of [`ServiceConfig`].
+#[derive(Default)]
+pub struct ServiceConfigBuilder {
+    lb_policy: Option<LbPolicy>,
+    method_configs: Vec<MethodConfig>,
+    retry_throttling: Option<(u32, f64)>,
+}
+
+impl ServiceConfigBuilder {
+    /// Initialize a new [`ServiceConfigBuilder`].
+    pub fn new() -> ServiceConfigBuilder {
+        ServiceConfigBuilder::default()
+    }
+
+    /// Set the load balancing policy.
+    pub fn load_balancing_policy(mut self, lb_policy: LbPolicy) -> ServiceConfigBuilder {
+        self.lb_policy = Some(lb_policy);
+        self
+    }
+
+    /// Add settings of a set of methods.
+    pub fn method_config(mut self, config: MethodConfig) -> ServiceConfigBuilder {
+        self.method_configs.push(config);
+        self
+    }
+
+    /// Throttle retries made by gRPC core.
+    ///
+    /// Every failed call takes one token away, and every successful call gives
+    /// back `token_ratio` tokens. Retries are only allowed while there are more
+    /// than half of `max_tokens` tokens left.
+    pub fn retry_throttling(mut self, max_tokens: u32, token_ratio: f64) -> ServiceConfigBuilder {
+        self.retry_throttling = Some((max_tokens, token_ratio));
+        self
+    }
+
+    /// Validate the settings and build the [`ServiceConfig`].
+    pub fn build(self) -> Result<ServiceConfig> {
+        let mut names = HashSet::new();
+        for config in &self.method_configs {
+            config.validate(&mut names)?;
+        }
+        if let Some((max_tokens, token_ratio)) = self.retry_throttling {
+            if max_tokens == 0 || max_tokens > 1000 {
+                return Err(invalid("max tokens must be in range [1, 1000]"));
+            }
+            if !is_positive(token_ratio) {
+                return Err(invalid("token ratio must be positive"));
+            }
+        }
+
+        let mut json = String::from("{");
+        if let Some(lb_policy) = self.lb_policy {
+            let name = match lb_policy {
+                LbPolicy::PickFirst => "pick_first",
+                LbPolicy::RoundRobin => "round_robin",
+            };
+            write!(json, "\"loadBalancingConfig\":[{{\"{}\":{{}}}}]", name).unwrap();
+        }
+        if !self.method_configs.is_empty() {
+            if json.len() > 1 {
+                json.push(',');
+            }
+            json.push_str("\"methodConfig\":[");
+            for (i, config) in self.method_configs.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                config.write_json(&mut json);
+            }
+            json.push(']');
+        }
+        if let Some((max_tokens, token_ratio)) = self.retry_throttling {
+            if json.len() > 1 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "\"retryThrottling\":{{\"maxTokens\":{},\"tokenRatio\":{:?}}}",
+                max_tokens, token_ratio
+            )
+            .unwrap();
+        }
+        json.push('}');
+
+        let enable_retries = self.method_configs.iter().any(|c| c.retry_policy.is_some());
+        Ok(ServiceConfig {
+            json,
+            enable_retries,
+        })
+    }
+}
+
+/// A validated service config.
+///
+/// Use [`ChannelBuilder::service_config`] to apply it to a channel.
+///
+/// [`ChannelBuilder::service_config`]: ./struct.ChannelBuilder.html#method.service_config
+#[derive(Clone, Debug)]
+pub struct ServiceConfig {
+    json: String,
+    enable_retries: bool,
+}
+
+impl ServiceConfig {
+    /// Get the config in the JSON format understood by gRPC core.
+    pub fn as_json(&self) -> &str {
+        &self.json
+    }
+
+    pub(crate) fn enable_retries(&self) -> bool {
+        self.enable_retries
+    }
+}
+
+fn invalid<S: Into<String>>(msg: S) -> Error {
+    Error::InvalidServiceConfig(msg.into())
+}
+
+fn is_positive(f: f64) -> bool {
+    f.is_finite() && f > 0.0
+}
+
+fn check_duration(name: &str, d: Duration) -> Result<()> {
+    if d == Duration::from_secs(0) || d.as_secs() > u64::from(u32::MAX) {
+        return Err(invalid(format!("{} {:?} is out of range", name, d)));
+    }
+    Ok(())
+}
+
+/// Writes the duration in the JSON format of `google.protobuf.Duration`.
+fn write_duration(out: &mut String, d: Duration) {
+    write!(out, "\"{}.{:09}s\"", d.as_secs(), d.subsec_nanos()).unwrap();
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RpcStatusCode;
+
+    #[test]
+    fn test_service_config_json() {
+        let cfg = ServiceConfigBuilder::new().build().unwrap();
+        assert_eq!(cfg.as_json(), "{}");
+        assert!(!cfg.enable_retries());
+
+        let cfg = ServiceConfigBuilder::new()
+            .load_balancing_policy(LbPolicy::RoundRobin)
+            .method_config(
+                MethodConfig::new()
+                    .service("helloworld.Greeter")
+                    .method("echo.\"Echo\"", "Echo")
+                    .wait_for_ready(true)
+                    .timeout(Duration::from_millis(1500))
+                    .max_request_message_bytes(1024)
+                    .max_response_message_bytes(2048),
+            )
+            .method_config(
+                MethodConfig::new()
+                    .method("helloworld.Greeter", "SayHello")
+                    .retry_policy(RetryPolicy::new(3).retryable_status_codes(vec![
+                        RpcStatusCode::UNAVAILABLE,
+                        RpcStatusCode::ABORTED,
+                    ])),
+            )
+            .retry_throttling(10, 0.5)
+            .build()
+            .unwrap();
+        let expected = concat!(
+            r#"{"loadBalancingConfig":[{"round_robin":{}}],"methodConfig":["#,
+            r#"{"name":[{"service":"helloworld.Greeter"},{"service":"echo.\"Echo\"","method":"Echo"}],"#,
+            r#""waitForReady":true,"timeout":"1.500000000s","#,
+            r#""maxRequestMessageBytes":1024,"maxResponseMessageBytes":2048},"#,
+            r#"{"name":[{"service":"helloworld.Greeter","method":"SayHello"}],"#,
+            r#""retryPolicy":{"maxAttempts":3,"initialBackoff":"0.100000000s","#,
+            r#""maxBackoff":"1.000000000s","backoffMultiplier":2.0,"#,
+            r#""retryableStatusCodes":["UNAVAILABLE","ABORTED"]}}],"#,
+            r#""retryThrottling":{"maxTokens":10,"tokenRatio":0.5}}"#,
+        );
+        assert_eq!(cfg.as_json(), expected);
+        assert!(cfg.enable_retries());
+    }
+
+    #[test]
+    fn test_service_config_validation() {
+        let method = || MethodConfig::new().service("helloworld.Greeter");
+        let cases = vec![
+            MethodConfig::new().timeout(Duration::from_secs(1)),
+            MethodConfig::new().service(""),
+            MethodConfig::new().method("helloworld.Greeter", "a/b"),
+            method()
+                .method("echo.Echo", "Echo")
+                .service("helloworld.Greeter"),
+            method().timeout(Duration::from_secs(0)),
+            method().max_request_message_bytes(i32::MAX as usize + 1),
+            method().retry_policy(RetryPolicy::new(1)),
+            method().retry_policy(RetryPolicy::new(2).initial_backoff(Duration::from_secs(0))),
+            method().retry_policy(RetryPolicy::new(2).backoff_multiplier(0.0)),
+            method().retry_policy(RetryPolicy::new(2).retryable_status_codes(vec![])),
+            method()
+                .retry_policy(RetryPolicy::new(2).retryable_status_codes(vec![RpcStatusCode::OK])),
+            method().retry_policy(
+                RetryPolicy::new(2).retryable_status_codes(vec![RpcStatusCode::from(100)]),
+            ),
+        ];
+        for config in cases {
+            let res = ServiceConfigBuilder::new()
+                .method_config(config.clone())
+                .build();
+            match res {
+                Err(Error::InvalidServiceConfig(_)) => {}
+                res => panic!("{:?} should be invalid, but get {:?}", config, res),
+            }
+        }
+
+        let res = ServiceConfigBuilder::new()
+            .method_config(method())
+            .method_config(method())
+            .build();
+        assert!(res.is_err());
+        for &(max_tokens, token_ratio) in &[(0, 1.0), (1001, 1.0), (10, 0.0)] {
+            let res = ServiceConfigBuilder::new()
+                .retry_throttling(max_tokens, token_ratio)
+                .build();
+            assert!(res.is_err(), "{} {}", max_tokens, token_ratio);
+        }
+    }
+}
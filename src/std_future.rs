@@ -0,0 +1,205 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Implementations of `std::future::Future` and futures 0.3 `Stream`/`Sink`
+//! for the futures 0.1 based types of this crate.
+//!
+//! All of the types are driven by the same futures 0.1 state machines. When
+//! polled by a std task, a futures 0.1 task is set up to forward notifications
+//! to the std waker.
+
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll as StdPoll, Waker};
+
+use futures::executor::{self, Notify};
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use futures03::compat::Compat;
+use futures03::task::noop_waker_ref;
+use futures03::{FutureExt, Sink as Sink03, SinkExt, Stream as Stream03, StreamExt};
+
+use crate::call::client::{
+    ClientCStreamReceiver, ClientDuplexReceiver, ClientSStreamReceiver, ClientUnaryReceiver,
+    StreamingCallSink,
+};
+use crate::call::server::{
+    CancellationFuture, ClientStreamingSinkResult, DuplexSink, DuplexSinkFailure, RequestStream,
+    ServerStreamingSink, ServerStreamingSinkFailure, UnarySinkResult,
+};
+use crate::call::RpcStatus;
+use crate::channel::{ConnectedFuture, ConnectivityStateStream, StateChangeFuture};
+use crate::error::{Error, Result};
+use crate::grpc_sys::grpc_call_error;
+use crate::server::{GracefulShutdownFuture, ShutdownFuture};
+use crate::task::CqFuture;
+use crate::WriteFlags;
+
+/// The responses of a streaming call returned by async handlers.
+///
+/// The call is failed with the status once an error is yielded.
+pub type ResponseStream<T> =
+    Pin<Box<dyn Stream03<Item = std::result::Result<T, RpcStatus>> + Send>>;
+
+/// Forwards futures 0.1 notifications to a std waker.
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _: usize) {
+        self.0.wake_by_ref();
+    }
+}
+
+/// Calls `f` within a futures 0.1 task that wakes up the task of `cx`.
+fn poll_01<T, R, F>(t: &mut T, cx: &mut Context<'_>, f: F) -> R
+where
+    F: FnOnce(&mut T) -> R,
+{
+    let notify = Arc::new(WakerNotify(cx.waker().clone()));
+    executor::spawn(t).poll_fn_notify(&notify, 0, |t| f(&mut **t))
+}
+
+fn into_std_poll<T>(p: Poll<T, Error>) -> StdPoll<Result<T>> {
+    match p {
+        Ok(Async::Ready(t)) => StdPoll::Ready(Ok(t)),
+        Ok(Async::NotReady) => StdPoll::Pending,
+        Err(e) => StdPoll::Ready(Err(e)),
+    }
+}
+
+/// Converts a std future into a futures 0.1 future, so that it can be driven
+/// by the executor of a completion queue.
+pub(crate) fn into_future_01<F>(f: F) -> impl Future<Item = (), Error = ()> + Send + 'static
+where
+    F: StdFuture<Output = ()> + Send + 'static,
+{
+    Compat::new(Box::pin(f.map(Ok::<(), ()>)))
+}
+
+macro_rules! impl_std_future {
+    ($($t:ident $(<$g:ident>)?),* $(,)?) => {
+        $(
+            impl$(<$g>)? Unpin for $t$(<$g>)? {}
+
+            impl$(<$g>)? StdFuture for $t$(<$g>)? {
+                type Output = Result<<Self as Future>::Item>;
+
+                fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> StdPoll<Self::Output> {
+                    into_std_poll(poll_01(self.get_mut(), cx, Future::poll))
+                }
+            }
+        )*
+    };
+}
+
+impl_std_future!(
+    CqFuture<T>,
+    ShutdownFuture,
+    GracefulShutdownFuture,
+    ClientUnaryReceiver<T>,
+    ClientCStreamReceiver<T>,
+    UnarySinkResult,
+    ClientStreamingSinkResult,
+    ServerStreamingSinkFailure,
+    DuplexSinkFailure,
+    StateChangeFuture,
+    ConnectedFuture,
+    CancellationFuture,
+);
+
+macro_rules! impl_stream_03 {
+    ($($t:ident $(<$g:ident>)?),* $(,)?) => {
+        $(
+            impl$(<$g>)? Unpin for $t$(<$g>)? {}
+
+            impl$(<$g>)? Stream03 for $t$(<$g>)? {
+                type Item = Result<<Self as Stream>::Item>;
+
+                fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> StdPoll<Option<Self::Item>> {
+                    match poll_01(self.get_mut(), cx, Stream::poll) {
+                        Ok(Async::Ready(Some(t))) => StdPoll::Ready(Some(Ok(t))),
+                        Ok(Async::Ready(None)) => StdPoll::Ready(None),
+                        Ok(Async::NotReady) => StdPoll::Pending,
+                        Err(e) => StdPoll::Ready(Some(Err(e))),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_stream_03!(
+    ClientSStreamReceiver<T>,
+    ClientDuplexReceiver<T>,
+    RequestStream<T>,
+    ConnectivityStateStream,
+);
+
+macro_rules! impl_sink_03 {
+    ($($t:ident<$g:ident>),* $(,)?) => {
+        $(
+            impl<$g> Unpin for $t<$g> {}
+
+            impl<$g> Sink03<($g, WriteFlags)> for $t<$g> {
+                type Error = Error;
+
+                /// The sink can only buffer one message, so it's ready only
+                /// when the previous message has been flushed.
+                fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> StdPoll<Result<()>> {
+                    into_std_poll(poll_01(self.get_mut(), cx, Sink::poll_complete))
+                }
+
+                /// Returns an error if the previous message is still pending, which
+                /// means `poll_ready` is not called or hasn't returned ready yet.
+                fn start_send(self: Pin<&mut Self>, item: ($g, WriteFlags)) -> Result<()> {
+                    // The message is sent directly as `poll_ready` makes sure there
+                    // is no pending message. Any notification registered here is
+                    // overridden by following `poll_flush` or `poll_ready`.
+                    let mut cx = Context::from_waker(noop_waker_ref());
+                    match poll_01(self.get_mut(), &mut cx, |s| s.start_send(item))? {
+                        AsyncSink::Ready => Ok(()),
+                        // Same as what gRPC core reports when starting two writes
+                        // at the same time.
+                        AsyncSink::NotReady(_) => Err(Error::CallFailure(
+                            grpc_call_error::GRPC_CALL_ERROR_TOO_MANY_OPERATIONS,
+                        )),
+                    }
+                }
+
+                fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> StdPoll<Result<()>> {
+                    into_std_poll(poll_01(self.get_mut(), cx, Sink::poll_complete))
+                }
+
+                fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> StdPoll<Result<()>> {
+                    into_std_poll(poll_01(self.get_mut(), cx, Sink::close))
+                }
+            }
+        )*
+    };
+}
+
+impl_sink_03!(StreamingCallSink<T>, ServerStreamingSink<T>, DuplexSink<T>,);
+
+macro_rules! impl_forward_response {
+    ($($fn_name:ident, $sink:ident;)*) => {
+        $(
+            /// Sends all responses to the client and finishes the call.
+            pub(crate) async fn $fn_name<T>(
+                mut stream: ResponseStream<T>,
+                mut sink: $sink<T>,
+            ) -> Result<()> {
+                while let Some(res) = stream.next().await {
+                    match res {
+                        Ok(resp) => SinkExt::send(&mut sink, (resp, WriteFlags::default())).await?,
+                        Err(status) => return sink.fail(status).await,
+                    }
+                }
+                SinkExt::close(&mut sink).await
+            }
+        )*
+    };
+}
+
+impl_forward_response!(
+    forward_server_streaming, ServerStreamingSink;
+    forward_duplex_streaming, DuplexSink;
+);
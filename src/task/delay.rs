@@ -0,0 +1,212 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Condvar, Mutex, Once};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use futures::task::{self, Task};
+
+#[derive(Default)]
+struct TimerState {
+    /// Deadlines of all registered timers, the earliest on the top. Entries of
+    /// cancelled timers are skipped when they expire.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    tasks: HashMap<u64, Task>,
+    next_id: u64,
+}
+
+/// A thread that wakes up tasks when their timers fire.
+///
+/// It's shared by all timers, so there is at most one extra thread no matter
+/// how many timers are pending.
+#[derive(Default)]
+struct Timer {
+    state: Mutex<TimerState>,
+    cond: Condvar,
+}
+
+impl Timer {
+    fn global() -> &'static Timer {
+        static INIT: Once = Once::new();
+        static mut TIMER: Option<&'static Timer> = None;
+        unsafe {
+            INIT.call_once(|| {
+                let timer: &'static Timer = Box::leak(Box::new(Timer::default()));
+                Builder::new()
+                    .name("grpc-timer".to_owned())
+                    .spawn(move || timer.run())
+                    .unwrap();
+                TIMER = Some(timer);
+            });
+            TIMER.unwrap()
+        }
+    }
+
+    fn register(&self, deadline: Instant, task: Task) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.tasks.insert(id, task);
+        let earliest = state
+            .deadlines
+            .peek()
+            .map_or(true, |Reverse((d, _))| deadline < *d);
+        state.deadlines.push(Reverse((deadline, id)));
+        if earliest {
+            self.cond.notify_one();
+        }
+        id
+    }
+
+    /// Updates the task to notify, returns false if the timer has fired.
+    fn update(&self, id: u64, task: Task) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.tasks.get_mut(&id) {
+            Some(t) => {
+                *t = task;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cancel(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.tasks.remove(&id);
+        // Don't let cancelled timers with far deadlines pile up.
+        if state.deadlines.len() > 2 * state.tasks.len() + 64 {
+            let TimerState {
+                deadlines, tasks, ..
+            } = &mut *state;
+            *deadlines = deadlines
+                .drain()
+                .filter(|Reverse((_, id))| tasks.contains_key(id))
+                .collect();
+        }
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut fired = vec![];
+            while let Some(Reverse((deadline, id))) = state.deadlines.peek().cloned() {
+                if deadline > now {
+                    break;
+                }
+                state.deadlines.pop();
+                fired.extend(state.tasks.remove(&id));
+            }
+            if !fired.is_empty() {
+                drop(state);
+                for task in fired {
+                    task.notify();
+                }
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            state = match state.deadlines.peek() {
+                Some(Reverse((deadline, _))) => {
+                    let timeout = *deadline - now;
+                    self.cond.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.cond.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+/// A timer that notifies the current task when it fires.
+///
+/// All timers are driven by one shared thread, and a timer is cancelled
+/// when it's dropped.
+pub struct Delay {
+    deadline: Instant,
+    id: Option<u64>,
+}
+
+impl Delay {
+    pub fn new(timeout: Duration) -> Delay {
+        Delay::at(Instant::now() + timeout)
+    }
+
+    pub fn at(deadline: Instant) -> Delay {
+        Delay { deadline, id: None }
+    }
+
+    /// Returns true if the timer fires, otherwise the current task will be
+    /// notified when it does.
+    pub fn poll(&mut self) -> bool {
+        if Instant::now() >= self.deadline {
+            return true;
+        }
+        let timer = Timer::global();
+        match self.id {
+            // The timer may fire right after the check above.
+            Some(id) => !timer.update(id, task::current()),
+            None => {
+                self.id = Some(timer.register(self.deadline, task::current()));
+                false
+            }
+        }
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            Timer::global().cancel(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{future, Async, Future};
+    use std::thread;
+
+    fn wait(mut delay: Delay) {
+        future::poll_fn(move || -> futures::Poll<(), ()> {
+            if delay.poll() {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_delay() {
+        let start = Instant::now();
+        let handles: Vec<_> = [300, 100, 200]
+            .iter()
+            .map(|ms| {
+                let delay = Delay::new(Duration::from_millis(*ms));
+                thread::spawn(move || {
+                    wait(delay);
+                    Instant::now()
+                })
+            })
+            .collect();
+        // A cancelled timer should not block others.
+        let mut cancelled = Delay::new(Duration::from_millis(50));
+        future::poll_fn(|| -> futures::Poll<(), ()> {
+            assert!(!cancelled.poll());
+            Ok(Async::Ready(()))
+        })
+        .wait()
+        .unwrap();
+        drop(cancelled);
+
+        for (h, ms) in handles.into_iter().zip(&[300, 100, 200]) {
+            let fired = h.join().unwrap();
+            assert!(fired - start >= Duration::from_millis(*ms));
+        }
+    }
+}
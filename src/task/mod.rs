@@ -1,6 +1,7 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
 mod callback;
+mod delay;
 mod executor;
 mod lock;
 mod promise;
@@ -13,13 +14,15 @@ use futures::{Async, Future, Poll};
 
 use self::callback::{Abort, Request as RequestCallback, UnaryRequest as UnaryRequestCallback};
 use self::executor::SpawnTask;
-use self::promise::{Batch as BatchPromise, Shutdown as ShutdownPromise};
-use crate::call::server::RequestContext;
+use self::promise::{Action as ActionPromise, Batch as BatchPromise, Shutdown as ShutdownPromise};
+use crate::call::client::ResponseMetadata;
+use crate::call::server::{CloseNotifier, RequestContext};
 use crate::call::{BatchContext, Call, MessageReader};
 use crate::cq::CompletionQueue;
 use crate::error::{Error, Result};
-use crate::server::RequestCallContext;
+use crate::server::{RequestCallContext, TrackedCall};
 
+pub(crate) use self::delay::Delay;
 pub(crate) use self::executor::{Executor, Kicker, UnfinishedWork};
 pub use self::lock::SpinLock;
 pub use self::promise::BatchType;
@@ -115,6 +118,7 @@ pub enum CallTag {
     UnaryRequest(UnaryRequestCallback),
     Abort(Abort),
     Shutdown(ShutdownPromise),
+    Action(ActionPromise),
     Spawn(Arc<SpawnTask>),
 }
 
@@ -126,6 +130,41 @@ impl CallTag {
         (CqFuture::new(inner), CallTag::Batch(batch))
     }
 
+    /// Generate a Future/CallTag pair for the batch job that waits for a
+    /// server call to finish.
+    ///
+    /// `tracked` is dropped along with the tag, and `close` is notified when
+    /// the call is finished.
+    pub fn server_finish_pair(
+        tracked: TrackedCall,
+        close: CloseNotifier,
+    ) -> (BatchFuture, CallTag) {
+        let inner = new_inner();
+        let mut batch = BatchPromise::new(BatchType::Finish, inner.clone());
+        batch.on_server_close(tracked, close);
+        (CqFuture::new(inner), CallTag::Batch(batch))
+    }
+
+    /// Generate a Future/CallTag pair for batch jobs that receive response metadata.
+    ///
+    /// The metadata is stored into `headers` and `trailers` respectively before
+    /// the future is resolved.
+    pub fn batch_pair_with_metadata(
+        ty: BatchType,
+        headers: Option<&ResponseMetadata>,
+        trailers: Option<&ResponseMetadata>,
+    ) -> (BatchFuture, CallTag) {
+        let inner = new_inner();
+        let mut batch = BatchPromise::new(ty, inner.clone());
+        if let Some(meta) = headers {
+            batch.collect_headers(meta.clone());
+        }
+        if let Some(meta) = trailers {
+            batch.collect_trailers(meta.clone());
+        }
+        (CqFuture::new(inner), CallTag::Batch(batch))
+    }
+
     /// Generate a CallTag for request job. We don't have an eventloop
     /// to pull the future, so just the tag is enough.
     pub fn request(ctx: RequestCallContext) -> CallTag {
@@ -139,6 +178,14 @@ impl CallTag {
         (CqFuture::new(inner), CallTag::Shutdown(shutdown))
     }
 
+    /// Generate a Future/CallTag pair for actions that only report whether
+    /// they succeed, e.g. watching the connectivity state of a channel.
+    pub fn action_pair() -> (CqFuture<bool>, CallTag) {
+        let inner = new_inner();
+        let action = ActionPromise::new(inner.clone());
+        (CqFuture::new(inner), CallTag::Action(action))
+    }
+
     /// Generate a CallTag for abort call before handler is called.
     pub fn abort(call: Call) -> CallTag {
         CallTag::Abort(Abort::new(call))
@@ -177,6 +224,7 @@ impl CallTag {
             CallTag::UnaryRequest(cb) => cb.resolve(cq, success),
             CallTag::Abort(_) => {}
             CallTag::Shutdown(prom) => prom.resolve(success),
+            CallTag::Action(prom) => prom.resolve(success),
             CallTag::Spawn(notify) => self::executor::resolve(cq, notify, success),
         }
     }
@@ -190,6 +238,7 @@ impl Debug for CallTag {
             CallTag::UnaryRequest(_) => write!(f, "CallTag::UnaryRequest(..)"),
             CallTag::Abort(_) => write!(f, "CallTag::Abort(..)"),
             CallTag::Shutdown(_) => write!(f, "CallTag::Shutdown"),
+            CallTag::Action(_) => write!(f, "CallTag::Action"),
             CallTag::Spawn(_) => write!(f, "CallTag::Spawn"),
         }
     }
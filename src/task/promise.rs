@@ -4,8 +4,11 @@ use std::fmt::{self, Debug, Formatter};
 use std::sync::Arc;
 
 use super::Inner;
+use crate::call::client::ResponseMetadata;
+use crate::call::server::CloseNotifier;
 use crate::call::{BatchContext, MessageReader, RpcStatusCode};
 use crate::error::Error;
+use crate::server::TrackedCall;
 
 /// Batch job type.
 #[derive(PartialEq, Debug)]
@@ -23,6 +26,10 @@ pub struct Batch {
     ty: BatchType,
     ctx: BatchContext,
     inner: Arc<Inner<Option<MessageReader>>>,
+    headers: Option<ResponseMetadata>,
+    trailers: Option<ResponseMetadata>,
+    tracked: Option<TrackedCall>,
+    close: Option<CloseNotifier>,
 }
 
 impl Batch {
@@ -31,6 +38,39 @@ impl Batch {
             ty,
             ctx: BatchContext::new(),
             inner,
+            headers: None,
+            trailers: None,
+            tracked: None,
+            close: None,
+        }
+    }
+
+    /// Keep the server call counted until the batch is finished, and notify
+    /// `close` then.
+    pub fn on_server_close(&mut self, tracked: TrackedCall, close: CloseNotifier) {
+        self.tracked = Some(tracked);
+        self.close = Some(close);
+    }
+
+    /// Store the received initial metadata into `meta` once the batch is finished.
+    pub fn collect_headers(&mut self, meta: ResponseMetadata) {
+        self.headers = Some(meta);
+    }
+
+    /// Store the received trailing metadata into `meta` once the batch is finished.
+    pub fn collect_trailers(&mut self, meta: ResponseMetadata) {
+        self.trailers = Some(meta);
+    }
+
+    fn collect_metadata(&mut self, success: bool) {
+        if !success {
+            return;
+        }
+        if let Some(meta) = self.headers.take() {
+            meta.set_headers(self.ctx.recv_initial_metadata());
+        }
+        if let Some(meta) = self.trailers.take() {
+            meta.set_trailers(self.ctx.recv_trailing_metadata());
         }
     }
 
@@ -83,6 +123,17 @@ impl Batch {
     }
 
     pub fn resolve(mut self, success: bool) {
+        // Metadata should be visible before the future is notified.
+        self.collect_metadata(success);
+        if self.tracked.is_some() || self.close.is_some() {
+            let cancelled = !success || self.ctx.recv_close_on_server_cancelled();
+            if let Some(close) = self.close.take() {
+                close.close(cancelled);
+            }
+            if let Some(tracked) = self.tracked.take() {
+                tracked.close(cancelled);
+            }
+        }
         match self.ty {
             BatchType::CheckRead => {
                 assert!(success);
@@ -126,3 +177,22 @@ impl Shutdown {
         task.map(|t| t.notify());
     }
 }
+
+/// A promise used to resolve actions that only report whether they succeed.
+pub struct Action {
+    inner: Arc<Inner<bool>>,
+}
+
+impl Action {
+    pub fn new(inner: Arc<Inner<bool>>) -> Action {
+        Action { inner }
+    }
+
+    pub fn resolve(self, success: bool) {
+        let task = {
+            let mut guard = self.inner.lock();
+            guard.set_result(Ok(success))
+        };
+        task.map(|t| t.notify());
+    }
+}
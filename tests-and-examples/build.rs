@@ -0,0 +1,56 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+fn main() {
+    #[cfg(all(feature = "std-future", feature = "protobuf-codec"))]
+    generate_async_route_guide();
+    #[cfg(all(feature = "std-future", feature = "prost-codec"))]
+    generate_prost_async_route_guide();
+}
+
+/// Generates `RouteGuide` with async service traits, so that the code generated
+/// by `grpcio-compiler` in async mode is compiled and tested.
+#[cfg(all(feature = "std-future", feature = "protobuf-codec"))]
+fn generate_async_route_guide() {
+    use grpcio_compiler::codegen;
+    use grpcio_compiler::Customize;
+    use protobuf::descriptor::FileDescriptorSet;
+    use protobuf::Message;
+    use std::{env, fs};
+
+    let includes = ["../proto/proto".to_owned()];
+    let proto = "grpc/example/route_guide.proto";
+    let files = [format!("{}/{}", includes[0], proto)];
+    println!("cargo:rerun-if-changed={}", files[0]);
+
+    let out_dir = format!("{}/route_guide_async", env::var("OUT_DIR").unwrap());
+    protobuf_build::generate_files(&includes, &files, &out_dir);
+
+    // Replace the service generated in sink mode.
+    let desc = fs::read(format!("{}/mod.desc", out_dir)).unwrap();
+    let desc = FileDescriptorSet::parse_from_bytes(&desc).unwrap();
+    let customize = Customize {
+        async_service: true,
+    };
+    for res in codegen::gen_with_customize(desc.get_file(), &[proto.to_owned()], &customize) {
+        fs::write(format!("{}/{}", out_dir, res.name), res.content).unwrap();
+    }
+}
+
+/// Same as `generate_async_route_guide`, but the code is generated for prost.
+#[cfg(all(feature = "std-future", feature = "prost-codec"))]
+fn generate_prost_async_route_guide() {
+    use grpcio_compiler::prost_codegen;
+    use grpcio_compiler::Customize;
+    use std::{env, fs};
+
+    let includes = ["../proto/proto"];
+    let protos = ["../proto/proto/grpc/example/route_guide.proto"];
+    println!("cargo:rerun-if-changed={}", protos[0]);
+
+    let out_dir = format!("{}/route_guide_async_prost", env::var("OUT_DIR").unwrap());
+    fs::create_dir_all(&out_dir).unwrap();
+    let customize = Customize {
+        async_service: true,
+    };
+    prost_codegen::compile_protos_with_customize(&protos, &includes, &out_dir, &customize).unwrap();
+}
@@ -0,0 +1,111 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use grpcio_proto::util;
+use std::sync::*;
+
+const CA: &str = include_str!("../../../proto/data/ca.pem");
+const CERT: &str = include_str!("../../../proto/data/server1.pem");
+const KEY: &str = include_str!("../../../proto/data/server1.key");
+
+/// Describes the auth context of the client in the reply.
+#[derive(Clone)]
+struct AuthService;
+
+impl Greeter for AuthService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let msg = match ctx.auth_context() {
+            None => "none".to_owned(),
+            Some(auth) => format!(
+                "{}|{}|{}|{}|{}",
+                auth.peer_is_authenticated(),
+                auth.transport_security_type().unwrap_or_default(),
+                auth.x509_common_name().unwrap_or_default(),
+                auth.x509_subject_alternative_names().join(","),
+                auth.x509_pem_cert().map(str::trim) == Some(CERT.trim()),
+            ),
+        };
+        let mut resp = HelloReply::default();
+        resp.set_message(msg);
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+fn start_server(env: Arc<Environment>, creds: Option<ServerCredentials>) -> Server {
+    let builder = ServerBuilder::new(env).register_service(create_greeter(AuthService));
+    let builder = match creds {
+        Some(creds) => builder.bind_secure("127.0.0.1", 0, creds),
+        None => builder.bind("127.0.0.1", 0),
+    };
+    let mut server = builder.build().unwrap();
+    server.start();
+    server
+}
+
+#[test]
+fn test_mutual_tls_auth_context() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let server_creds = ServerCredentialsBuilder::new()
+        .root_cert(CA, true)
+        .add_cert(CERT.into(), KEY.into())
+        .build();
+    let mut server = start_server(env.clone(), Some(server_creds));
+    let port = server.bind_addrs()[0].1;
+    let channel_creds = ChannelCredentialsBuilder::new()
+        .root_cert(CA.into())
+        .cert(CERT.into(), KEY.into())
+        .build();
+    let ch = ChannelBuilder::new(env)
+        .override_ssl_target("foo.test.google.fr")
+        .secure_connect(&format!("127.0.0.1:{}", port), channel_creds);
+    let client = GreeterClient::new(ch);
+
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert_eq!(
+        resp.get_message(),
+        "true|ssl|*.test.google.com|\
+         *.test.google.fr,waterzooi.test.google.be,*.test.youtube.com,192.168.1.3|true"
+    );
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_tls_auth_context() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = start_server(env.clone(), Some(util::create_test_server_credentials()));
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env)
+        .override_ssl_target("foo.test.google.fr")
+        .secure_connect(
+            &format!("127.0.0.1:{}", port),
+            util::create_test_channel_credentials(),
+        );
+    let client = GreeterClient::new(ch);
+
+    // The client doesn't present a certificate.
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert_eq!(resp.get_message(), "false|ssl|||false");
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_insecure_auth_context() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = start_server(env.clone(), None);
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert_eq!(resp.get_message(), "none");
+
+    let _ = server.shutdown().wait();
+}
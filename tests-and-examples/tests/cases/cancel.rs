@@ -8,6 +8,8 @@ use std::time::Duration;
 use futures::sync::mpsc;
 use futures::{future, stream as streams, Async, Future, Poll, Sink, Stream};
 use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
 use grpcio_proto::example::route_guide::*;
 use grpcio_proto::example::route_guide_grpc::*;
 
@@ -270,3 +272,64 @@ fn test_early_exit() {
 
     rx.recv_timeout(Duration::from_secs(1)).unwrap();
 }
+
+/// Holds every call until it's cancelled, and reports the cancellation.
+#[derive(Clone)]
+struct ScanService {
+    events: Arc<Mutex<std_mpsc::Sender<&'static str>>>,
+}
+
+impl Greeter for ScanService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let events = self.events.lock().unwrap().clone();
+        if !ctx.is_cancelled() {
+            events.send("started").unwrap();
+        }
+        let cancelled = ctx.cancelled();
+        thread::spawn(move || {
+            cancelled.wait().unwrap();
+            events.send("cancelled").unwrap();
+            drop(sink);
+        });
+    }
+}
+
+fn prepare_scan_suite() -> (std_mpsc::Receiver<&'static str>, GreeterClient, Server) {
+    let (tx, rx) = std_mpsc::channel();
+    let service = ScanService {
+        events: Arc::new(Mutex::new(tx)),
+    };
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(service))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    (rx, GreeterClient::new(ch), server)
+}
+
+#[test]
+fn test_server_notified_on_deadline() {
+    let (events, client, _server) = prepare_scan_suite();
+
+    let opt = CallOption::default().timeout(Duration::from_millis(200));
+    match client.say_hello_opt(&HelloRequest::default(), opt) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::DEADLINE_EXCEEDED),
+        res => panic!("expect DEADLINE_EXCEEDED, but get {:?}", res),
+    }
+    assert_eq!(events.recv_timeout(Duration::from_secs(5)), Ok("started"));
+    assert_eq!(events.recv_timeout(Duration::from_secs(5)), Ok("cancelled"));
+}
+
+#[test]
+fn test_server_notified_on_client_cancel() {
+    let (events, client, _server) = prepare_scan_suite();
+
+    let mut rx = client.say_hello_async(&HelloRequest::default()).unwrap();
+    assert_eq!(events.recv_timeout(Duration::from_secs(5)), Ok("started"));
+    rx.cancel();
+    assert_eq!(events.recv_timeout(Duration::from_secs(5)), Ok("cancelled"));
+}
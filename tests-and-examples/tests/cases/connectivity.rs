@@ -0,0 +1,71 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use std::net::TcpListener;
+use std::sync::*;
+use std::time::*;
+
+#[test]
+fn test_connectivity_watch() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env.clone()).connect(&format!("127.0.0.1:{}", port));
+    assert_eq!(
+        ch.check_connectivity_state(false),
+        ConnectivityState::GRPC_CHANNEL_IDLE
+    );
+
+    assert!(ch
+        .wait_for_connected(Duration::from_secs(5))
+        .wait()
+        .unwrap());
+    assert_eq!(
+        ch.check_connectivity_state(false),
+        ConnectivityState::GRPC_CHANNEL_READY
+    );
+
+    // The state doesn't change before the deadline.
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let f = ch.wait_for_state_change(ConnectivityState::GRPC_CHANNEL_READY, deadline);
+    assert!(!f.wait().unwrap());
+
+    let mut states = ch.connectivity_state_stream().wait();
+    assert_eq!(
+        states.next().unwrap().unwrap(),
+        ConnectivityState::GRPC_CHANNEL_READY
+    );
+
+    // Connections are closed after the server is shutdown.
+    let _ = server.shutdown().wait();
+    drop(server);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let f = ch.wait_for_state_change(ConnectivityState::GRPC_CHANNEL_READY, deadline);
+    assert!(f.wait().unwrap());
+    assert_ne!(
+        states.next().unwrap().unwrap(),
+        ConnectivityState::GRPC_CHANNEL_READY
+    );
+}
+
+#[test]
+fn test_connect_timeout() {
+    let env = Arc::new(EnvBuilder::new().build());
+    // Reserve a port that nobody listens on.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let ch = ChannelBuilder::new(env).connect(&addr.to_string());
+    let timer = Instant::now();
+    assert!(!ch
+        .wait_for_connected(Duration::from_millis(200))
+        .wait()
+        .unwrap());
+    assert!(timer.elapsed() >= Duration::from_millis(200));
+}
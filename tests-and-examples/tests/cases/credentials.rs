@@ -0,0 +1,128 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use grpcio_proto::util;
+use std::sync::*;
+use std::thread;
+
+/// Replies with the values of all `x-token` headers.
+#[derive(Clone)]
+struct TokenService;
+
+impl Greeter for TokenService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut tokens: Vec<_> = ctx
+            .request_headers()
+            .iter()
+            .filter(|(k, _)| *k == "x-token")
+            .map(|(_, v)| String::from_utf8(v.to_vec()).unwrap())
+            .collect();
+        tokens.sort();
+        let mut resp = HelloReply::default();
+        resp.set_message(tokens.join(","));
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+/// Provides a token, either before returning or from another thread.
+struct TokenPlugin {
+    token: &'static str,
+    asynchronous: bool,
+}
+
+impl AuthMetadataPlugin for TokenPlugin {
+    fn get_metadata(&self, ctx: AuthMetadataContext, callback: AuthMetadataCallback) {
+        assert_eq!(ctx.method_name(), "SayHello");
+        assert!(ctx.service_url().ends_with("/helloworld.Greeter"));
+        let mut builder = MetadataBuilder::new();
+        builder.add_str("x-token", self.token).unwrap();
+        let meta = builder.build();
+        if self.asynchronous {
+            thread::spawn(move || callback.success(meta));
+        } else {
+            callback.success(meta);
+        }
+    }
+}
+
+struct FailPlugin(&'static str);
+
+impl AuthMetadataPlugin for FailPlugin {
+    fn get_metadata(&self, _: AuthMetadataContext, callback: AuthMetadataCallback) {
+        callback.fail(RpcStatus::new(
+            RpcStatusCode::UNAUTHENTICATED,
+            Some(self.0.to_owned()),
+        ));
+    }
+}
+
+fn token_creds(token: &'static str, asynchronous: bool) -> CallCredentials {
+    CallCredentials::from_plugin(TokenPlugin {
+        token,
+        asynchronous,
+    })
+}
+
+#[test]
+fn test_call_credentials() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(TokenService))
+        .bind_secure("127.0.0.1", 0, util::create_test_server_credentials())
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let addr = format!("127.0.0.1:{}", port);
+    let ch = ChannelBuilder::new(env.clone())
+        .override_ssl_target("foo.test.google.fr")
+        .secure_connect(&addr, util::create_test_channel_credentials());
+    let client = GreeterClient::new(ch);
+    let req = HelloRequest::default();
+
+    for &asynchronous in &[false, true] {
+        let opt = CallOption::default().call_credentials(token_creds("a", asynchronous));
+        let resp = client.say_hello_opt(&req, opt).unwrap();
+        assert_eq!(resp.get_message(), "a");
+    }
+
+    let creds = token_creds("a", false).compose(token_creds("b", true));
+    let opt = CallOption::default().call_credentials(creds);
+    let resp = client.say_hello_opt(&req, opt).unwrap();
+    assert_eq!(resp.get_message(), "a,b");
+
+    // NUL bytes can't be passed to gRPC core, they are stripped.
+    for details in &["token service is down", "token service\0 is down"] {
+        let opt = CallOption::default()
+            .call_credentials(CallCredentials::from_plugin(FailPlugin(details)));
+        match client.say_hello_opt(&req, opt) {
+            Err(Error::RpcFailure(s)) => {
+                assert_eq!(s.status, RpcStatusCode::UNAUTHENTICATED);
+                // gRPC core may add some context to the details.
+                let msg = s.details.unwrap();
+                assert!(msg.contains("token service is down"), "{}", msg);
+            }
+            res => panic!("expect failure, but get {:?}", res),
+        }
+    }
+
+    // Credentials of the channel are used by all calls.
+    let creds = util::create_test_channel_credentials().compose(token_creds("c", true));
+    let ch = ChannelBuilder::new(env)
+        .override_ssl_target("foo.test.google.fr")
+        .secure_connect(&addr, creds);
+    let client = GreeterClient::new(ch);
+    let resp = client.say_hello(&req).unwrap();
+    assert_eq!(resp.get_message(), "c");
+    let opt = CallOption::default().call_credentials(token_creds("d", false));
+    let resp = client.say_hello_opt(&req, opt).unwrap();
+    assert_eq!(resp.get_message(), "c,d");
+
+    let _ = server.shutdown().wait();
+}
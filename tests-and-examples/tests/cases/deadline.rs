@@ -0,0 +1,86 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::sync::*;
+use std::thread;
+use std::time::*;
+
+/// Forwards `frontend` calls to itself as `backend` calls with the deadline
+/// propagated. `backend` calls reply with the milliseconds left.
+#[derive(Clone, Default)]
+struct FanoutService {
+    client: Arc<Mutex<Option<GreeterClient>>>,
+}
+
+impl Greeter for FanoutService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, req: HelloRequest, sink: UnarySink<HelloReply>) {
+        let deadline = *ctx.deadline();
+        if req.get_name() == "backend" {
+            let mut resp = HelloReply::default();
+            if deadline.is_infinite() {
+                assert!(deadline.to_instant().is_none());
+                assert!(deadline.to_system_time().is_none());
+                resp.set_message("inf".to_owned());
+            } else {
+                let instant = deadline.to_instant().unwrap();
+                assert!(instant > Instant::now());
+                assert!(deadline.to_system_time().unwrap() > SystemTime::now());
+                resp.set_message(deadline.remaining().as_millis().to_string());
+            }
+            ctx.spawn(
+                sink.success(resp)
+                    .map_err(|e| panic!("failed to reply: {:?}", e)),
+            );
+            return;
+        }
+
+        let client = self.client.lock().unwrap().clone().unwrap();
+        thread::spawn(move || {
+            let mut req = HelloRequest::default();
+            req.set_name("backend".to_owned());
+            let opt = CallOption::default()
+                .timeout(Duration::from_secs(30))
+                .deadline_from(&deadline);
+            let resp = client.say_hello_opt(&req, opt).unwrap();
+            let _ = sink.success(resp).wait();
+        });
+    }
+}
+
+#[test]
+fn test_deadline_propagation() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = FanoutService::default();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(service.clone()))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+    *service.client.lock().unwrap() = Some(client.clone());
+
+    let mut req = HelloRequest::default();
+    req.set_name("frontend".to_owned());
+    let opt = CallOption::default().timeout(Duration::from_secs(5));
+    let resp = client.say_hello_opt(&req, opt).unwrap();
+    let left: u64 = resp.get_message().parse().unwrap();
+    assert!(left > 0 && left <= 5000, "{}", left);
+
+    // The timeout is used if the incoming call has no deadline.
+    let resp = client.say_hello(&req).unwrap();
+    let left: u64 = resp.get_message().parse().unwrap();
+    assert!(left > 5000 && left <= 30000, "{}", left);
+
+    // Nothing is propagated from calls without deadline.
+    req.set_name("backend".to_owned());
+    let resp = client.say_hello(&req).unwrap();
+    assert_eq!(resp.get_message(), "inf");
+
+    let _ = server.shutdown().wait();
+}
@@ -0,0 +1,90 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use grpcio_proto::google::rpc::error_details::*;
+use grpcio_proto::google::rpc::status::Status;
+use grpcio_proto::util;
+use std::sync::*;
+
+#[derive(Clone)]
+struct GreeterService;
+
+impl Greeter for GreeterService {
+    fn say_hello(
+        &mut self,
+        ctx: RpcContext<'_>,
+        req: HelloRequest,
+        mut sink: UnarySink<HelloReply>,
+    ) {
+        let mut violation = BadRequest_FieldViolation::default();
+        violation.set_field("name".to_owned());
+        violation.set_description(format!("{:?} is not allowed", req.get_name()));
+        let mut bad_request = BadRequest::default();
+        bad_request.mut_field_violations().push(violation);
+
+        let mut status = Status::default();
+        status.set_code(RpcStatusCode::INVALID_ARGUMENT.into());
+        status.set_message("invalid name".to_owned());
+        status
+            .mut_details()
+            .push(util::pack_any("google.rpc.BadRequest", &bad_request));
+
+        let mut trailers = MetadataBuilder::new();
+        trailers.add_str("retry-after", "10").unwrap();
+        sink.set_trailers(trailers.build());
+        ctx.spawn(
+            sink.fail(util::new_rpc_status(&status))
+                .map_err(|e| panic!("failed to reply {:?}", e)),
+        );
+    }
+}
+
+#[test]
+fn test_error_details() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = create_greeter(GreeterService);
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let mut req = HelloRequest::default();
+    req.set_name("root".to_owned());
+    let receiver = client.say_hello_async(&req).unwrap();
+    let meta = receiver.response_metadata();
+    let status = match receiver.wait().unwrap_err() {
+        Error::RpcFailure(s) => s,
+        e => panic!("unexpected error: {:?}", e),
+    };
+    assert_eq!(status.status, RpcStatusCode::INVALID_ARGUMENT);
+    assert_eq!(
+        status.details.as_ref().map(String::as_str),
+        Some("invalid name")
+    );
+
+    let details = util::status_details(&status).unwrap();
+    assert_eq!(details.get_message(), "invalid name");
+    assert_eq!(details.get_details().len(), 1);
+    assert!(
+        util::unpack_any::<RetryInfo>("google.rpc.RetryInfo", &details.get_details()[0]).is_none()
+    );
+    let bad_request: BadRequest =
+        util::unpack_any("google.rpc.BadRequest", &details.get_details()[0]).unwrap();
+    let violation = &bad_request.get_field_violations()[0];
+    assert_eq!(violation.get_field(), "name");
+    assert_eq!(violation.get_description(), "\"root\" is not allowed");
+
+    // User defined trailers are kept.
+    let trailers = meta.trailers().unwrap();
+    assert!(trailers
+        .iter()
+        .any(|(k, v)| k == "retry-after" && v == b"10"));
+}
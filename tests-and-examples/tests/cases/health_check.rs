@@ -2,58 +2,26 @@
 
 use futures::*;
 use grpcio::*;
+use grpcio_health::*;
 use grpcio_proto::health::v1::health::*;
 use grpcio_proto::health::v1::health_grpc::*;
-use std::collections::*;
 use std::sync::*;
 
-type StatusRegistry = HashMap<String, HealthCheckResponse_ServingStatus>;
-
-#[derive(Clone)]
-struct HealthService {
-    status: Arc<RwLock<StatusRegistry>>,
-}
-
-impl Health for HealthService {
-    fn check(
-        &mut self,
-        ctx: RpcContext<'_>,
-        req: HealthCheckRequest,
-        sink: UnarySink<HealthCheckResponse>,
-    ) {
-        let status = self.status.read().unwrap();
-        let res = match status.get(req.get_service()) {
-            None => sink.fail(RpcStatus::new(RpcStatusCode::NOT_FOUND, None)),
-            Some(s) => {
-                let mut resp = HealthCheckResponse::default();
-                resp.set_status(*s);
-                sink.success(resp)
-            }
-        };
-        ctx.spawn(res.map_err(|e| println!("failed to report result: {:?}", e)));
-    }
-}
-
 fn check_health(
     client: &HealthClient,
-    status: &Arc<RwLock<StatusRegistry>>,
+    health: &HealthService,
     service: &str,
     exp: HealthCheckResponse_ServingStatus,
 ) {
-    status.write().unwrap().insert(service.to_owned(), exp);
+    health.set_serving_status(service, exp);
     let mut req = HealthCheckRequest::default();
     req.set_service(service.to_owned());
     let status = client.check(&req).unwrap().get_status();
     assert_eq!(status, exp);
 }
 
-#[test]
-fn test_health_check() {
-    let env = Arc::new(Environment::new(1));
-    let status: Arc<RwLock<StatusRegistry>> = Arc::default();
-    let service = create_health(HealthService {
-        status: status.clone(),
-    });
+fn start_server(env: &Arc<Environment>, health: &HealthService) -> (Server, HealthClient) {
+    let service = create_health_service(health.clone());
     let mut server = ServerBuilder::new(env.clone())
         .register_service(service)
         .bind("127.0.0.1", 0)
@@ -62,24 +30,31 @@ fn test_health_check() {
     server.start();
     let (_, port) = server.bind_addrs()[0];
 
-    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
-    let client = HealthClient::new(ch);
+    let ch = ChannelBuilder::new(env.clone()).connect(&format!("127.0.0.1:{}", port));
+    (server, HealthClient::new(ch))
+}
+
+#[test]
+fn test_health_check() {
+    let env = Arc::new(Environment::new(1));
+    let health = HealthService::new();
+    let (_server, client) = start_server(&env, &health);
 
     check_health(
         &client,
-        &status,
+        &health,
         "test",
         HealthCheckResponse_ServingStatus::SERVING,
     );
     check_health(
         &client,
-        &status,
+        &health,
         "test",
         HealthCheckResponse_ServingStatus::NOT_SERVING,
     );
     check_health(
         &client,
-        &status,
+        &health,
         "test",
         HealthCheckResponse_ServingStatus::UNKNOWN,
     );
@@ -92,3 +67,59 @@ fn test_health_check() {
         e => panic!("unexpected error: {:?}", e),
     }
 }
+
+#[test]
+fn test_health_watch() {
+    let env = Arc::new(Environment::new(1));
+    let health = HealthService::new();
+    let (_server, client) = start_server(&env, &health);
+
+    let mut req = HealthCheckRequest::default();
+    req.set_service("test".to_owned());
+    let mut statuses = client.watch(&req).unwrap().wait();
+    let mut next_status = move || statuses.next().unwrap().unwrap().get_status();
+    assert_eq!(
+        next_status(),
+        HealthCheckResponse_ServingStatus::SERVICE_UNKNOWN
+    );
+
+    health.set_serving_status("test", HealthCheckResponse_ServingStatus::SERVING);
+    assert_eq!(next_status(), HealthCheckResponse_ServingStatus::SERVING);
+    // Setting the same status again should not notify watchers.
+    health.set_serving_status("test", HealthCheckResponse_ServingStatus::SERVING);
+    health.set_serving_status("test", HealthCheckResponse_ServingStatus::NOT_SERVING);
+    assert_eq!(
+        next_status(),
+        HealthCheckResponse_ServingStatus::NOT_SERVING
+    );
+}
+
+#[test]
+fn test_health_shutdown() {
+    let env = Arc::new(Environment::new(1));
+    let health = HealthService::new();
+    let (mut server, client) = start_server(&env, &health);
+
+    health.set_serving_status("", HealthCheckResponse_ServingStatus::SERVING);
+    let req = HealthCheckRequest::default();
+    let mut statuses = client.watch(&req).unwrap().wait();
+    let status = statuses.next().unwrap().unwrap().get_status();
+    assert_eq!(status, HealthCheckResponse_ServingStatus::SERVING);
+
+    let f = server.shutdown();
+    let status = statuses.next().unwrap().unwrap().get_status();
+    assert_eq!(status, HealthCheckResponse_ServingStatus::NOT_SERVING);
+    // Watch streams are finished so that shutdown can complete.
+    assert!(statuses.next().is_none());
+    f.wait().unwrap();
+    assert_eq!(
+        health.serving_status(""),
+        Some(HealthCheckResponse_ServingStatus::NOT_SERVING)
+    );
+    // Updates are ignored once the server is shutdown.
+    health.set_serving_status("", HealthCheckResponse_ServingStatus::SERVING);
+    assert_eq!(
+        health.serving_status(""),
+        Some(HealthCheckResponse_ServingStatus::NOT_SERVING)
+    );
+}
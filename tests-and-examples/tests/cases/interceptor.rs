@@ -0,0 +1,309 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::result;
+use std::sync::atomic::*;
+use std::sync::*;
+
+#[derive(Clone)]
+struct GreeterService;
+
+impl Greeter for GreeterService {
+    fn say_hello(
+        &mut self,
+        ctx: RpcContext<'_>,
+        mut req: HelloRequest,
+        sink: UnarySink<HelloReply>,
+    ) {
+        let mut resp = HelloReply::default();
+        resp.set_message(format!("hello {}", req.take_name()));
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply {:?}", e)),
+        );
+    }
+}
+
+fn check_token(ctx: &RpcContext<'_>) -> CheckResult {
+    for (key, value) in ctx.request_headers() {
+        if key == "token" && value == b"secret" {
+            return CheckResult::Continue;
+        }
+    }
+    CheckResult::Abort(RpcStatus::new(
+        RpcStatusCode::UNAUTHENTICATED,
+        Some("missing token".to_owned()),
+    ))
+}
+
+#[test]
+fn test_server_interceptor() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let counter = Arc::new(AtomicUsize::new(0));
+    let c = counter.clone();
+    let count_calls = move |ctx: &RpcContext<'_>| {
+        assert_eq!(ctx.method(), b"/helloworld.Greeter/SayHello");
+        c.fetch_add(1, Ordering::SeqCst);
+        CheckResult::Continue
+    };
+    let service = create_greeter(GreeterService);
+    let mut server = ServerBuilder::new(env.clone())
+        .add_interceptor(count_calls)
+        .add_interceptor(check_token)
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    match client.say_hello(&req).unwrap_err() {
+        Error::RpcFailure(s) => {
+            assert_eq!(s.status, RpcStatusCode::UNAUTHENTICATED);
+            assert_eq!(
+                s.details.as_ref().map(String::as_str),
+                Some("missing token")
+            );
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    let mut builder = MetadataBuilder::new();
+    builder.add_str("token", "secret").unwrap();
+    let opt = CallOption::default().headers(builder.build());
+    let resp = client.say_hello_opt(&req, opt).unwrap();
+    assert_eq!(resp.get_message(), "hello world");
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_service_interceptor() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (tx, rx) = mpsc::channel();
+    let record = move |name: &'static str| {
+        let tx = tx.clone();
+        move |_: &RpcContext<'_>| {
+            tx.send(name).unwrap();
+            CheckResult::Continue
+        }
+    };
+
+    // Build the service by hand to attach a service level interceptor.
+    let method = Method {
+        ty: MethodType::Unary,
+        name: "/helloworld.Greeter/SayHello",
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+    };
+    let service = ServiceBuilder::new()
+        .add_interceptor(record("service"))
+        .add_unary_handler(&method, move |ctx, req, sink| {
+            GreeterService.say_hello(ctx, req, sink)
+        })
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .add_interceptor(record("server"))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert_eq!(resp.get_message(), "hello ");
+    assert_eq!(rx.try_recv().unwrap(), "server");
+    assert_eq!(rx.try_recv().unwrap(), "service");
+    assert!(rx.try_recv().is_err());
+}
+
+struct TokenInjector {
+    statuses: Arc<Mutex<Vec<(String, RpcStatusCode)>>>,
+}
+
+impl ClientInterceptor for TokenInjector {
+    fn before_call(&self, method: &str, opt: &mut CallOption) -> result::Result<(), RpcStatus> {
+        if method.ends_with("/Reject") {
+            return Err(RpcStatus::new(
+                RpcStatusCode::CANCELLED,
+                Some("rejected".to_owned()),
+            ));
+        }
+        let mut builder = MetadataBuilder::new();
+        builder.add_str("token", "secret").unwrap();
+        *opt = opt.clone().headers(builder.build());
+        Ok(())
+    }
+
+    fn on_finish(&self, method: &str, status: &RpcStatus) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .push((method.to_owned(), status.status));
+    }
+}
+
+#[test]
+fn test_client_interceptor() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = create_greeter(GreeterService);
+    let mut server = ServerBuilder::new(env.clone())
+        .add_interceptor(check_token)
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let statuses = Arc::new(Mutex::new(vec![]));
+    let ch = ChannelBuilder::new(env)
+        .add_interceptor(TokenInjector {
+            statuses: statuses.clone(),
+        })
+        .connect(&format!("127.0.0.1:{}", port));
+
+    // Generated clients pick up interceptors from the channel.
+    let client = GreeterClient::new(ch.clone());
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let resp = client.say_hello(&req).unwrap();
+    assert_eq!(resp.get_message(), "hello world");
+    assert_eq!(
+        *statuses.lock().unwrap(),
+        vec![("/helloworld.Greeter/SayHello".to_owned(), RpcStatusCode::OK)]
+    );
+
+    // Interceptors can short-circuit calls.
+    let method = Method {
+        ty: MethodType::Unary,
+        name: "/helloworld.Greeter/Reject",
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+    };
+    let client = Client::new(ch);
+    let res: Result<HelloReply> = client.unary_call(&method, &req, CallOption::default());
+    match res.unwrap_err() {
+        Error::RpcFailure(s) => assert_eq!(s.status, RpcStatusCode::CANCELLED),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    assert_eq!(statuses.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_client_interceptor_observe_failure() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = create_greeter(GreeterService);
+    let mut server = ServerBuilder::new(env.clone())
+        .add_interceptor(check_token)
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+
+    let (tx, rx) = mpsc::channel();
+    struct Observer(Mutex<mpsc::Sender<RpcStatusCode>>);
+    impl ClientInterceptor for Observer {
+        fn on_finish(&self, _: &str, status: &RpcStatus) {
+            self.0.lock().unwrap().send(status.status).unwrap();
+        }
+    }
+    let client = Client::new(ch).add_interceptor(Observer(Mutex::new(tx)));
+    let method = Method {
+        ty: MethodType::ServerStreaming,
+        name: "/helloworld.Greeter/SayHello",
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+    };
+    let req = HelloRequest::default();
+    let stream = client
+        .server_streaming::<_, HelloReply>(&method, &req, CallOption::default())
+        .unwrap();
+    assert!(stream.collect().wait().is_err());
+    assert_eq!(rx.try_recv().unwrap(), RpcStatusCode::UNAUTHENTICATED);
+    assert!(rx.try_recv().is_err());
+}
+
+fn fail_de(_: MessageReader) -> Result<HelloReply> {
+    Err(Error::Codec("broken response".into()))
+}
+
+#[test]
+fn test_client_interceptor_observe_local_failure() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(GreeterService))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let statuses = Arc::new(Mutex::new(vec![]));
+    let client = Client::new(ch).add_interceptor(TokenInjector {
+        statuses: statuses.clone(),
+    });
+
+    // Responses that can't be decoded are reported as internal errors.
+    let mut method = Method {
+        ty: MethodType::Unary,
+        name: "/helloworld.Greeter/SayHello",
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: fail_de,
+        },
+    };
+    let req = HelloRequest::default();
+    match client.unary_call(&method, &req, CallOption::default()) {
+        Err(Error::Codec(_)) => {}
+        res => panic!("expect codec error, but get {:?}", res),
+    }
+
+    // Calls that are dropped before they finish are reported as cancelled.
+    method.ty = MethodType::ServerStreaming;
+    let stream = client
+        .server_streaming(&method, &req, CallOption::default())
+        .unwrap();
+    drop(stream);
+
+    let statuses: Vec<_> = statuses.lock().unwrap().drain(..).map(|(_, s)| s).collect();
+    assert_eq!(
+        statuses,
+        vec![RpcStatusCode::INTERNAL, RpcStatusCode::CANCELLED]
+    );
+
+    let _ = server.shutdown().wait();
+}
@@ -0,0 +1,87 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::sync::*;
+
+/// Only serves local clients.
+#[derive(Clone)]
+struct LocalService;
+
+impl Greeter for LocalService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let is_local = ctx.auth_context().map_or(false, |auth| auth.is_local());
+        let f = if is_local {
+            let mut resp = HelloReply::default();
+            resp.set_message(ctx.peer());
+            sink.success(resp)
+        } else {
+            sink.fail(RpcStatus::new(RpcStatusCode::PERMISSION_DENIED, None))
+        };
+        ctx.spawn(f.map_err(|e| panic!("failed to reply: {:?}", e)));
+    }
+}
+
+#[test]
+fn test_local_tcp_credentials() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(LocalService))
+        .bind_secure(
+            "127.0.0.1",
+            0,
+            ServerCredentials::local(LocalConnectType::LocalTcp),
+        )
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+
+    let ch = ChannelBuilder::new(env.clone()).secure_connect(
+        &format!("127.0.0.1:{}", port),
+        ChannelCredentials::local(LocalConnectType::LocalTcp),
+    );
+    let client = GreeterClient::new(ch);
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert!(resp.get_message().contains("127.0.0.1"), "{:?}", resp);
+
+    // Plaintext clients can't talk to a server with local credentials.
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+    match client.say_hello(&HelloRequest::default()) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        res => panic!("expect UNAVAILABLE, but get {:?}", res),
+    }
+
+    let _ = server.shutdown().wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_uds_credentials() {
+    let path = std::env::temp_dir().join(format!("grpcio-local-{}.sock", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(LocalService))
+        .bind_unix_secure(&path, ServerCredentials::local(LocalConnectType::Uds))
+        .build()
+        .unwrap();
+    server.start();
+    assert_eq!(server.bind_addrs(), &[(format!("unix:{}", path), 0)]);
+
+    let ch = ChannelBuilder::new(env).secure_connect(
+        &format!("unix:{}", path),
+        ChannelCredentials::local(LocalConnectType::Uds),
+    );
+    let client = GreeterClient::new(ch);
+    let resp = client.say_hello(&HelloRequest::default()).unwrap();
+    assert!(resp.get_message().starts_with("unix:"), "{:?}", resp);
+
+    let _ = server.shutdown().wait();
+    let _ = std::fs::remove_file(&path);
+}
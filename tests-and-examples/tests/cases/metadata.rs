@@ -68,3 +68,193 @@ fn test_metadata() {
     let metadata = rx.recv_timeout(Duration::from_secs(1)).unwrap();
     assert_eq!(metadata, ("k1-bin".to_owned(), vec![0x00, 0x01, 0x02]));
 }
+
+#[test]
+fn test_response_metadata() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (tx, _rx) = mpsc::channel();
+    let service = create_greeter(GreeterService { tx: tx });
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch.clone());
+
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let receiver = client.say_hello_async(&req).unwrap();
+    let meta = receiver.response_metadata();
+    let resp = receiver.wait().unwrap();
+    assert_eq!(resp.get_message(), "hello world");
+    assert!(meta.headers().is_some());
+    assert!(meta.trailers().is_some());
+
+    // Trailers are still available when the call fails.
+    let method = Method {
+        ty: MethodType::ServerStreaming,
+        name: "/helloworld.Greeter/Unknown",
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+    };
+    let client = Client::new(ch);
+    let receiver = client
+        .server_streaming::<_, HelloReply>(&method, &req, CallOption::default())
+        .unwrap();
+    let meta = receiver.response_metadata();
+    match receiver.collect().wait().unwrap_err() {
+        Error::RpcFailure(s) => assert_eq!(s.status, RpcStatusCode::UNIMPLEMENTED),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    assert!(meta.trailers().is_some());
+}
+
+#[derive(Clone)]
+struct RetryAfterService;
+
+impl Greeter for RetryAfterService {
+    fn say_hello(
+        &mut self,
+        ctx: RpcContext<'_>,
+        req: HelloRequest,
+        mut sink: UnarySink<HelloReply>,
+    ) {
+        let mut headers = MetadataBuilder::new();
+        headers.add_str("request-id", "42").unwrap();
+        sink.set_headers(headers.build());
+        let mut trailers = MetadataBuilder::new();
+        trailers.add_str("retry-after", "10").unwrap();
+        trailers.add_bytes("debug-bin", &[0x01, 0x02]).unwrap();
+        sink.set_trailers(trailers.build());
+
+        let f = if req.get_name().is_empty() {
+            sink.fail(RpcStatus::new(RpcStatusCode::UNAVAILABLE, None))
+        } else {
+            sink.success(HelloReply::default())
+        };
+        ctx.spawn(f.map_err(|e| panic!("failed to reply {:?}", e)));
+    }
+}
+
+#[test]
+fn test_send_metadata_from_server() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = create_greeter(RetryAfterService);
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let check_trailers = |meta: &ResponseMetadata| {
+        let trailers = meta.trailers().unwrap();
+        let kvs: Vec<_> = trailers.iter().collect();
+        assert_eq!(
+            kvs,
+            vec![
+                ("retry-after", &b"10"[..]),
+                ("debug-bin", &[0x01, 0x02][..])
+            ]
+        );
+    };
+
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let receiver = client.say_hello_async(&req).unwrap();
+    let meta = receiver.response_metadata();
+    receiver.wait().unwrap();
+    let headers = meta.headers().unwrap();
+    assert!(headers.iter().any(|(k, v)| k == "request-id" && v == b"42"));
+    check_trailers(&meta);
+
+    let receiver = client.say_hello_async(&HelloRequest::default()).unwrap();
+    let meta = receiver.response_metadata();
+    match receiver.wait().unwrap_err() {
+        Error::RpcFailure(s) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    check_trailers(&meta);
+}
+
+fn single_metadata(key: &str, value: &str) -> Metadata {
+    let mut builder = MetadataBuilder::new();
+    builder.add_str(key, value).unwrap();
+    builder.build()
+}
+
+#[test]
+fn test_send_metadata_from_streaming_sink() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let method = Method {
+        ty: MethodType::ServerStreaming,
+        name: "/test.Metadata/Stream",
+        req_mar: Marshaller {
+            ser: raw_ser,
+            de: raw_de,
+        },
+        resp_mar: Marshaller {
+            ser: raw_ser,
+            de: raw_de,
+        },
+    };
+    let service = ServiceBuilder::new()
+        .add_server_streaming_handler(&method, |ctx, req, mut sink| {
+            sink.set_headers(single_metadata("request-id", "42"))
+                .unwrap();
+            let f = sink
+                .send((req, WriteFlags::default()))
+                .and_then(|mut sink| {
+                    // Headers are sent with the first message.
+                    assert!(sink.set_headers(single_metadata("late", "1")).is_err());
+                    sink.set_trailers(single_metadata("retry-after", "10"))
+                        .unwrap();
+                    future::poll_fn(move || {
+                        if let Async::NotReady = sink.close()? {
+                            return Ok(Async::NotReady);
+                        }
+                        // The status is sent already.
+                        assert!(sink.set_trailers(single_metadata("late", "1")).is_err());
+                        Ok(Async::Ready(()))
+                    })
+                })
+                .map_err(|e| panic!("failed to reply: {:?}", e));
+            ctx.spawn(f);
+        })
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = Client::new(ch);
+
+    let receiver = client
+        .server_streaming(&method, &b"hello".to_vec(), CallOption::default())
+        .unwrap();
+    let meta = receiver.response_metadata();
+    assert_eq!(receiver.collect().wait().unwrap(), vec![b"hello".to_vec()]);
+    let headers = meta.headers().unwrap();
+    assert!(headers.iter().any(|(k, v)| k == "request-id" && v == b"42"));
+    assert!(!headers.iter().any(|(k, _)| k == "late"));
+    let trailers = meta.trailers().unwrap();
+    let trailers: Vec<_> = trailers.iter().collect();
+    assert_eq!(trailers, vec![("retry-after", &b"10"[..])]);
+
+    let _ = server.shutdown().wait();
+}
@@ -130,3 +130,84 @@ fn test_soundness() {
     }
     assert_eq!(counter.load(Ordering::SeqCst), 9000);
 }
+
+#[cfg(unix)]
+#[derive(Clone)]
+struct PeerService;
+
+#[cfg(unix)]
+impl Greeter for PeerService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut resp = HelloReply::default();
+        resp.set_message(ctx.peer());
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply {:?}", e)),
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_domain_socket() {
+    let path = std::env::temp_dir().join(format!("grpcio-test-{}.sock", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = create_greeter(PeerService);
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind_unix(&path)
+        .build()
+        .unwrap();
+    server.start();
+    assert_eq!(
+        server.listening_addrs(),
+        &[ListeningAddr::Unix(path.clone())]
+    );
+    assert!(server.bind_addrs().is_empty());
+    let ch = ChannelBuilder::new(env).connect_unix(&path);
+    let client = GreeterClient::new(ch);
+
+    let req = HelloRequest::default();
+    let resp = client.say_hello(&req).unwrap();
+    assert!(resp.get_message().starts_with("unix:"), "{:?}", resp);
+
+    drop(server);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_unix_abstract_socket() {
+    let name = format!("grpcio-test-{}", std::process::id());
+    let env = Arc::new(EnvBuilder::new().build());
+    let res = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(PeerService))
+        .bind("127.0.0.1", 0)
+        .bind_unix_abstract(name.as_str())
+        .build();
+    let mut server = match res {
+        Ok(server) => server,
+        // Older versions of gRPC C core don't know the scheme.
+        Err(Error::BindFail(addr, 0)) => {
+            assert_eq!(addr, format!("unix-abstract:{}", name));
+            return;
+        }
+        Err(e) => panic!("unexpected error: {:?}", e),
+    };
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    assert_eq!(
+        server.listening_addrs(),
+        &[
+            ListeningAddr::Tcp("127.0.0.1".to_owned(), port),
+            ListeningAddr::UnixAbstract(name.clone()),
+        ]
+    );
+    let ch = ChannelBuilder::new(env).connect_unix_abstract(&name);
+    let client = GreeterClient::new(ch);
+
+    client.say_hello(&HelloRequest::default()).unwrap();
+}
@@ -1,7 +1,25 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+mod auth_context;
 mod cancel;
+mod connectivity;
+mod credentials;
+mod deadline;
+mod error_details;
 mod health_check;
+mod interceptor;
 mod kick;
+mod local_credentials;
 mod metadata;
 mod misc;
+mod proxy;
+mod reflection;
+mod resolver;
+mod retry;
+mod server_credentials;
+mod service_config;
+mod shutdown;
+#[cfg(all(feature = "std-future", feature = "protobuf-codec"))]
+mod std_future;
+#[cfg(all(feature = "std-future", feature = "prost-codec"))]
+mod std_future_prost;
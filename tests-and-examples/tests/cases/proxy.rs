@@ -0,0 +1,231 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use protobuf::Message;
+use std::sync::*;
+
+#[derive(Clone)]
+struct GreeterService;
+
+impl Greeter for GreeterService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, req: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut resp = HelloReply::default();
+        resp.set_message(format!("hello {}", req.get_name()));
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+/// Forwards all calls to the backend without knowing their types.
+fn forward(
+    client: &Client,
+    ctx: RpcContext<'_>,
+    reqs: RequestStream<Vec<u8>>,
+    sink: DuplexSink<Vec<u8>>,
+) {
+    let path = String::from_utf8(ctx.method().to_vec()).unwrap();
+    let (tx, rx) = client
+        .duplex_streaming_raw(&path, CallOption::default())
+        .unwrap();
+    let send_reqs = tx
+        .send_all(reqs.map(|req| (req, WriteFlags::default())))
+        .map(|_| ())
+        .map_err(|_| ());
+    ctx.spawn(send_reqs);
+    let send_resps = rx
+        .collect()
+        .then(move |res| match res {
+            Ok(resps) => {
+                let resps = resps.into_iter().map(|resp| (resp, WriteFlags::default()));
+                future::Either::A(
+                    sink.send_all(stream::iter_ok::<_, Error>(resps))
+                        .map(|_| ()),
+                )
+            }
+            Err(Error::RpcFailure(status)) => future::Either::B(sink.fail(status)),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        })
+        .map_err(|e| panic!("failed to reply: {:?}", e));
+    ctx.spawn(send_resps);
+}
+
+fn start_backend(env: Arc<Environment>) -> Server {
+    let mut server = ServerBuilder::new(env)
+        .register_service(create_greeter(GreeterService))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    server
+}
+
+fn connect(env: Arc<Environment>, server: &Server) -> Channel {
+    let port = server.bind_addrs()[0].1;
+    ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port))
+}
+
+#[test]
+fn test_raw_unary_call() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut backend = start_backend(env.clone());
+    let client = Client::new(connect(env, &backend));
+
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let payload = req.write_to_bytes().unwrap();
+    let resp = client
+        .unary_call_raw(
+            "/helloworld.Greeter/SayHello",
+            payload,
+            CallOption::default(),
+        )
+        .unwrap();
+    let mut reply = HelloReply::default();
+    reply.merge_from_bytes(&resp).unwrap();
+    assert_eq!(reply.get_message(), "hello world");
+
+    match client.unary_call_raw("/helloworld.Greeter/Unknown", vec![], CallOption::default()) {
+        Err(Error::RpcFailure(ref s)) if s.status == RpcStatusCode::UNIMPLEMENTED => {}
+        res => panic!("expect unimplemented, but get {:?}", res),
+    }
+
+    let _ = backend.shutdown().wait();
+}
+
+#[test]
+fn test_fallback_proxy() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut backend = start_backend(env.clone());
+    let backend_client = Client::new(connect(env.clone(), &backend));
+    let mut proxy = ServerBuilder::new(env.clone())
+        .fallback_handler(move |ctx, reqs, sink| forward(&backend_client, ctx, reqs, sink))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    proxy.start();
+    let ch = connect(env, &proxy);
+
+    let client = GreeterClient::new(ch.clone());
+    let mut req = HelloRequest::default();
+    req.set_name("proxy".to_owned());
+    let resp = client.say_hello(&req).unwrap();
+    assert_eq!(resp.get_message(), "hello proxy");
+
+    // Statuses of the backend are forwarded as well.
+    let client = Client::new(ch);
+    match client.unary_call_raw("/helloworld.Greeter/Unknown", vec![], CallOption::default()) {
+        Err(Error::RpcFailure(ref s)) if s.status == RpcStatusCode::UNIMPLEMENTED => {}
+        res => panic!("expect unimplemented, but get {:?}", res),
+    }
+
+    let _ = proxy.shutdown().wait();
+    let _ = backend.shutdown().wait();
+}
+
+fn say_hi(req: Vec<u8>) -> Vec<u8> {
+    let mut hello = HelloRequest::default();
+    hello.merge_from_bytes(&req).unwrap();
+    let mut resp = HelloReply::default();
+    resp.set_message(format!("hi {}", hello.get_name()));
+    resp.write_to_bytes().unwrap()
+}
+
+/// Serves a method of an older version of `Greeter`, and echoes all the
+/// requests of methods under `/echo.`.
+fn shim(ctx: RpcContext<'_>, reqs: RequestStream<Vec<u8>>, sink: DuplexSink<Vec<u8>>) {
+    let reply: fn(Vec<u8>) -> Vec<u8> = match ctx.method() {
+        b"/helloworld.v1.Greeter/SayHi" => say_hi,
+        path if path.starts_with(b"/echo.") => |req| req,
+        _ => {
+            let status = RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None);
+            ctx.spawn(
+                sink.fail(status)
+                    .map_err(|e| panic!("failed to reply: {:?}", e)),
+            );
+            return;
+        }
+    };
+    let resps = reqs.map(move |req| (reply(req), WriteFlags::default()));
+    ctx.spawn(
+        sink.send_all(resps)
+            .map(|_| ())
+            .map_err(|e| panic!("failed to reply: {:?}", e)),
+    );
+}
+
+fn check_token(ctx: &RpcContext<'_>) -> CheckResult {
+    for (key, value) in ctx.request_headers() {
+        if key == "token" && value == b"secret" {
+            return CheckResult::Continue;
+        }
+    }
+    CheckResult::Abort(RpcStatus::new(RpcStatusCode::UNAUTHENTICATED, None))
+}
+
+#[test]
+fn test_fallback_handler() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(GreeterService))
+        .fallback_handler(shim)
+        .add_interceptor(check_token)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let client = Client::new(connect(env, &server));
+    let opt = || {
+        let mut builder = MetadataBuilder::new();
+        builder.add_str("token", "secret").unwrap();
+        CallOption::default().headers(builder.build())
+    };
+
+    // Registered methods are not affected.
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let payload = req.write_to_bytes().unwrap();
+    let resp = client
+        .unary_call_raw("/helloworld.Greeter/SayHello", payload.clone(), opt())
+        .unwrap();
+    let mut reply = HelloReply::default();
+    reply.merge_from_bytes(&resp).unwrap();
+    assert_eq!(reply.get_message(), "hello world");
+
+    let resp = client
+        .unary_call_raw("/helloworld.v1.Greeter/SayHi", payload.clone(), opt())
+        .unwrap();
+    let mut reply = HelloReply::default();
+    reply.merge_from_bytes(&resp).unwrap();
+    assert_eq!(reply.get_message(), "hi world");
+
+    let (tx, rx) = client
+        .duplex_streaming_raw("/echo.Echo/Echo", opt())
+        .unwrap();
+    let reqs = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    let send_reqs =
+        stream::iter_ok::<_, Error>(reqs.clone()).map(|req| (req, WriteFlags::default()));
+    let (_, resps) = tx.send_all(send_reqs).join(rx.collect()).wait().unwrap();
+    assert_eq!(resps, reqs);
+
+    match client.unary_call_raw("/helloworld.v1.Greeter/Unknown", payload.clone(), opt()) {
+        Err(Error::RpcFailure(ref s)) if s.status == RpcStatusCode::UNIMPLEMENTED => {}
+        res => panic!("expect unimplemented, but get {:?}", res),
+    }
+
+    // Server-wide interceptors are invoked before the fallback handler.
+    match client.unary_call_raw(
+        "/helloworld.v1.Greeter/SayHi",
+        payload,
+        CallOption::default(),
+    ) {
+        Err(Error::RpcFailure(ref s)) if s.status == RpcStatusCode::UNAUTHENTICATED => {}
+        res => panic!("expect unauthenticated, but get {:?}", res),
+    }
+
+    let _ = server.shutdown().wait();
+}
@@ -0,0 +1,140 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld;
+use grpcio_proto::example::helloworld_grpc::FILE_DESCRIPTORS_GREETER;
+use grpcio_proto::reflection::v1alpha::reflection::*;
+use grpcio_proto::reflection::v1alpha::reflection_grpc::*;
+use grpcio_proto::testing::test_grpc::FILE_DESCRIPTORS_TEST_SERVICE;
+use grpcio_reflection::*;
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::Message;
+use std::sync::*;
+
+fn query(
+    client: &ServerReflectionClient,
+    reqs: Vec<ServerReflectionRequest>,
+) -> Vec<ServerReflectionResponse> {
+    let (mut sink, receiver) = client.server_reflection_info().unwrap();
+    for req in reqs {
+        sink = sink.send((req, WriteFlags::default())).wait().unwrap();
+    }
+    future::poll_fn(|| sink.close()).wait().unwrap();
+    receiver.collect().wait().unwrap()
+}
+
+fn file_names(resp: &ServerReflectionResponse) -> Vec<String> {
+    resp.get_file_descriptor_response()
+        .get_file_descriptor_proto()
+        .iter()
+        .map(|bytes| {
+            let mut file = FileDescriptorProto::new();
+            file.merge_from_bytes(bytes).unwrap();
+            file.get_name().to_owned()
+        })
+        .collect()
+}
+
+fn start_server(reflection: ReflectionService) -> (Server, ServerReflectionClient) {
+    let env = Arc::new(Environment::new(1));
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_reflection_service(reflection))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    (server, ServerReflectionClient::new(ch))
+}
+
+#[test]
+fn test_reflection() {
+    let desc = helloworld::file_descriptor_proto()
+        .write_to_bytes()
+        .unwrap();
+    let mut reflection = ReflectionService::new();
+    reflection.add_file_descriptors(&[&desc]).unwrap();
+    let (_server, client) = start_server(reflection);
+
+    let mut list = ServerReflectionRequest::default();
+    list.set_list_services(String::new());
+    let mut by_service = ServerReflectionRequest::default();
+    by_service.set_file_containing_symbol("helloworld.Greeter".to_owned());
+    let mut by_message = ServerReflectionRequest::default();
+    by_message.set_file_containing_symbol("helloworld.HelloRequest".to_owned());
+    let mut by_file = ServerReflectionRequest::default();
+    by_file.set_file_by_filename("grpc/example/helloworld.proto".to_owned());
+    let mut unknown = ServerReflectionRequest::default();
+    unknown.set_file_containing_symbol("helloworld.Unknown".to_owned());
+
+    let resps = query(
+        &client,
+        vec![list, by_service, by_message, by_file, unknown.clone()],
+    );
+    assert_eq!(resps.len(), 5);
+    let services: Vec<_> = resps[0]
+        .get_list_services_response()
+        .get_service()
+        .iter()
+        .map(|s| s.get_name().to_owned())
+        .collect();
+    assert_eq!(services, vec!["helloworld.Greeter".to_owned()]);
+    for resp in &resps[1..4] {
+        assert_eq!(file_names(resp), vec!["grpc/example/helloworld.proto"]);
+    }
+    let err = resps[4].get_error_response();
+    assert_eq!(
+        RpcStatusCode::from(err.get_error_code()),
+        RpcStatusCode::NOT_FOUND
+    );
+    assert_eq!(*resps[4].get_original_request(), unknown);
+}
+
+#[test]
+fn test_reflection_generated_descriptors() {
+    // The constants are generated by the compiler in this repository, and
+    // contain the dependencies of the services as well.
+    let mut reflection = ReflectionService::new();
+    reflection
+        .add_file_descriptors(FILE_DESCRIPTORS_TEST_SERVICE)
+        .unwrap();
+    reflection
+        .add_file_descriptors(FILE_DESCRIPTORS_GREETER)
+        .unwrap();
+    let (_server, client) = start_server(reflection);
+
+    let mut list = ServerReflectionRequest::default();
+    list.set_list_services(String::new());
+    let mut by_service = ServerReflectionRequest::default();
+    by_service.set_file_containing_symbol("grpc.testing.TestService".to_owned());
+    let mut by_dep = ServerReflectionRequest::default();
+    by_dep.set_file_containing_symbol("grpc.testing.SimpleRequest".to_owned());
+
+    let resps = query(&client, vec![list, by_service, by_dep]);
+    let services: Vec<_> = resps[0]
+        .get_list_services_response()
+        .get_service()
+        .iter()
+        .map(|s| s.get_name().to_owned())
+        .collect();
+    assert_eq!(
+        services,
+        vec![
+            "grpc.testing.ReconnectService",
+            "grpc.testing.TestService",
+            "grpc.testing.UnimplementedService",
+            "helloworld.Greeter",
+        ]
+    );
+    assert_eq!(
+        file_names(&resps[1]),
+        vec![
+            "grpc/testing/test.proto",
+            "grpc/testing/empty.proto",
+            "grpc/testing/messages.proto",
+        ]
+    );
+    assert_eq!(file_names(&resps[2]), vec!["grpc/testing/messages.proto"]);
+}
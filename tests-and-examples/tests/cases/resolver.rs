@@ -0,0 +1,252 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::*;
+use std::thread;
+use std::time::*;
+
+#[derive(Clone)]
+struct GreeterService {
+    name: &'static str,
+}
+
+impl Greeter for GreeterService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut resp = HelloReply::default();
+        resp.set_message(self.name.to_owned());
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+fn start_server(env: Arc<Environment>, name: &'static str) -> (Server, SocketAddr) {
+    let mut server = ServerBuilder::new(env)
+        .register_service(create_greeter(GreeterService { name }))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    (server, format!("127.0.0.1:{}", port).parse().unwrap())
+}
+
+fn say_hello(client: &GreeterClient) -> Result<String> {
+    client
+        .say_hello(&HelloRequest::default())
+        .map(|mut r| r.take_message())
+}
+
+#[test]
+fn test_static_resolver() {
+    let resolver = StaticResolverFactory::new("static");
+    let env = Arc::new(
+        EnvBuilder::new()
+            .register_resolver(resolver.clone())
+            .build(),
+    );
+    let (mut server1, addr1) = start_server(env.clone(), "server1");
+    let (mut server2, addr2) = start_server(env.clone(), "server2");
+
+    resolver.set("svc", Resolution::new(vec![addr1]));
+    let ch = ChannelBuilder::new(env.clone()).connect("static:///svc");
+    let client = GreeterClient::new(ch);
+    assert_eq!(say_hello(&client).unwrap(), "server1");
+
+    // Updates are pushed to existing channels.
+    resolver.set("svc", Resolution::new(vec![addr2]));
+    assert_eq!(say_hello(&client).unwrap(), "server2");
+
+    resolver.set("svc", Resolution::new(vec![]));
+    match say_hello(&client) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        res => panic!("expect unavailable, but get {:?}", res),
+    }
+
+    // Targets that can't be resolved fail all calls.
+    let ch = ChannelBuilder::new(env).connect("static:///unknown");
+    let client = GreeterClient::new(ch);
+    match say_hello(&client) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        res => panic!("expect unavailable, but get {:?}", res),
+    }
+
+    let _ = server1.shutdown().wait();
+    let _ = server2.shutdown().wait();
+}
+
+/// Resolves to an address that can't be connected at first, and to `addr`
+/// once re-resolution is requested.
+struct FallbackResolverFactory {
+    addr: SocketAddr,
+    requests: Arc<AtomicUsize>,
+}
+
+struct FallbackResolver {
+    addr: SocketAddr,
+    requests: Arc<AtomicUsize>,
+    observer: ResolverObserver,
+}
+
+impl ResolverFactory for FallbackResolverFactory {
+    fn scheme(&self) -> &str {
+        "fallback"
+    }
+
+    fn build(&self, endpoint: &str, observer: ResolverObserver) -> Box<dyn Resolver> {
+        assert_eq!(endpoint, "svc");
+        // Reserve a port that nobody listens on.
+        let closed_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        observer.update(Resolution::new(vec![closed_addr]));
+        Box::new(FallbackResolver {
+            addr: self.addr,
+            requests: self.requests.clone(),
+            observer,
+        })
+    }
+}
+
+impl Resolver for FallbackResolver {
+    fn request_reresolution(&self) {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.observer.update(Resolution::new(vec![self.addr]));
+    }
+}
+
+#[test]
+fn test_reresolution() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let server_env = Arc::new(EnvBuilder::new().build());
+    let (mut server, addr) = start_server(server_env, "server");
+    let env = Arc::new(
+        EnvBuilder::new()
+            .register_resolver(FallbackResolverFactory {
+                addr,
+                requests: requests.clone(),
+            })
+            .build(),
+    );
+    let ch = ChannelBuilder::new(env).connect("fallback:///svc");
+
+    let timer = Instant::now();
+    while requests.load(Ordering::SeqCst) == 0 {
+        assert!(timer.elapsed() < Duration::from_secs(10));
+        ch.check_connectivity_state(true);
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    let client = GreeterClient::new(ch);
+    assert_eq!(say_hello(&client).unwrap(), "server");
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_unchanged_resolution() {
+    let resolver = StaticResolverFactory::new("static");
+    let env = Arc::new(
+        EnvBuilder::new()
+            .register_resolver(resolver.clone())
+            .build(),
+    );
+    let (mut server, addr) = start_server(env.clone(), "server");
+
+    resolver.set("svc", Resolution::new(vec![addr]));
+    let ch = ChannelBuilder::new(env).connect("static:///svc");
+    let client = GreeterClient::new(ch.clone());
+    assert_eq!(say_hello(&client).unwrap(), "server");
+    assert_eq!(
+        ch.check_connectivity_state(false),
+        ConnectivityState::GRPC_CHANNEL_READY
+    );
+
+    // A new channel would be idle.
+    resolver.set("svc", Resolution::new(vec![addr]));
+    assert_eq!(
+        ch.check_connectivity_state(false),
+        ConnectivityState::GRPC_CHANNEL_READY
+    );
+
+    let _ = server.shutdown().wait();
+}
+
+const CA: &str = include_str!("../../../proto/data/ca.pem");
+const CERT: &str = include_str!("../../../proto/data/server1.pem");
+const KEY: &str = include_str!("../../../proto/data/server1.key");
+
+/// Replies the authority of the request.
+#[derive(Clone)]
+struct AuthorityService;
+
+impl Greeter for AuthorityService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut resp = HelloReply::default();
+        resp.set_message(String::from_utf8(ctx.host().to_vec()).unwrap());
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+#[test]
+fn test_secure_resolver() {
+    let resolver = StaticResolverFactory::new("static");
+    let env = Arc::new(
+        EnvBuilder::new()
+            .register_resolver(resolver.clone())
+            .build(),
+    );
+    let creds = ServerCredentialsBuilder::new()
+        .add_cert(CERT.into(), KEY.into())
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(AuthorityService))
+        .bind_secure("127.0.0.1", 0, creds)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    let creds = || {
+        ChannelCredentialsBuilder::new()
+            .root_cert(CA.into())
+            .build()
+    };
+
+    // The certificate is checked against the endpoint instead of the address.
+    resolver.set("foo.test.google.fr", Resolution::new(vec![addr]));
+    let ch =
+        ChannelBuilder::new(env.clone()).secure_connect("static:///foo.test.google.fr", creds());
+    let client = GreeterClient::new(ch);
+    assert_eq!(say_hello(&client).unwrap(), "foo.test.google.fr");
+
+    // The endpoint doesn't match the certificate.
+    resolver.set("foo.example.com", Resolution::new(vec![addr]));
+    let ch = ChannelBuilder::new(env.clone()).secure_connect("static:///foo.example.com", creds());
+    let client = GreeterClient::new(ch);
+    match say_hello(&client) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        res => panic!("expect unavailable, but get {:?}", res),
+    }
+
+    // Explicit settings take precedence.
+    let ch = ChannelBuilder::new(env)
+        .default_authority("bar.test.google.fr")
+        .override_ssl_target("bar.test.google.fr")
+        .secure_connect("static:///foo.example.com", creds());
+    let client = GreeterClient::new(ch);
+    assert_eq!(say_hello(&client).unwrap(), "bar.test.google.fr");
+
+    let _ = server.shutdown().wait();
+}
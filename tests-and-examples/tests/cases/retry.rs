@@ -0,0 +1,269 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::*;
+use std::thread;
+use std::time::*;
+
+/// Fails the first `fail_times` calls with `code`.
+#[derive(Clone)]
+struct FlakyService {
+    attempts: Arc<AtomicUsize>,
+    fail_times: usize,
+    code: RpcStatusCode,
+}
+
+impl Greeter for FlakyService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        let f = if attempt <= self.fail_times {
+            sink.fail(RpcStatus::new(self.code, None))
+        } else {
+            let mut resp = HelloReply::default();
+            resp.set_message(format!("attempt {}", attempt));
+            sink.success(resp)
+        };
+        ctx.spawn(f.map_err(|e| panic!("failed to reply: {:?}", e)));
+    }
+}
+
+fn start_server(
+    env: Arc<Environment>,
+    fail_times: usize,
+    code: RpcStatusCode,
+) -> (Server, Arc<AtomicUsize>) {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let service = create_greeter(FlakyService {
+        attempts: attempts.clone(),
+        fail_times,
+        code,
+    });
+    let mut server = ServerBuilder::new(env)
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    (server, attempts)
+}
+
+fn check_status<T: std::fmt::Debug>(res: Result<T>, code: RpcStatusCode) {
+    match res {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, code),
+        res => panic!("expect {:?}, but get {:?}", code, res),
+    }
+}
+
+#[test]
+fn test_retry_policy() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (mut server, attempts) = start_server(env.clone(), 2, RpcStatusCode::UNAVAILABLE);
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    // Calls are not retried by default.
+    check_status(
+        client.say_hello(&HelloRequest::default()),
+        RpcStatusCode::UNAVAILABLE,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let policy = RetryPolicy::new(3).initial_backoff(Duration::from_millis(10));
+    let opt = CallOption::default().retry_policy(policy);
+    let resp = client.say_hello_opt(&HelloRequest::default(), opt).unwrap();
+    assert_eq!(resp.get_message(), "attempt 3");
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_retry_exhausted() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (mut server, attempts) = start_server(env.clone(), 5, RpcStatusCode::UNAVAILABLE);
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env)
+        .default_retry_policy(RetryPolicy::new(3).initial_backoff(Duration::from_millis(10)))
+        .connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    check_status(
+        client.say_hello(&HelloRequest::default()),
+        RpcStatusCode::UNAVAILABLE,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    // Options of a call override the default of the channel.
+    let policy = RetryPolicy::new(2).initial_backoff(Duration::from_millis(10));
+    let opt = CallOption::default().retry_policy(policy);
+    check_status(
+        client.say_hello_opt(&HelloRequest::default(), opt),
+        RpcStatusCode::UNAVAILABLE,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 5);
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_retry_non_retryable() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (mut server, attempts) = start_server(env.clone(), 2, RpcStatusCode::INTERNAL);
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env)
+        .default_retry_policy(RetryPolicy::new(3).initial_backoff(Duration::from_millis(10)))
+        .connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    check_status(
+        client.say_hello(&HelloRequest::default()),
+        RpcStatusCode::INTERNAL,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let policy = RetryPolicy::new(3)
+        .initial_backoff(Duration::from_millis(10))
+        .retryable_status_codes(vec![RpcStatusCode::INTERNAL]);
+    let opt = CallOption::default().retry_policy(policy);
+    let resp = client.say_hello_opt(&HelloRequest::default(), opt).unwrap();
+    assert_eq!(resp.get_message(), "attempt 3");
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_retry_throttling() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (mut server, attempts) = start_server(env.clone(), 10, RpcStatusCode::UNAVAILABLE);
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env)
+        .default_retry_policy(RetryPolicy::new(5).initial_backoff(Duration::from_millis(10)))
+        .retry_throttling(4, 1.0)
+        .connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    // The second failure drops the tokens to half of the maximum.
+    check_status(
+        client.say_hello(&HelloRequest::default()),
+        RpcStatusCode::UNAVAILABLE,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+    // No more retries until enough calls succeed.
+    check_status(
+        client.say_hello(&HelloRequest::default()),
+        RpcStatusCode::UNAVAILABLE,
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    let _ = server.shutdown().wait();
+}
+
+/// Replies to the first call after a long delay, and to the others immediately.
+#[derive(Clone)]
+struct SlowService {
+    attempts: Arc<AtomicUsize>,
+}
+
+impl Greeter for SlowService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, sink: UnarySink<HelloReply>) {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut resp = HelloReply::default();
+        resp.set_message(format!("attempt {}", attempt));
+        if attempt == 1 {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(3));
+                // The call may have been cancelled by the client already.
+                let _ = sink.success(resp).wait();
+            });
+        } else {
+            ctx.spawn(
+                sink.success(resp)
+                    .map_err(|e| panic!("failed to reply: {:?}", e)),
+            );
+        }
+    }
+}
+
+#[test]
+fn test_hedging_policy() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let service = create_greeter(SlowService {
+        attempts: attempts.clone(),
+    });
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let policy = HedgingPolicy::new(3, Duration::from_millis(100));
+    let opt = CallOption::default()
+        .idempotent(true)
+        .hedging_policy(policy);
+    let now = Instant::now();
+    let resp = client.say_hello_opt(&HelloRequest::default(), opt).unwrap();
+    assert_eq!(resp.get_message(), "attempt 2");
+    assert!(now.elapsed() < Duration::from_secs(3));
+
+    let _ = server.shutdown().wait();
+}
+
+/// Sends headers before failing every call with `UNAVAILABLE`.
+#[derive(Clone)]
+struct CommittedService {
+    attempts: Arc<AtomicUsize>,
+}
+
+impl Greeter for CommittedService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, _: HelloRequest, mut sink: UnarySink<HelloReply>) {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut headers = MetadataBuilder::new();
+        headers.add_str("attempt", &attempt.to_string()).unwrap();
+        sink.set_headers(headers.build());
+        let f = sink.fail(RpcStatus::new(RpcStatusCode::UNAVAILABLE, None));
+        ctx.spawn(f.map_err(|e| panic!("failed to reply: {:?}", e)));
+    }
+}
+
+#[test]
+fn test_retry_committed() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let service = create_greeter(CommittedService {
+        attempts: attempts.clone(),
+    });
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env)
+        .default_retry_policy(RetryPolicy::new(3).initial_backoff(Duration::from_millis(10)))
+        .connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    // The call is committed once headers are received, so it's not retried.
+    let receiver = client.say_hello_async(&HelloRequest::default()).unwrap();
+    let meta = receiver.response_metadata();
+    check_status(receiver.wait(), RpcStatusCode::UNAVAILABLE);
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    let headers = meta.headers().unwrap();
+    let attempt: Vec<_> = headers.iter().filter(|(k, _)| *k == "attempt").collect();
+    assert_eq!(attempt, vec![("attempt", &b"1"[..])]);
+
+    let _ = server.shutdown().wait();
+}
@@ -0,0 +1,310 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use grpcio_proto::util;
+use std::error::Error as StdError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::*;
+
+const CA: &str = include_str!("../../../proto/data/ca.pem");
+const CERT: &str = include_str!("../../../proto/data/server1.pem");
+const KEY: &str = include_str!("../../../proto/data/server1.key");
+const SNI_A_CERT: &str = include_str!("../../../proto/data/sni-a.pem");
+const SNI_A_KEY: &str = include_str!("../../../proto/data/sni-a.key");
+const SNI_B_CERT: &str = include_str!("../../../proto/data/sni-b.pem");
+const SNI_B_KEY: &str = include_str!("../../../proto/data/sni-b.key");
+
+#[derive(Clone)]
+struct GreeterService;
+
+impl Greeter for GreeterService {
+    fn say_hello(&mut self, ctx: RpcContext<'_>, req: HelloRequest, sink: UnarySink<HelloReply>) {
+        let mut resp = HelloReply::default();
+        resp.set_message(format!("hello {}", req.get_name()));
+        ctx.spawn(
+            sink.success(resp)
+                .map_err(|e| panic!("failed to reply: {:?}", e)),
+        );
+    }
+}
+
+/// Returns the certificate set by `rotate` once, and then reports either no
+/// change or a failure.
+#[derive(Clone, Default)]
+struct TestFetcher {
+    fetches: Arc<AtomicUsize>,
+    fail: Arc<AtomicBool>,
+    next: Arc<Mutex<Option<(&'static str, &'static str)>>>,
+}
+
+impl TestFetcher {
+    fn rotate(&self, cert: &'static str, key: &'static str) {
+        *self.next.lock().unwrap() = Some((cert, key));
+    }
+}
+
+impl ServerCredentialsFetcher for TestFetcher {
+    fn fetch(&self) -> std::result::Result<Option<ServerCredentialsBuilder>, Box<dyn StdError>> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        if self.fail.load(Ordering::SeqCst) {
+            return Err("certificates are not available".into());
+        }
+        Ok(self
+            .next
+            .lock()
+            .unwrap()
+            .take()
+            .map(|(cert, key)| ServerCredentialsBuilder::new().add_cert(cert.into(), key.into())))
+    }
+}
+
+fn say_hello_with(
+    env: Arc<Environment>,
+    port: u16,
+    creds: ChannelCredentials,
+) -> Result<HelloReply> {
+    let ch = ChannelBuilder::new(env)
+        .override_ssl_target("foo.test.google.fr")
+        .secure_connect(&format!("127.0.0.1:{}", port), creds);
+    let client = GreeterClient::new(ch);
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    client.say_hello(&req)
+}
+
+fn say_hello(env: Arc<Environment>, port: u16) -> Result<HelloReply> {
+    say_hello_with(env, port, util::create_test_channel_credentials())
+}
+
+/// The SNI certificates are self-signed, so a client only trusts the
+/// certificate it's given as root.
+fn say_hello_to(env: Arc<Environment>, port: u16, host: &str, root: &str) -> Result<HelloReply> {
+    let creds = ChannelCredentialsBuilder::new()
+        .root_cert(root.into())
+        .build();
+    let ch = ChannelBuilder::new(env)
+        .override_ssl_target(host)
+        .secure_connect(&format!("127.0.0.1:{}", port), creds);
+    GreeterClient::new(ch).say_hello(&HelloRequest::default())
+}
+
+fn check_unavailable(res: Result<HelloReply>) {
+    match res {
+        Err(Error::RpcFailure(ref s)) if s.status == RpcStatusCode::UNAVAILABLE => {}
+        res => panic!("expect unavailable, but get {:?}", res),
+    }
+}
+
+#[test]
+fn test_credentials_fetcher() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let fetcher = TestFetcher::default();
+    fetcher.rotate(SNI_A_CERT, SNI_A_KEY);
+    let creds = ServerCredentials::with_fetcher(
+        fetcher.clone(),
+        CertificateRequestType::DontRequestClientCertificate,
+    );
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(GreeterService))
+        .bind_secure("127.0.0.1", 0, creds)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+
+    say_hello_to(env.clone(), port, "a.sni.example.com", SNI_A_CERT).unwrap();
+    let fetches = fetcher.fetches.load(Ordering::SeqCst);
+    assert!(fetches >= 2, "{}", fetches);
+
+    // New connections see the new certificate.
+    fetcher.rotate(SNI_B_CERT, SNI_B_KEY);
+    say_hello_to(env.clone(), port, "b.sni.example.com", SNI_B_CERT).unwrap();
+    check_unavailable(say_hello_to(
+        env.clone(),
+        port,
+        "a.sni.example.com",
+        SNI_A_CERT,
+    ));
+    let fetches = fetcher.fetches.load(Ordering::SeqCst);
+
+    // The previous certificates are kept if fetching fails.
+    fetcher.fail.store(true, Ordering::SeqCst);
+    say_hello_to(env, port, "b.sni.example.com", SNI_B_CERT).unwrap();
+    assert!(fetcher.fetches.load(Ordering::SeqCst) > fetches);
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_credentials_fetcher_bind_fail() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let fetcher = TestFetcher::default();
+    fetcher.fail.store(true, Ordering::SeqCst);
+    let creds = ServerCredentials::with_fetcher(
+        fetcher,
+        CertificateRequestType::DontRequestClientCertificate,
+    );
+    let res = ServerBuilder::new(env)
+        .register_service(create_greeter(GreeterService))
+        .bind_secure("127.0.0.1", 0, creds)
+        .build();
+    match res {
+        Err(Error::BindFail(..)) => {}
+        Err(e) => panic!("expect bind failure, but get {:?}", e),
+        Ok(_) => panic!("expect bind failure"),
+    }
+}
+
+fn start_server(env: Arc<Environment>, request_type: CertificateRequestType) -> Server {
+    let creds = ServerCredentialsBuilder::new()
+        .root_cert(CA, false)
+        .add_cert(CERT.into(), KEY.into())
+        .client_certificate_request(request_type)
+        .build();
+    let mut server = ServerBuilder::new(env)
+        .register_service(create_greeter(GreeterService))
+        .bind_secure("127.0.0.1", 0, creds)
+        .build()
+        .unwrap();
+    server.start();
+    server
+}
+
+#[test]
+fn test_client_certificate_request() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let client_creds = |cert: &str, key: &str| {
+        ChannelCredentialsBuilder::new()
+            .root_cert(CA.into())
+            .cert(cert.into(), key.into())
+            .build()
+    };
+
+    // (request type, allow anonymous clients, allow untrusted clients)
+    let cases = [
+        (
+            CertificateRequestType::DontRequestClientCertificate,
+            true,
+            true,
+        ),
+        (
+            CertificateRequestType::RequestClientCertificateButDontVerify,
+            true,
+            true,
+        ),
+        (
+            CertificateRequestType::RequestClientCertificateAndVerify,
+            true,
+            false,
+        ),
+        (
+            CertificateRequestType::RequestAndRequireClientCertificateButDontVerify,
+            false,
+            true,
+        ),
+        (
+            CertificateRequestType::RequestAndRequireClientCertificateAndVerify,
+            false,
+            false,
+        ),
+    ];
+    for &(request_type, allow_anonymous, allow_untrusted) in &cases {
+        let mut server = start_server(env.clone(), request_type);
+        let port = server.bind_addrs()[0].1;
+
+        let resp = say_hello_with(env.clone(), port, client_creds(CERT, KEY)).unwrap();
+        assert_eq!(resp.get_message(), "hello world");
+
+        // The certificate is self-signed, so it's not trusted by the server.
+        let untrusted = client_creds(SNI_A_CERT, SNI_A_KEY);
+        let results = [
+            (say_hello(env.clone(), port), allow_anonymous),
+            (
+                say_hello_with(env.clone(), port, untrusted),
+                allow_untrusted,
+            ),
+        ];
+        for (res, allowed) in results.iter() {
+            match res {
+                Ok(_) if *allowed => {}
+                Err(Error::RpcFailure(ref s))
+                    if !*allowed && s.status == RpcStatusCode::UNAVAILABLE => {}
+                res => panic!("{:?}: unexpected result {:?}", request_type, res),
+            }
+        }
+
+        let _ = server.shutdown().wait();
+    }
+}
+
+#[test]
+fn test_sni_certificate_selection() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let creds = ServerCredentialsBuilder::new()
+        .add_cert(SNI_A_CERT.into(), SNI_A_KEY.into())
+        .add_cert(SNI_B_CERT.into(), SNI_B_KEY.into())
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(GreeterService))
+        .bind_secure("127.0.0.1", 0, creds)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+
+    let cases = [
+        ("a.sni.example.com", SNI_A_CERT, true),
+        ("b.sni.example.com", SNI_B_CERT, true),
+        ("b.sni.example.com", SNI_A_CERT, false),
+    ];
+    for &(host, cert, ok) in &cases {
+        match say_hello_to(env.clone(), port, host, cert) {
+            Ok(_) if ok => {}
+            Err(Error::RpcFailure(ref s)) if !ok && s.status == RpcStatusCode::UNAVAILABLE => {}
+            res => panic!("{}: unexpected result {:?}", host, res),
+        }
+    }
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_sni_default_certificate() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let start = |certs: &[(&str, &str)]| {
+        let creds = certs
+            .iter()
+            .fold(ServerCredentialsBuilder::new(), |b, (cert, key)| {
+                b.add_cert((*cert).into(), (*key).into())
+            })
+            .build();
+        let mut server = ServerBuilder::new(env.clone())
+            .register_service(create_greeter(GreeterService))
+            .bind_secure("127.0.0.1", 0, creds)
+            .build()
+            .unwrap();
+        server.start();
+        server
+    };
+
+    // No server name is indicated for an IP address, and only the test
+    // certificate is valid for 192.168.1.3.
+    let cases = [
+        (vec![(CERT, KEY), (SNI_A_CERT, SNI_A_KEY)], true),
+        (vec![(SNI_A_CERT, SNI_A_KEY), (CERT, KEY)], false),
+    ];
+    for (certs, ok) in &cases {
+        let mut server = start(certs);
+        let port = server.bind_addrs()[0].1;
+        let res = say_hello_to(env.clone(), port, "192.168.1.3", CA);
+        if *ok {
+            res.unwrap();
+        } else {
+            check_unavailable(res);
+        }
+        let _ = server.shutdown().wait();
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::sync::*;
+use std::thread;
+use std::time::*;
+
+/// Replies after the duration given in the name of the request, in milliseconds.
+#[derive(Clone)]
+struct SleepService;
+
+impl Greeter for SleepService {
+    fn say_hello(&mut self, _: RpcContext<'_>, req: HelloRequest, sink: UnarySink<HelloReply>) {
+        let millis = req.get_name().parse().unwrap_or(0);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(millis));
+            let mut resp = HelloReply::default();
+            resp.set_message("hello".to_owned());
+            // The call may have been timed out already.
+            let _ = sink.success(resp).wait();
+        });
+    }
+}
+
+fn check_status<T: std::fmt::Debug>(res: Result<T>, code: RpcStatusCode) {
+    match res {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, code),
+        res => panic!("expect {:?}, but get {:?}", code, res),
+    }
+}
+
+#[test]
+fn test_service_config() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(SleepService))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+
+    let config = ServiceConfigBuilder::new()
+        .load_balancing_policy(LbPolicy::PickFirst)
+        .method_config(
+            MethodConfig::new()
+                .method("helloworld.Greeter", "SayHello")
+                .timeout(Duration::from_millis(200))
+                .max_request_message_bytes(64),
+        )
+        .build()
+        .unwrap();
+    let ch = ChannelBuilder::new(env)
+        .service_config(&config)
+        .connect(&format!("127.0.0.1:{}", port));
+    let client = GreeterClient::new(ch);
+
+    let mut req = HelloRequest::default();
+    req.set_name("0".to_owned());
+    let resp = client.say_hello(&req).unwrap();
+    assert_eq!(resp.get_message(), "hello");
+
+    // The timeout of the method is used.
+    req.set_name("1000".to_owned());
+    check_status(client.say_hello(&req), RpcStatusCode::DEADLINE_EXCEEDED);
+
+    // So is the limit of request size.
+    req.set_name("0".repeat(100));
+    check_status(client.say_hello(&req), RpcStatusCode::RESOURCE_EXHAUSTED);
+
+    let _ = server.shutdown().wait();
+}
+
+#[test]
+fn test_invalid_service_config() {
+    let res = ServiceConfigBuilder::new()
+        .method_config(MethodConfig::new().timeout(Duration::from_secs(1)))
+        .build();
+    match res {
+        Err(Error::InvalidServiceConfig(_)) => {}
+        res => panic!("expect invalid service config, but get {:?}", res),
+    }
+}
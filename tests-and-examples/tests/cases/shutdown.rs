@@ -0,0 +1,113 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::*;
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::*;
+use std::thread;
+use std::time::*;
+
+/// Replies to `fast` after a short delay, and to others only after
+/// `release` is set.
+#[derive(Clone, Default)]
+struct DrainService {
+    received: Arc<AtomicUsize>,
+    release: Arc<AtomicBool>,
+}
+
+impl Greeter for DrainService {
+    fn say_hello(&mut self, _: RpcContext<'_>, req: HelloRequest, sink: UnarySink<HelloReply>) {
+        self.received.fetch_add(1, Ordering::SeqCst);
+        let release = self.release.clone();
+        thread::spawn(move || {
+            if req.get_name() == "fast" {
+                thread::sleep(Duration::from_millis(200));
+            } else {
+                while !release.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            let mut resp = HelloReply::default();
+            resp.set_message(req.get_name().to_owned());
+            // The call may have been cancelled already.
+            let _ = sink.success(resp).wait();
+        });
+    }
+}
+
+fn start_server(env: Arc<Environment>, service: DrainService) -> (Server, GreeterClient) {
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(service))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    (server, GreeterClient::new(ch))
+}
+
+fn request(name: &str) -> HelloRequest {
+    let mut req = HelloRequest::default();
+    req.set_name(name.to_owned());
+    req
+}
+
+fn wait_received(service: &DrainService, count: usize) {
+    let now = Instant::now();
+    while service.received.load(Ordering::SeqCst) < count {
+        assert!(
+            now.elapsed() < Duration::from_secs(5),
+            "calls are not received"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_shutdown_graceful() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = DrainService::default();
+    let (mut server, client) = start_server(env, service.clone());
+
+    let fast = client.say_hello_async(&request("fast")).unwrap();
+    wait_received(&service, 1);
+
+    let f = server.shutdown_graceful(Instant::now() + Duration::from_secs(5));
+    let now = Instant::now();
+    // No call is cancelled as the call finishes before the deadline.
+    assert_eq!(f.wait().unwrap(), 0);
+    assert!(now.elapsed() < Duration::from_secs(5));
+    assert_eq!(fast.wait().unwrap().get_message(), "fast");
+
+    // New calls are rejected.
+    match client.say_hello(&request("fast")) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNAVAILABLE),
+        res => panic!("expect UNAVAILABLE, but get {:?}", res),
+    }
+}
+
+#[test]
+fn test_shutdown_graceful_deadline() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = DrainService::default();
+    let (mut server, client) = start_server(env, service.clone());
+
+    let fast = client.say_hello_async(&request("fast")).unwrap();
+    let stuck = client.say_hello_async(&request("stuck")).unwrap();
+    wait_received(&service, 2);
+
+    let f = server.shutdown_graceful(Instant::now() + Duration::from_secs(1));
+    let handle = thread::spawn(move || f.wait());
+    assert_eq!(fast.wait().unwrap().get_message(), "fast");
+    match stuck.wait() {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::CANCELLED),
+        res => panic!("expect CANCELLED, but get {:?}", res),
+    }
+
+    // Shutdown doesn't wait for the handler to let go of the cancelled call.
+    assert_eq!(handle.join().unwrap().unwrap(), 1);
+    service.release.store(true, Ordering::SeqCst);
+}
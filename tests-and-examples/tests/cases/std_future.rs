@@ -0,0 +1,438 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures03::executor::block_on;
+use futures03::{future, stream, Sink, SinkExt, StreamExt, TryStreamExt};
+use grpcio::*;
+use grpcio_proto::example::helloworld::*;
+use grpcio_proto::example::helloworld_grpc::*;
+use grpcio_sys::grpc_call_error;
+use std::pin::Pin;
+use std::sync::*;
+
+/// `RouteGuide` generated in async mode by the build script.
+mod route_guide_async {
+    include!(concat!(env!("OUT_DIR"), "/route_guide_async/mod.rs"));
+}
+
+use self::route_guide_async::route_guide::*;
+use self::route_guide_async::route_guide_grpc::*;
+
+#[derive(Clone)]
+struct GreeterService;
+
+impl Greeter for GreeterService {
+    fn say_hello(
+        &mut self,
+        ctx: RpcContext<'_>,
+        mut req: HelloRequest,
+        sink: UnarySink<HelloReply>,
+    ) {
+        let mut resp = HelloReply::default();
+        resp.set_message(format!("hello {}", req.take_name()));
+        ctx.spawn_std(async move {
+            sink.success(resp).await.unwrap();
+        });
+    }
+}
+
+fn echo_method(ty: MethodType, name: &'static str) -> Method<HelloRequest, HelloReply> {
+    Method {
+        ty,
+        name,
+        req_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+        resp_mar: Marshaller {
+            ser: pb_ser,
+            de: pb_de,
+        },
+    }
+}
+
+#[test]
+fn test_std_future() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let echo = ServiceBuilder::new()
+        .add_duplex_streaming_handler(
+            &echo_method(MethodType::Duplex, "/helloworld.Greeter/Echo"),
+            |ctx, mut stream: RequestStream<HelloRequest>, mut sink| {
+                ctx.spawn_std(async move {
+                    while let Some(mut req) = stream.try_next().await.unwrap() {
+                        let mut resp = HelloReply::default();
+                        resp.set_message(req.take_name());
+                        sink.send((resp, WriteFlags::default())).await.unwrap();
+                    }
+                    sink.close().await.unwrap();
+                });
+            },
+        )
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_greeter(GreeterService))
+        .register_service(echo)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+
+    let client = GreeterClient::new(ch.clone());
+    let mut req = HelloRequest::default();
+    req.set_name("world".to_owned());
+    let resp = block_on(client.say_hello_async(&req).unwrap()).unwrap();
+    assert_eq!(resp.get_message(), "hello world");
+
+    let client = Client::new(ch);
+    let (mut tx, rx) = client
+        .duplex_streaming(
+            &echo_method(MethodType::Duplex, "/helloworld.Greeter/Echo"),
+            CallOption::default(),
+        )
+        .unwrap();
+    let (done_tx, done_rx) = futures03::channel::oneshot::channel();
+    client.spawn_std(async move {
+        for name in &["a", "b", "c"] {
+            let mut req = HelloRequest::default();
+            req.set_name((*name).to_owned());
+            tx.send((req, WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        done_tx.send(()).unwrap();
+    });
+    let names: Vec<_> = block_on(rx.map(|r| r.unwrap().take_message()).collect());
+    assert_eq!(names, vec!["a", "b", "c"]);
+    block_on(done_rx).unwrap();
+
+    block_on(server.shutdown()).unwrap();
+}
+
+fn hello_reply(message: String) -> HelloReply {
+    let mut resp = HelloReply::default();
+    resp.set_message(message);
+    resp
+}
+
+fn to_status(e: Error) -> RpcStatus {
+    RpcStatus::new(RpcStatusCode::INTERNAL, Some(format!("{:?}", e)))
+}
+
+#[test]
+fn test_async_handlers() {
+    let unary = echo_method(MethodType::Unary, "/helloworld.Greeter/Unary");
+    let cstream = echo_method(MethodType::ClientStreaming, "/helloworld.Greeter/CStream");
+    let sstream = echo_method(MethodType::ServerStreaming, "/helloworld.Greeter/SStream");
+    let duplex = echo_method(MethodType::Duplex, "/helloworld.Greeter/Duplex");
+
+    let env = Arc::new(EnvBuilder::new().build());
+    let service = ServiceBuilder::new()
+        .add_async_unary_handler(&unary, |ctx, mut req: HelloRequest| async move {
+            assert_eq!(ctx.method(), b"/helloworld.Greeter/Unary");
+            if req.get_name().is_empty() {
+                return Err(RpcStatus::new(RpcStatusCode::INVALID_ARGUMENT, None));
+            }
+            Ok(hello_reply(req.take_name()))
+        })
+        .add_async_client_streaming_handler(&cstream, |_, stream| async move {
+            let names: Vec<_> = stream
+                .map_ok(|mut req: HelloRequest| req.take_name())
+                .try_collect()
+                .await
+                .map_err(to_status)?;
+            Ok(hello_reply(names.join(",")))
+        })
+        .add_async_server_streaming_handler(&sstream, |_, req: HelloRequest| async move {
+            let resps: Vec<_> = req
+                .get_name()
+                .split(',')
+                .map(|name| Ok(hello_reply(name.to_owned())))
+                .collect();
+            Ok(stream::iter(resps).boxed())
+        })
+        .add_async_duplex_streaming_handler(&duplex, |_, stream| async move {
+            let resps = stream.map(|res| {
+                res.map(|mut req: HelloRequest| hello_reply(req.take_name()))
+                    .map_err(to_status)
+            });
+            Ok(resps.boxed())
+        })
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = Client::new(ch);
+
+    let mut req = HelloRequest::default();
+    req.set_name("a".to_owned());
+    let resp = client
+        .unary_call(&unary, &req, CallOption::default())
+        .unwrap();
+    assert_eq!(resp.get_message(), "a");
+    match client.unary_call(&unary, &HelloRequest::default(), CallOption::default()) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::INVALID_ARGUMENT),
+        res => panic!("expect failure, but get {:?}", res),
+    }
+
+    let (mut tx, rx) = client
+        .client_streaming(&cstream, CallOption::default())
+        .unwrap();
+    let resp = block_on(async move {
+        for name in &["a", "b"] {
+            let mut req = HelloRequest::default();
+            req.set_name((*name).to_owned());
+            tx.send((req, WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        rx.await.unwrap()
+    });
+    assert_eq!(resp.get_message(), "a,b");
+
+    req.set_name("a,b,c".to_owned());
+    let rx = client
+        .server_streaming(&sstream, &req, CallOption::default())
+        .unwrap();
+    let names: Vec<_> = block_on(rx.map(|r| r.unwrap().take_message()).collect());
+    assert_eq!(names, vec!["a", "b", "c"]);
+
+    let (mut tx, rx) = client
+        .duplex_streaming(&duplex, CallOption::default())
+        .unwrap();
+    let (done_tx, done_rx) = futures03::channel::oneshot::channel();
+    client.spawn_std(async move {
+        for name in &["a", "b"] {
+            let mut req = HelloRequest::default();
+            req.set_name((*name).to_owned());
+            tx.send((req, WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        done_tx.send(()).unwrap();
+    });
+    let names: Vec<_> = block_on(rx.map(|r| r.unwrap().take_message()).collect());
+    assert_eq!(names, vec!["a", "b"]);
+    block_on(done_rx).unwrap();
+
+    block_on(server.shutdown()).unwrap();
+}
+
+#[test]
+fn test_async_fallback_handler() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .async_fallback_handler(|ctx, stream| async move {
+            if ctx.method() != b"/echo.Echo/Echo" {
+                return Err(RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None));
+            }
+            Ok(stream.map_err(to_status).boxed())
+        })
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = Client::new(ch);
+
+    let (mut tx, rx) = client
+        .duplex_streaming_raw("/echo.Echo/Echo", CallOption::default())
+        .unwrap();
+    let (done_tx, done_rx) = futures03::channel::oneshot::channel();
+    client.spawn_std(async move {
+        for msg in &[b"a", b"b"] {
+            tx.send((msg.to_vec(), WriteFlags::default()))
+                .await
+                .unwrap();
+        }
+        tx.close().await.unwrap();
+        done_tx.send(()).unwrap();
+    });
+    let msgs: Vec<_> = block_on(rx.map(Result::unwrap).collect());
+    assert_eq!(msgs, vec![b"a".to_vec(), b"b".to_vec()]);
+    block_on(done_rx).unwrap();
+
+    match client.unary_call_raw("/echo.Echo/Unknown", vec![], CallOption::default()) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::UNIMPLEMENTED),
+        res => panic!("expect failure, but get {:?}", res),
+    }
+
+    block_on(server.shutdown()).unwrap();
+}
+
+#[test]
+fn test_start_send_before_ready() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let (resume_tx, resume_rx) = futures03::channel::oneshot::channel::<()>();
+    let resume_rx = Arc::new(Mutex::new(Some(resume_rx)));
+    let method = echo_method(MethodType::Duplex, "/helloworld.Greeter/Echo");
+    let service = ServiceBuilder::new()
+        .add_duplex_streaming_handler(&method, move |ctx, stream, sink| {
+            let resume = resume_rx.lock().unwrap().take().unwrap();
+            ctx.spawn_std(async move {
+                // Nothing is read until the client finishes checking, so large
+                // messages can't be flushed.
+                let _ = resume.await;
+                drop((stream, sink));
+            });
+        })
+        .build();
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(service)
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = Client::new(ch);
+
+    let (mut tx, _rx) = client
+        .duplex_streaming(&method, CallOption::default())
+        .unwrap();
+    let mut req = HelloRequest::default();
+    req.set_name("a".repeat(1 << 21));
+    block_on(future::poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx))).unwrap();
+    Pin::new(&mut tx)
+        .start_send((req.clone(), WriteFlags::default()))
+        .unwrap();
+    match Pin::new(&mut tx).start_send((req, WriteFlags::default())) {
+        Err(Error::CallFailure(grpc_call_error::GRPC_CALL_ERROR_TOO_MANY_OPERATIONS)) => {}
+        res => panic!("expect too many operations, but get {:?}", res),
+    }
+
+    resume_tx.send(()).unwrap();
+    drop(tx);
+    block_on(server.shutdown()).unwrap();
+}
+
+fn point(latitude: i32, longitude: i32) -> Point {
+    let mut p = Point::default();
+    p.set_latitude(latitude);
+    p.set_longitude(longitude);
+    p
+}
+
+struct RouteGuideService {
+    features: Vec<Feature>,
+}
+
+#[async_trait]
+impl RouteGuide for RouteGuideService {
+    async fn get_feature(
+        &self,
+        _: AsyncRpcContext,
+        p: Point,
+    ) -> std::result::Result<Feature, RpcStatus> {
+        self.features
+            .iter()
+            .find(|f| *f.get_location() == p)
+            .cloned()
+            .ok_or_else(|| RpcStatus::new(RpcStatusCode::NOT_FOUND, None))
+    }
+
+    async fn list_features(
+        &self,
+        _: AsyncRpcContext,
+        rect: Rectangle,
+    ) -> std::result::Result<ResponseStream<Feature>, RpcStatus> {
+        let (lo, hi) = (rect.get_lo(), rect.get_hi());
+        let features: Vec<_> = self
+            .features
+            .iter()
+            .filter(|f| {
+                let p = f.get_location();
+                p.get_latitude() >= lo.get_latitude() && p.get_latitude() <= hi.get_latitude()
+            })
+            .cloned()
+            .map(Ok)
+            .collect();
+        Ok(stream::iter(features).boxed())
+    }
+
+    async fn record_route(
+        &self,
+        _: AsyncRpcContext,
+        points: RequestStream<Point>,
+    ) -> std::result::Result<RouteSummary, RpcStatus> {
+        let count = points
+            .try_fold(0, |count, _| async move { Ok(count + 1) })
+            .await
+            .map_err(to_status)?;
+        let mut summary = RouteSummary::default();
+        summary.set_point_count(count);
+        Ok(summary)
+    }
+
+    async fn route_chat(
+        &self,
+        _: AsyncRpcContext,
+        notes: RequestStream<RouteNote>,
+    ) -> std::result::Result<ResponseStream<RouteNote>, RpcStatus> {
+        Ok(notes.map(|res| res.map_err(to_status)).boxed())
+    }
+}
+
+#[test]
+fn test_generated_async_service() {
+    let features = (0..3)
+        .map(|i| {
+            let mut f = Feature::default();
+            f.set_name(format!("feature {}", i));
+            f.set_location(point(i, i));
+            f
+        })
+        .collect();
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_route_guide(RouteGuideService { features }))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = RouteGuideClient::new(ch);
+
+    let f = block_on(client.get_feature_async(&point(1, 1)).unwrap()).unwrap();
+    assert_eq!(f.get_name(), "feature 1");
+    match client.get_feature(&point(1, 2)) {
+        Err(Error::RpcFailure(s)) => assert_eq!(s.status, RpcStatusCode::NOT_FOUND),
+        res => panic!("expect not found, but get {:?}", res),
+    }
+
+    let mut rect = Rectangle::default();
+    rect.set_lo(point(1, 0));
+    rect.set_hi(point(2, 0));
+    let rx = client.list_features(&rect).unwrap();
+    let names: Vec<_> = block_on(rx.map(|r| r.unwrap().take_name()).collect());
+    assert_eq!(names, vec!["feature 1", "feature 2"]);
+
+    let (mut tx, rx) = client.record_route().unwrap();
+    let summary = block_on(async move {
+        for i in 0..4 {
+            tx.send((point(i, i), WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        rx.await.unwrap()
+    });
+    assert_eq!(summary.get_point_count(), 4);
+
+    let (mut tx, rx) = client.route_chat().unwrap();
+    let send_notes = async move {
+        for msg in &["a", "b"] {
+            let mut note = RouteNote::default();
+            note.set_message((*msg).to_owned());
+            tx.send((note, WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+    };
+    let recv_notes = rx.map(|r| r.unwrap().take_message()).collect::<Vec<_>>();
+    let (_, msgs) = block_on(future::join(send_notes, recv_notes));
+    assert_eq!(msgs, vec!["a", "b"]);
+
+    block_on(server.shutdown()).unwrap();
+}
@@ -0,0 +1,123 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures03::executor::block_on;
+use futures03::{stream, SinkExt, StreamExt, TryStreamExt};
+use grpcio::*;
+use std::sync::*;
+
+/// `RouteGuide` generated in async mode for prost by the build script.
+#[allow(dead_code)]
+mod routeguide {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/route_guide_async_prost/routeguide.rs"
+    ));
+}
+
+use self::routeguide::*;
+
+fn to_status(e: Error) -> RpcStatus {
+    RpcStatus::new(RpcStatusCode::INTERNAL, Some(format!("{:?}", e)))
+}
+
+fn point(latitude: i32, longitude: i32) -> Point {
+    Point {
+        latitude,
+        longitude,
+    }
+}
+
+/// Has a feature at every point.
+struct RouteGuideService;
+
+#[async_trait]
+impl RouteGuide for RouteGuideService {
+    async fn get_feature(
+        &self,
+        _: AsyncRpcContext,
+        p: Point,
+    ) -> std::result::Result<Feature, RpcStatus> {
+        Ok(Feature {
+            name: format!("{},{}", p.latitude, p.longitude),
+            location: Some(p),
+        })
+    }
+
+    async fn list_features(
+        &self,
+        _: AsyncRpcContext,
+        rect: Rectangle,
+    ) -> std::result::Result<ResponseStream<Feature>, RpcStatus> {
+        let features: Vec<_> = vec![rect.lo, rect.hi]
+            .into_iter()
+            .map(|p| {
+                Ok(Feature {
+                    name: String::new(),
+                    location: p,
+                })
+            })
+            .collect();
+        Ok(stream::iter(features).boxed())
+    }
+
+    async fn record_route(
+        &self,
+        _: AsyncRpcContext,
+        points: RequestStream<Point>,
+    ) -> std::result::Result<RouteSummary, RpcStatus> {
+        let point_count = points
+            .try_fold(0, |count, _| async move { Ok(count + 1) })
+            .await
+            .map_err(to_status)?;
+        Ok(RouteSummary {
+            point_count,
+            ..Default::default()
+        })
+    }
+
+    async fn route_chat(
+        &self,
+        _: AsyncRpcContext,
+        notes: RequestStream<RouteNote>,
+    ) -> std::result::Result<ResponseStream<RouteNote>, RpcStatus> {
+        Ok(notes.map(|res| res.map_err(to_status)).boxed())
+    }
+}
+
+#[test]
+fn test_generated_prost_async_service() {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(env.clone())
+        .register_service(create_route_guide(RouteGuideService))
+        .bind("127.0.0.1", 0)
+        .build()
+        .unwrap();
+    server.start();
+    let port = server.bind_addrs()[0].1;
+    let ch = ChannelBuilder::new(env).connect(&format!("127.0.0.1:{}", port));
+    let client = RouteGuideClient::new(ch);
+
+    let f = client.get_feature(&point(1, 2)).unwrap();
+    assert_eq!(f.name, "1,2");
+    assert_eq!(f.location, Some(point(1, 2)));
+
+    let rect = Rectangle {
+        lo: Some(point(1, 0)),
+        hi: Some(point(2, 0)),
+    };
+    let rx = client.list_features(&rect).unwrap();
+    let locations: Vec<_> = block_on(rx.map(|r| r.unwrap().location).collect());
+    assert_eq!(locations, vec![Some(point(1, 0)), Some(point(2, 0))]);
+
+    let (mut tx, rx) = client.record_route().unwrap();
+    let summary = block_on(async move {
+        for i in 0..4 {
+            tx.send((point(i, i), WriteFlags::default())).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        rx.await.unwrap()
+    });
+    assert_eq!(summary.point_count, 4);
+
+    let _ = block_on(server.shutdown());
+}